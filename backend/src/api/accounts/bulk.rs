@@ -0,0 +1,670 @@
+use actix_web::{get, http::header::ContentDisposition, put, web, web::Data, HttpResponse};
+use entities::{
+    account::{
+        database_helper::AccountListQuery,
+        export::{ExportAccount, ExportOptions},
+        import::{import_accounts, ImportAccount, ImportFormat, ImportMode, ImportOptions},
+        Column as AccountColumn,
+    },
+    emails,
+    emails::{Column as EmailColumn, EmailType},
+    AccountEntity, AccountModel, EmailActiveModel, EmailEntity, GroupEntity, GroupModel,
+};
+use sea_orm::{
+    sea_query::IntoCondition, ActiveModelTrait, ActiveValue, ColumnTrait, EntityTrait,
+    IntoActiveModel, QueryFilter, QueryOrder, TryIntoModel,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error, warn};
+use utils::{database::EmailAddress, quota_size::QuotaSize};
+
+use crate::{
+    auth::{
+        backup_email::BackupEmailVerifications, password_reset::PasswordResetManager,
+        permissions::Permissions, Authentication,
+    },
+    email_service::EmailAccess,
+    headers::Origin,
+    notifications::{account_address, notify_account, AccountDeactivatedEmail, AliasAddedEmail},
+    quota::check_quota,
+    DatabaseConnection, Error, Result, SharedConfig,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct Import {
+    #[serde(default)]
+    pub format: ImportFormat,
+    #[serde(default)]
+    pub mode: ImportMode,
+    /// Sends a password reset email to every imported account with a backup email.
+    ///
+    /// The email is held until the backup email is confirmed
+    #[serde(default)]
+    pub send_password_reset_emails: bool,
+}
+/// Creates accounts from a CSV or JSON file sent as the body.
+///
+/// Responds with the accounts that were created and the errors for each row that was not.
+/// Imported backup emails are unverified and are sent a confirmation link
+#[put("/import")]
+pub async fn import(
+    auth: Authentication,
+    query: web::Query<Import>,
+    body: web::Bytes,
+    database: DatabaseConnection,
+    settings: Data<SharedConfig>,
+    verifications: Data<BackupEmailVerifications>,
+    origin: Origin,
+) -> Result<HttpResponse> {
+    if !auth.can_manage_users() {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    let rows = ImportAccount::parse(query.format, &body).map_err(|error| {
+        warn!("Unable to parse import: {}", error);
+        Error::BadRequest("Unable to parse the import file")
+    })?;
+    let options = ImportOptions {
+        mode: query.mode,
+        default_group: settings.default_group(),
+        password_type: settings.password_hash(),
+        domain_allocations: settings.domain_allocations(),
+    };
+    let report = import_accounts(database.as_ref(), rows, options).await?;
+
+    for account in &report.imported {
+        if let Some(email) = account.backup_email.clone() {
+            verifications.request(
+                account.username.clone(),
+                account.id,
+                email,
+                origin.clone(),
+                query.send_password_reset_emails,
+            );
+        }
+    }
+    Ok(HttpResponse::Ok().json(report))
+}
+
+/// Downloads the accounts with their emails and groups as CSV, JSON or LDIF.
+///
+/// Including the password hashes requires the `manage_system` permission
+#[get("/export")]
+pub async fn export(
+    auth: Authentication,
+    query: web::Query<ExportOptions>,
+    database: DatabaseConnection,
+) -> Result<HttpResponse> {
+    if !auth.can_manage_users() || (query.include_password_hashes && !auth.can_manage_system()) {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    let accounts = ExportAccount::get_all(database.as_ref(), &query).await?;
+    let body = ExportAccount::write(&accounts, query.format, &query.base_dn).map_err(|error| {
+        error!("Unable to write export: {}", error);
+        Error::IoError(std::io::Error::new(std::io::ErrorKind::Other, error))
+    })?;
+    Ok(HttpResponse::Ok()
+        .content_type(query.format.content_type())
+        .insert_header(ContentDisposition::attachment(format!(
+            "accounts.{}",
+            query.format.extension()
+        )))
+        .body(body))
+}
+
+/// The accounts a bulk action is applied to
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkAccounts {
+    Ids(Vec<i64>),
+    /// Every account matching the filters. Paging and sorting are ignored
+    Filter(AccountListQuery),
+}
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BulkAction {
+    SetActive {
+        active: bool,
+    },
+    MoveGroup {
+        group: i64,
+    },
+    /// Bytes or a size such as `5GiB`
+    SetQuota {
+        quota: QuotaSize,
+    },
+    /// Requires a password change. Optionally sends a password reset email to the backup email
+    ForcePasswordChange {
+        #[serde(default)]
+        send_email: bool,
+    },
+    /// Adds `{local part of the primary email}@{domain}` as an alias.
+    ///
+    /// The username is used as the local part if the account has no primary email
+    AddDomainAlias {
+        domain: String,
+    },
+}
+impl BulkAction {
+    /// Actions that would lock the admin out of the panel when applied to their own account
+    fn locks_out(&self) -> bool {
+        matches!(
+            self,
+            BulkAction::SetActive { active: false } | BulkAction::MoveGroup { .. }
+        )
+    }
+}
+#[derive(Debug, Deserialize)]
+pub struct BulkRequest {
+    pub accounts: BulkAccounts,
+    pub action: BulkAction,
+    /// Confirms that a filter without any conditions is meant for every account
+    #[serde(default)]
+    pub all: bool,
+}
+#[derive(Debug, Serialize)]
+pub struct BulkResult {
+    pub id: i64,
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<Error>,
+}
+impl BulkResult {
+    fn new(id: i64, result: Result<()>) -> Self {
+        match result {
+            Ok(()) => Self {
+                id,
+                success: true,
+                error: None,
+            },
+            Err(error) => Self {
+                id,
+                success: false,
+                error: Some(error),
+            },
+        }
+    }
+}
+
+/// Applies one action to many accounts.
+///
+/// Every account is updated on its own. The response has a result for each account.
+/// A filter without conditions is refused unless `all` is set.
+/// Deactivating or moving the account of the admin doing it is refused for that account
+#[put("/bulk")]
+pub async fn bulk_action(
+    auth: Authentication,
+    data: web::Json<BulkRequest>,
+    database: DatabaseConnection,
+    password_reset: Data<PasswordResetManager>,
+    settings: Data<SharedConfig>,
+    email: Data<EmailAccess>,
+    origin: Origin,
+) -> Result<HttpResponse> {
+    if !auth.can_manage_users() {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    let results = apply_bulk(
+        data.into_inner(),
+        auth.user_id(),
+        database.as_ref(),
+        &password_reset,
+        &settings,
+        &email,
+        &origin,
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(results))
+}
+
+async fn apply_bulk(
+    request: BulkRequest,
+    caller: i64,
+    database: &sea_orm::DatabaseConnection,
+    password_reset: &PasswordResetManager,
+    settings: &SharedConfig,
+    email: &EmailAccess,
+    origin: &Origin,
+) -> Result<Vec<BulkResult>> {
+    let BulkRequest {
+        accounts,
+        action,
+        all,
+    } = request;
+    let new_group = if let BulkAction::MoveGroup { group } = &action {
+        let group = GroupEntity::find_by_id(*group)
+            .one(database)
+            .await?
+            .ok_or(Error::BadRequest("Group does not exist"))?;
+        Some(group)
+    } else {
+        None
+    };
+
+    let (ids, filter) = match accounts {
+        BulkAccounts::Ids(ids) => (ids.clone(), AccountColumn::Id.is_in(ids).into_condition()),
+        BulkAccounts::Filter(query) if !query.has_filters() && !all => {
+            return Err(Error::BadRequest(
+                "The filter matches every account. Set `all` to apply the action to all of them",
+            ));
+        }
+        BulkAccounts::Filter(query) => (Vec::new(), query.condition()),
+    };
+    let found = AccountEntity::find()
+        .filter(filter)
+        .order_by_asc(AccountColumn::Id)
+        .all(database)
+        .await?;
+
+    let mut results = Vec::with_capacity(found.len());
+    for id in ids {
+        if !found.iter().any(|account| account.id == id) {
+            results.push(BulkResult::new(id, Err(Error::NotFound)));
+        }
+    }
+    for account in found {
+        let id = account.id;
+        if id == caller && action.locks_out() {
+            results.push(BulkResult::new(
+                id,
+                Err(Error::BadRequest(
+                    "Can not deactivate or move your own account",
+                )),
+            ));
+            continue;
+        }
+        let result = apply_action(
+            &action,
+            account,
+            database,
+            password_reset,
+            email,
+            settings,
+            new_group.as_ref(),
+            origin,
+        )
+        .await;
+        results.push(BulkResult::new(id, result));
+    }
+    Ok(results)
+}
+
+async fn apply_action(
+    action: &BulkAction,
+    account: AccountModel,
+    database: &sea_orm::DatabaseConnection,
+    password_reset: &PasswordResetManager,
+    email: &EmailAccess,
+    settings: &SharedConfig,
+    new_group: Option<&GroupModel>,
+    origin: &Origin,
+) -> Result<()> {
+    match action {
+        BulkAction::SetActive { active } => {
+            let was_active = account.active;
+            let mut account = account.into_active_model();
+            account.active = ActiveValue::Set(*active);
+            // TODO: Run post active hook
+            let account: AccountModel = account.save(database).await?.try_into_model()?;
+            if was_active && !*active {
+                notify_account(
+                    database,
+                    email,
+                    &account,
+                    AccountDeactivatedEmail {
+                        name: account.name.clone(),
+                        username: account.username.clone(),
+                    },
+                )
+                .await;
+            }
+        }
+        BulkAction::MoveGroup { group } => {
+            if let Some(new_group) = new_group {
+                new_group.check_quota(account.quota)?;
+            }
+            let mut account = account.into_active_model();
+            account.group_id = ActiveValue::Set(*group);
+            account.save(database).await?;
+        }
+        BulkAction::SetQuota { quota } => {
+            let group = GroupEntity::find_by_id(account.group_id)
+                .one(database)
+                .await?
+                .ok_or(Error::NotFound)?;
+            let primary_email =
+                emails::database_helper::get_primary_address(database, account.id).await?;
+            check_quota(
+                database,
+                settings,
+                &group,
+                Some(account.id),
+                primary_email.as_ref().map(|email| &email.email_address),
+                quota.bytes(),
+            )
+            .await?;
+            let mut account = account.into_active_model();
+            account.quota = ActiveValue::Set(quota.bytes());
+            account.save(database).await?;
+        }
+        BulkAction::ForcePasswordChange { send_email } => {
+            let send_to = if *send_email {
+                Some(
+                    account_address(database, &account)
+                        .await?
+                        .ok_or(Error::BadRequest("Account has no address to email"))?,
+                )
+            } else {
+                None
+            };
+            let (username, id) = (account.username.clone(), account.id);
+            let mut account = account.into_active_model();
+            account.require_password_change = ActiveValue::Set(true);
+            account.save(database).await?;
+            if let Some(send_to) = send_to {
+                debug!("Sending password reset email to {}", send_to);
+                password_reset.request(username, id, send_to, origin.clone(), true);
+            }
+        }
+        BulkAction::AddDomainAlias { domain } => {
+            let local_part = emails::database_helper::get_primary_address(database, account.id)
+                .await?
+                .and_then(|primary| {
+                    primary
+                        .email_address
+                        .split_once('@')
+                        .map(|(local_part, _)| local_part.to_owned())
+                })
+                .unwrap_or_else(|| account.username.clone());
+            let address = EmailAddress::new(format!("{local_part}@{domain}"))
+                .map_err(|_| Error::BadRequest("Invalid email address"))?;
+            if let Some(existing) = EmailEntity::find()
+                .filter(EmailColumn::EmailAddress.eq(address.clone()))
+                .one(database)
+                .await?
+            {
+                return if existing.account == account.id {
+                    Ok(())
+                } else {
+                    Err(Error::Conflict("Email address belongs to another account"))
+                };
+            }
+            EmailActiveModel {
+                id: ActiveValue::NotSet,
+                account: ActiveValue::Set(account.id),
+                email_address: ActiveValue::Set(address.clone()),
+                email_type: ActiveValue::Set(EmailType::Alias),
+                created: entities::now(),
+            }
+            .insert(database)
+            .await?;
+            notify_account(
+                database,
+                email,
+                &account,
+                AliasAddedEmail {
+                    name: account.name.clone(),
+                    username: account.username.clone(),
+                    alias: address,
+                },
+            )
+            .await;
+        }
+    }
+    Ok(())
+}
+#[cfg(test)]
+mod tests {
+    use chrono::Local;
+    use entities::{
+        account::{
+            database_helper::AccountListQuery,
+            import::{import_accounts, ImportAccount, ImportFormat, ImportMode, ImportOptions},
+        },
+        emails::EmailType,
+        groups::GroupPermissions,
+        AccountEntity, AccountModel, EmailActiveModel,
+    };
+    use sea_orm::{
+        ActiveModelTrait, ActiveValue, DatabaseConnection, EntityTrait, IntoActiveModel,
+    };
+    use utils::database::password::PasswordType;
+
+    use super::{apply_bulk, BulkAccounts, BulkAction, BulkRequest, BulkResult};
+    use crate::{
+        auth::password_reset::PasswordResetManager,
+        test_support::{
+            add_account, add_group, address, database, origin, shared_config, TestEmail,
+        },
+        Error,
+    };
+
+    async fn run(
+        database: &DatabaseConnection,
+        email: &TestEmail,
+        caller: i64,
+        request: BulkRequest,
+    ) -> crate::Result<Vec<BulkResult>> {
+        let password_reset = PasswordResetManager::new(email.access.clone());
+        apply_bulk(
+            request,
+            caller,
+            database,
+            &password_reset,
+            &shared_config(),
+            &email.access,
+            &origin(),
+        )
+        .await
+    }
+    async fn reload(database: &DatabaseConnection, account: &AccountModel) -> AccountModel {
+        AccountEntity::find_by_id(account.id)
+            .one(database)
+            .await
+            .unwrap()
+            .unwrap()
+    }
+    #[actix_rt::test]
+    pub async fn test_empty_filter_requires_all() {
+        let database = database().await;
+        let group = add_group(&database, "user", GroupPermissions::default()).await;
+        let alice = add_account(&database, "alice", group.id).await;
+        let email = TestEmail::start().await;
+        let request = |all| BulkRequest {
+            accounts: BulkAccounts::Filter(AccountListQuery::default()),
+            action: BulkAction::ForcePasswordChange { send_email: false },
+            all,
+        };
+
+        let refused = run(&database, &email, 0, request(false)).await;
+        assert!(matches!(refused, Err(Error::BadRequest(_))));
+        assert!(!reload(&database, &alice).await.require_password_change);
+
+        let results = run(&database, &email, 0, request(true)).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(results[0].success);
+        assert!(reload(&database, &alice).await.require_password_change);
+    }
+    #[actix_rt::test]
+    pub async fn test_own_account_is_not_deactivated() {
+        let database = database().await;
+        let group = add_group(&database, "admin", GroupPermissions::new_admin()).await;
+        let admin = add_account(&database, "admin", group.id).await;
+        let bob = add_account(&database, "bob", group.id).await;
+        let email = TestEmail::start().await;
+
+        let request = BulkRequest {
+            accounts: BulkAccounts::Ids(vec![admin.id, bob.id, 404]),
+            action: BulkAction::SetActive { active: false },
+            all: false,
+        };
+        let results = run(&database, &email, admin.id, request).await.unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].id, 404);
+        assert!(matches!(results[0].error, Some(Error::NotFound)));
+        assert_eq!(results[1].id, admin.id);
+        assert!(matches!(results[1].error, Some(Error::BadRequest(_))));
+        assert_eq!(results[2].id, bob.id);
+        assert!(results[2].success);
+
+        assert!(reload(&database, &admin).await.active);
+        assert!(!reload(&database, &bob).await.active);
+    }
+    #[actix_rt::test]
+    pub async fn test_force_password_change_partial_failure() {
+        let database = database().await;
+        let group = add_group(&database, "user", GroupPermissions::default()).await;
+        let alice = add_account(&database, "alice", group.id).await;
+        let mut verified = alice.clone().into_active_model();
+        verified.backup_email = ActiveValue::Set(Some(address("alice@backup.example.com")));
+        verified.backup_email_verified = ActiveValue::Set(Some(Local::now().into()));
+        verified.update(&database).await.unwrap();
+        // No verified backup email or primary email to send the reset to
+        let bob = add_account(&database, "bob", group.id).await;
+        let email = TestEmail::start().await;
+
+        let request = BulkRequest {
+            accounts: BulkAccounts::Filter(AccountListQuery {
+                group: Some(group.id),
+                ..Default::default()
+            }),
+            action: BulkAction::ForcePasswordChange { send_email: true },
+            all: false,
+        };
+        let results = run(&database, &email, 0, request).await.unwrap();
+        assert_eq!(results.len(), 2);
+        assert!(results[0].success);
+        assert!(matches!(results[1].error, Some(Error::BadRequest(_))));
+
+        assert!(reload(&database, &alice).await.require_password_change);
+        assert!(!reload(&database, &bob).await.require_password_change);
+        let sent = email.take(1).await;
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].to(), vec!["alice@backup.example.com".to_owned()]);
+    }
+    #[actix_rt::test]
+    pub async fn test_import_validation() {
+        let database = database().await;
+        let group = add_group(&database, "user", GroupPermissions::default()).await;
+        let alice = add_account(&database, "alice", group.id).await;
+        let mut with_backup = alice.into_active_model();
+        with_backup.backup_email = ActiveValue::Set(Some(address("alice@backup.example.com")));
+        let alice = with_backup.update(&database).await.unwrap();
+        EmailActiveModel {
+            id: ActiveValue::NotSet,
+            account: ActiveValue::Set(alice.id),
+            email_address: ActiveValue::Set(address("alice@example.com")),
+            email_type: ActiveValue::Set(EmailType::Primary),
+            created: entities::now(),
+        }
+        .insert(&database)
+        .await
+        .unwrap();
+        let csv = "name,username,group,primary_email,aliases,backup_email\n\
+                   Alice,alice,,new@example.com,,\n\
+                   Bob,bob,,alice@example.com,,\n\
+                   Carol,carol,,carol@example.com,c@example.com;c@example.com,\n\
+                   Dave,dave,,,,alice@backup.example.com\n\
+                   Erin,erin,404,,,\n\
+                   Frank,frank,,frank@example.com,,\n\
+                   Frank,frank,,,,\n";
+        let rows = ImportAccount::parse(ImportFormat::Csv, csv.as_bytes()).unwrap();
+        let options = ImportOptions {
+            mode: ImportMode::Transaction,
+            default_group: group.id,
+            password_type: PasswordType::Argon2,
+            domain_allocations: Default::default(),
+        };
+        let report = import_accounts(&database, rows, options).await.unwrap();
+
+        assert!(report.imported.is_empty());
+        let errors: Vec<_> = report
+            .errors
+            .iter()
+            .map(|row| (row.row, row.errors.join(", ")))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                (1, "Username `alice` already exists".to_owned()),
+                (2, "`alice@example.com` is already in use".to_owned()),
+                (3, "`c@example.com` appears more than once".to_owned()),
+                (
+                    4,
+                    "Backup email `alice@backup.example.com` is used by another account".to_owned()
+                ),
+                (5, "Group 404 does not exist".to_owned()),
+                (7, "Username `frank` appears more than once".to_owned()),
+            ]
+        );
+        // A valid row is not imported when another row fails in a transaction
+        let accounts = AccountEntity::find().all(&database).await.unwrap();
+        assert_eq!(accounts.len(), 1);
+    }
+    #[actix_rt::test]
+    pub async fn test_import_checks_every_row_against_the_database() {
+        let database = database().await;
+        let group = add_group(&database, "user", GroupPermissions::default()).await;
+        add_account(&database, "taken", group.id).await;
+        // More rows than fit in one lookup
+        let mut csv = "name,username,password_hash\n".to_owned();
+        for index in 0..2500 {
+            csv.push_str(&format!("User {index},user{index},{{PLAIN}}password\n"));
+        }
+        csv.push_str("Taken,taken,{PLAIN}password\n");
+        let rows = ImportAccount::parse(ImportFormat::Csv, csv.as_bytes()).unwrap();
+        let options = ImportOptions {
+            mode: ImportMode::Transaction,
+            default_group: group.id,
+            password_type: PasswordType::Argon2,
+            domain_allocations: Default::default(),
+        };
+        let report = import_accounts(&database, rows, options).await.unwrap();
+
+        assert!(report.imported.is_empty());
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.errors[0].row, 2501);
+        assert_eq!(report.errors[0].username, "taken");
+    }
+    #[actix_rt::test]
+    pub async fn test_import_checks_quotas() {
+        let database = database().await;
+        let group = add_group(&database, "user", GroupPermissions::default()).await;
+        let mut limited = group.into_active_model();
+        limited.default_quota = ActiveValue::Set(Some(1000));
+        limited.max_quota = ActiveValue::Set(Some(4000));
+        let group = limited.update(&database).await.unwrap();
+        let csv = "name,username,quota,primary_email\n\
+                   Alice,alice,,alice@example.com\n\
+                   Bob,bob,5000,bob@other.com\n\
+                   Carol,carol,3000,carol@example.com\n\
+                   Dave,dave,2000,dave@example.com\n";
+        let rows = ImportAccount::parse(ImportFormat::Csv, csv.as_bytes()).unwrap();
+        let options = ImportOptions {
+            mode: ImportMode::BestEffort,
+            default_group: group.id,
+            password_type: PasswordType::Argon2,
+            domain_allocations: [("example.com".to_owned(), 5000)].into_iter().collect(),
+        };
+        let report = import_accounts(&database, rows, options).await.unwrap();
+
+        let imported: Vec<_> = report
+            .imported
+            .iter()
+            .map(|account| account.username.as_str())
+            .collect();
+        assert_eq!(imported, vec!["alice", "carol"]);
+        let failed: Vec<_> = report
+            .errors
+            .iter()
+            .map(|row| row.username.as_str())
+            .collect();
+        // Bob is above the group maximum. Dave does not fit in the allocation next to Alice and Carol
+        assert_eq!(failed, vec!["bob", "dave"], "{:?}", report.errors);
+        let alice = AccountEntity::find_by_id(report.imported[0].id)
+            .one(&database)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(alice.quota, 1000);
+    }
+}
@@ -0,0 +1,79 @@
+use actix_web::{delete, get, put, web, web::Data, HttpResponse};
+use entities::{
+    forwarding::database_helper::{
+        get_forwarding, remove_forwarding, set_forwarding, NewForwarding,
+    },
+    AccountEntity,
+};
+use sea_orm::{EntityTrait, TransactionTrait};
+
+use crate::{
+    auth::{permissions::Permissions, Authentication},
+    sieve::SieveScripts,
+    DatabaseConnection, Error, Result,
+};
+
+#[get("/forwarding/{user}")]
+pub async fn get_account_forwarding(
+    user: web::Path<i64>,
+    auth: Authentication,
+    connection: DatabaseConnection,
+) -> Result<HttpResponse> {
+    if !auth.can_manage_users() {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    match get_forwarding(connection.as_ref(), user.into_inner()).await? {
+        Some(forwarding) => Ok(HttpResponse::Ok().json(forwarding)),
+        None => Err(Error::NotFound),
+    }
+}
+
+#[put("/forwarding/{user}")]
+pub async fn set_account_forwarding(
+    user: web::Path<i64>,
+    auth: Authentication,
+    body: web::Json<NewForwarding>,
+    connection: DatabaseConnection,
+    sieve: Data<SieveScripts>,
+) -> Result<HttpResponse> {
+    if !auth.can_manage_users() {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    body.validate().map_err(Error::BadRequest)?;
+    let account = AccountEntity::find_by_id(user.into_inner())
+        .one(connection.as_ref())
+        .await?
+        .ok_or(Error::NotFound)?;
+    let transaction = connection.begin().await?;
+    let forwarding = set_forwarding(&transaction, account.id, body.into_inner()).await?;
+    sieve
+        .update_account(&transaction, account.id, &account.username)
+        .await?;
+    transaction.commit().await?;
+    Ok(HttpResponse::Ok().json(forwarding))
+}
+
+#[delete("/forwarding/{user}")]
+pub async fn remove_account_forwarding(
+    user: web::Path<i64>,
+    auth: Authentication,
+    connection: DatabaseConnection,
+    sieve: Data<SieveScripts>,
+) -> Result<HttpResponse> {
+    if !auth.can_manage_users() {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    let account = AccountEntity::find_by_id(user.into_inner())
+        .one(connection.as_ref())
+        .await?
+        .ok_or(Error::NotFound)?;
+    let transaction = connection.begin().await?;
+    if !remove_forwarding(&transaction, account.id).await? {
+        return Err(Error::NotFound);
+    }
+    sieve
+        .update_account(&transaction, account.id, &account.username)
+        .await?;
+    transaction.commit().await?;
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -1,34 +1,41 @@
-use actix_web::{get, web, HttpResponse};
-use entities::account::{database_helper::AccountSimple, full_user::FullUser};
-use serde::{Deserialize, Serialize};
+use actix_web::{get, web, web::Data, HttpResponse};
+use entities::account::{
+    database_helper::{AccountListQuery, AccountSimple},
+    full_user::FullUser,
+};
+use serde::Deserialize;
 
 use crate::{
     auth::{permissions::Permissions, Authentication},
+    quota::QuotaUsage,
     DatabaseConnection,
 };
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
-pub struct List {
-    #[serde(default)]
-    pub active: bool,
-}
-
+/// Lists the accounts a page at a time.
+///
+/// See [AccountListQuery] for the supported query parameters
 #[get("/list")]
 pub async fn list(
     auth: Authentication,
-    query: web::Query<List>,
+    query: web::Query<AccountListQuery>,
     db: DatabaseConnection,
+    quota: Data<QuotaUsage>,
 ) -> crate::Result<HttpResponse> {
     if !auth.can_manage_users() {
         return Ok(HttpResponse::Forbidden().finish());
     }
-    if query.active {
-        AccountSimple::get_all_active_accounts(db.as_ref()).await
-    } else {
-        AccountSimple::get_all_accounts(db.as_ref()).await
+    let mut list = AccountSimple::get_accounts(db.as_ref(), &query).await?;
+    let usage = quota
+        .get_many(
+            list.accounts
+                .iter()
+                .map(|account| account.username.as_str()),
+        )
+        .await;
+    for (account, used) in list.accounts.iter_mut().zip(usage) {
+        account.quota_used = used;
     }
-    .map(|users| HttpResponse::Ok().json(users))
-    .map_err(Into::into)
+    Ok(HttpResponse::Ok().json(list))
 }
 
 #[derive(Debug, Deserialize)]
@@ -42,13 +49,15 @@ pub async fn get_full_user(
     user: web::Path<i64>,
     get_params: web::Query<GetUser>,
     db: DatabaseConnection,
+    quota: Data<QuotaUsage>,
 ) -> crate::Result<HttpResponse> {
     if !auth.can_manage_users() {
         return Ok(HttpResponse::Forbidden().finish());
     }
 
-    FullUser::get_by_id(db.as_ref(), user.into_inner(), get_params.include_emails)
+    let mut user = FullUser::get_by_id(db.as_ref(), user.into_inner(), get_params.include_emails)
         .await?
-        .map(|user| HttpResponse::Ok().json(user))
-        .ok_or(crate::Error::NotFound)
+        .ok_or(crate::Error::NotFound)?;
+    user.quota_used = quota.get(&user.username).await;
+    Ok(HttpResponse::Ok().json(user))
 }
@@ -1,5 +1,8 @@
+mod bulk;
+mod forwarding;
 mod getters;
 mod setters;
+mod vacation;
 
 use actix_web::web::ServiceConfig;
 
@@ -10,6 +13,16 @@ pub fn init(service: &mut ServiceConfig) {
         .service(setters::password_change)
         .service(setters::set_password)
         .service(setters::update_active)
+        .service(setters::update_backup_email_verified)
         .service(setters::update_core)
-        .service(setters::new);
+        .service(setters::new)
+        .service(bulk::import)
+        .service(bulk::export)
+        .service(bulk::bulk_action)
+        .service(forwarding::get_account_forwarding)
+        .service(forwarding::set_account_forwarding)
+        .service(forwarding::remove_account_forwarding)
+        .service(vacation::get_account_vacation)
+        .service(vacation::set_account_vacation)
+        .service(vacation::remove_account_vacation);
 }
@@ -1,9 +1,10 @@
 use actix_web::{put, web, web::Data, HttpResponse};
+use chrono::Local;
 use entities::{
     account::{AccountType, ActiveModel},
     emails,
     emails::EmailType,
-    AccountEntity, AccountModel, ActiveAccountModel,
+    AccountEntity, AccountModel, ActiveAccountModel, GroupEntity,
 };
 use sea_orm::{
     sea_query::OnConflict, ActiveModelTrait, ActiveValue, EntityTrait, IntoActiveModel,
@@ -12,18 +13,28 @@ use sea_orm::{
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use tracing::debug;
-use utils::database::{EmailAddress, OptionalEmailAddress, Password};
+use utils::{
+    database::{EmailAddress, OptionalEmailAddress, Password},
+    quota_size::QuotaSize,
+};
 
 use crate::{
-    auth::{password_reset::PasswordResetManager, permissions::Permissions, Authentication},
+    auth::{
+        backup_email::BackupEmailVerifications, password_reset::PasswordResetManager,
+        permissions::Permissions, Authentication,
+    },
+    email_service::EmailAccess,
     headers::Origin,
+    notifications::{notify_account, AccountDeactivatedEmail, PasswordChangedEmail, WelcomeEmail},
+    quota::check_quota,
     DatabaseConnection, Error, Result, SharedConfig,
 };
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct UpdateAccount {
     pub name: Option<String>,
     pub description: Option<String>,
-    pub quota: Option<i64>,
+    /// Bytes or a size such as `5GiB`
+    pub quota: Option<QuotaSize>,
     pub account_type: Option<AccountType>,
     pub backup_email: Option<Option<EmailAddress>>,
 }
@@ -37,7 +48,7 @@ impl UpdateAccount {
             user.description = ActiveValue::Set(description);
         }
         if let Some(quota) = self.quota {
-            user.quota = ActiveValue::Set(quota);
+            user.quota = ActiveValue::Set(quota.into());
         }
         if let Some(account_type) = self.account_type {
             user.account_type = ActiveValue::Set(account_type);
@@ -48,25 +59,84 @@ impl UpdateAccount {
     }
 }
 
+/// A new backup email starts unverified and is sent a confirmation link
 #[put("/update/{user}/core")]
 pub async fn update_core(
     user: web::Path<i64>,
     auth: Authentication,
     data: web::Json<UpdateAccount>,
     database: DatabaseConnection,
+    settings: Data<SharedConfig>,
+    verifications: Data<BackupEmailVerifications>,
+    origin: Origin,
 ) -> Result<HttpResponse> {
     if !auth.can_manage_users() {
         return Ok(HttpResponse::Forbidden().finish());
     }
 
-    let mut user: ActiveAccountModel = AccountEntity::find_by_id(user.into_inner())
+    let user: AccountModel = AccountEntity::find_by_id(user.into_inner())
         .one(database.as_ref())
         .await?
-        .map(|x| x.into_active_model())
         .ok_or(Error::NotFound)?;
+    if let Some(quota) = data.quota {
+        let group = GroupEntity::find_by_id(user.group_id)
+            .one(database.as_ref())
+            .await?
+            .ok_or(Error::NotFound)?;
+        let primary_email =
+            emails::database_helper::get_primary_address(database.as_ref(), user.id).await?;
+        check_quota(
+            database.as_ref(),
+            &settings,
+            &group,
+            Some(user.id),
+            primary_email.as_ref().map(|email| &email.email_address),
+            quota.into(),
+        )
+        .await?;
+    }
+    let new_backup_email = data
+        .backup_email
+        .clone()
+        .filter(|backup_email| *backup_email != user.backup_email);
+    let mut user = user.into_active_model();
 
     data.into_inner().apply_changes(&mut user);
+    if new_backup_email.is_some() {
+        user.backup_email_verified = ActiveValue::Set(None);
+    }
+
+    let user = user.save(database.as_ref()).await?.try_into_model()?;
+    if let Some(Some(backup_email)) = new_backup_email {
+        verifications.request(user.username, user.id, backup_email, origin, false);
+    }
 
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Lets an admin mark a backup email as verified without the confirmation link
+#[put("/update/{user}/backup-email-verified/{verified}")]
+pub async fn update_backup_email_verified(
+    user: web::Path<(i64, bool)>,
+    auth: Authentication,
+    database: DatabaseConnection,
+) -> Result<HttpResponse> {
+    if !auth.can_manage_users() {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+
+    let (user, verified) = user.into_inner();
+    let user = AccountEntity::find_by_id(user)
+        .one(database.as_ref())
+        .await?
+        .ok_or(Error::NotFound)?;
+    if user.backup_email.is_none() {
+        return Err(Error::BadRequest(
+            "The account does not have a backup email",
+        ));
+    }
+    let mut user = user.into_active_model();
+    user.backup_email_verified = ActiveValue::Set(verified.then(|| Local::now().into()));
     user.save(database.as_ref()).await?;
 
     Ok(HttpResponse::NoContent().finish())
@@ -77,6 +147,7 @@ pub async fn update_active(
     user: web::Path<(i64, bool)>,
     auth: Authentication,
     database: DatabaseConnection,
+    email: Data<EmailAccess>,
 ) -> Result<HttpResponse> {
     if !auth.can_manage_users() {
         return Ok(HttpResponse::Forbidden().finish());
@@ -89,11 +160,24 @@ pub async fn update_active(
         .map(|x| x.into_active_model())
         .ok_or(Error::NotFound)?;
 
+    let was_active = user.active.clone().unwrap();
     user.active = ActiveValue::Set(active);
 
     // TODO: Run post active hook
 
-    user.save(database.as_ref()).await?;
+    let user: AccountModel = user.save(database.as_ref()).await?.try_into_model()?;
+    if was_active && !active {
+        notify_account(
+            database.as_ref(),
+            &email,
+            &user,
+            AccountDeactivatedEmail {
+                name: user.name.clone(),
+                username: user.username.clone(),
+            },
+        )
+        .await;
+    }
 
     Ok(HttpResponse::NoContent().finish())
 }
@@ -141,7 +225,9 @@ pub struct NewAccount {
     pub username: String,
     #[serde(default)]
     pub description: String,
-    pub quota: i64,
+    /// Bytes or a size such as `5GiB`. Defaults to the default quota of the group
+    #[serde(default)]
+    pub quota: Option<QuotaSize>,
     #[serde(default)]
     pub require_password_change: bool,
     #[serde(default)]
@@ -164,13 +250,14 @@ pub async fn set_password(
     user: web::Path<i64>,
     database: DatabaseConnection,
     settings: Data<SharedConfig>,
+    email: Data<EmailAccess>,
 ) -> Result<HttpResponse> {
     if !auth.can_manage_users() {
         return Ok(HttpResponse::Forbidden().finish());
     }
 
     let data = data.into_inner();
-    let password = Password::new_hash(data.password, settings.password_hash)
+    let password = Password::new_hash(data.password, settings.password_hash())
         .map_err(|_| Error::UnableToHashPassword)?;
 
     let mut user: ActiveAccountModel = AccountEntity::find_by_id(user.into_inner())
@@ -181,7 +268,17 @@ pub async fn set_password(
 
     user.password = ActiveValue::Set(password);
 
-    user.save(database.as_ref()).await?;
+    let user: AccountModel = user.save(database.as_ref()).await?.try_into_model()?;
+    notify_account(
+        database.as_ref(),
+        &email,
+        &user,
+        PasswordChangedEmail {
+            name: user.name.clone(),
+            username: user.username.clone(),
+        },
+    )
+    .await;
 
     Ok(HttpResponse::NoContent().finish())
 }
@@ -191,8 +288,8 @@ pub async fn new(
     data: web::Json<NewAccount>,
     database: DatabaseConnection,
     settings: Data<SharedConfig>,
-    password_reset: Data<PasswordResetManager>,
-
+    verifications: Data<BackupEmailVerifications>,
+    email: Data<EmailAccess>,
     origin: Origin,
 ) -> Result<HttpResponse> {
     if !auth.can_manage_users() {
@@ -200,17 +297,35 @@ pub async fn new(
     }
 
     let data = data.into_inner();
-    let password = Password::new_hash(data.password, settings.password_hash)
+    let group = GroupEntity::find_by_id(data.group)
+        .one(database.as_ref())
+        .await?
+        .ok_or(Error::BadRequest("Group does not exist"))?;
+    let quota = data
+        .quota
+        .map(i64::from)
+        .unwrap_or_else(|| group.default_quota_or_unlimited());
+    check_quota(
+        database.as_ref(),
+        &settings,
+        &group,
+        None,
+        data.primary_email.0.as_ref(),
+        quota,
+    )
+    .await?;
+    let password = Password::new_hash(data.password, settings.password_hash())
         .map_err(|_| Error::UnableToHashPassword)?;
     let user = ActiveModel {
         id: ActiveValue::NotSet,
-        name: ActiveValue::Set(data.name),
-        username: ActiveValue::Set(data.username),
+        name: ActiveValue::Set(data.name.clone()),
+        username: ActiveValue::Set(data.username.clone()),
         description: ActiveValue::Set(data.description),
-        quota: ActiveValue::Set(data.quota),
+        quota: ActiveValue::Set(quota),
         require_password_change: ActiveValue::Set(data.require_password_change),
         account_type: ActiveValue::Set(data.account_type),
         backup_email: ActiveValue::Set(data.backup_email.clone().0),
+        backup_email_verified: ActiveValue::Set(None),
         active: Default::default(),
         group_id: ActiveValue::Set(data.group),
         created: Default::default(),
@@ -229,30 +344,21 @@ pub async fn new(
     match result {
         Ok(ok) => {
             let id = ok.last_insert_id;
-            if data.send_a_password_reset_email {
-                let user: AccountModel = AccountEntity::find_by_id(id)
-                    .one(database.as_ref())
-                    .await?
-                    .ok_or(Error::NotFound)?;
-                if let Some(email) = data.backup_email.0 {
-                    debug!("Sending password reset email to {}", email);
-                    password_reset.request(
-                        user.username,
-                        user.id,
-                        email,
-                        origin,
-                        user.require_password_change,
-                    );
-                } else {
-                    debug!("No backup email provided, not sending password reset email");
-                }
+            // The password reset is held until the new backup email is confirmed
+            if let Some(backup_email) = data.backup_email.0.clone() {
+                verifications.request(
+                    data.username.clone(),
+                    id,
+                    backup_email,
+                    origin.clone(),
+                    data.send_a_password_reset_email,
+                );
+            } else if data.send_a_password_reset_email {
+                debug!("No backup email provided, not sending password reset email");
             }
-            let primary_email_address_added = if let Some(value) = data.primary_email.0 {
-                if emails::database_helper::does_primary_email_exist(
-                    database.as_ref(),
-                    value.clone(),
-                )
-                .await?
+            let primary_email_address_added = if let Some(value) = data.primary_email.0.clone() {
+                if emails::database_helper::does_email_exist(database.as_ref(), value.clone())
+                    .await?
                 {
                     false
                 } else {
@@ -272,6 +378,19 @@ pub async fn new(
                 false
             };
 
+            let primary_email = data.primary_email.0.filter(|_| primary_email_address_added);
+            // The backup email is preferred even though it is not verified yet. The login instructions are needed to read the mailbox
+            if let Some(welcome_to) = data.backup_email.0.or_else(|| primary_email.clone()) {
+                email.send_one_fn(
+                    welcome_to,
+                    WelcomeEmail {
+                        name: data.name,
+                        username: data.username,
+                        panel_url: origin.into(),
+                        email_address: primary_email,
+                    },
+                );
+            }
             Ok(HttpResponse::Created().json(json!({
                 "id": id,
                 "primary_email_address_added": primary_email_address_added
@@ -0,0 +1,33 @@
+use actix_web::{
+    post,
+    web::{Data, ServiceConfig},
+    HttpResponse,
+};
+
+use crate::{
+    auth::{permissions::Permissions, Authentication},
+    config_reload::{ConfigReloader, ReloadError},
+};
+
+pub fn init(service: &mut ServiceConfig) {
+    service.service(reload_config);
+}
+/// Reads the config file again and applies it.
+///
+/// Responds with the settings that changed and the ones that need a restart.
+/// Nothing changes if the config is invalid
+#[post("/reload")]
+pub async fn reload_config(
+    auth: Authentication,
+    reloader: Data<ConfigReloader>,
+) -> crate::Result<HttpResponse> {
+    if !auth.can_manage_system() {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    let errors: Vec<String> = match reloader.reload() {
+        Ok(report) => return Ok(HttpResponse::Ok().json(report)),
+        Err(ReloadError::Config(errors)) => errors.0.iter().map(ToString::to_string).collect(),
+        Err(error) => vec![error.to_string()],
+    };
+    Ok(HttpResponse::BadRequest().json(errors))
+}
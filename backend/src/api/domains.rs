@@ -0,0 +1,62 @@
+use actix_web::{delete, get, put, web, web::ServiceConfig, HttpResponse};
+use entities::domain_settings::database_helper::{
+    get_all_domain_settings, normalize_domain, remove_domain_settings, set_domain_settings,
+    NewDomainSettings,
+};
+
+use crate::{
+    auth::{permissions::Permissions, Authentication},
+    error::WebsiteError,
+    DatabaseConnection, Result,
+};
+pub fn init(service: &mut ServiceConfig) {
+    service
+        .service(get_domains)
+        .service(update_domain)
+        .service(reset_domain);
+}
+
+#[get("/list")]
+pub async fn get_domains(
+    connection: DatabaseConnection,
+    auth: Authentication,
+) -> Result<HttpResponse> {
+    if !auth.can_manage_system() {
+        return Err(WebsiteError::Unauthorized);
+    }
+    let domains = get_all_domain_settings(connection.as_ref()).await?;
+    Ok(HttpResponse::Ok().json(domains))
+}
+
+#[put("/{domain}")]
+pub async fn update_domain(
+    connection: DatabaseConnection,
+    domain: web::Path<String>,
+    settings: web::Json<NewDomainSettings>,
+    auth: Authentication,
+) -> Result<HttpResponse> {
+    if !auth.can_manage_system() {
+        return Err(WebsiteError::Unauthorized);
+    }
+    let domain = normalize_domain(&domain).ok_or(WebsiteError::BadRequest("Invalid domain"))?;
+    let settings = set_domain_settings(connection.as_ref(), domain, settings.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(settings))
+}
+
+/// The domain goes back to the defaults
+#[delete("/{domain}")]
+pub async fn reset_domain(
+    connection: DatabaseConnection,
+    domain: web::Path<String>,
+    auth: Authentication,
+) -> Result<HttpResponse> {
+    if !auth.can_manage_system() {
+        return Err(WebsiteError::Unauthorized);
+    }
+    let domain = normalize_domain(&domain).ok_or(WebsiteError::BadRequest("Invalid domain"))?;
+    if remove_domain_settings(connection.as_ref(), &domain).await? {
+        Ok(HttpResponse::NoContent().finish())
+    } else {
+        Err(WebsiteError::NotFound)
+    }
+}
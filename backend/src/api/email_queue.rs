@@ -0,0 +1,83 @@
+use actix_web::{
+    delete, get, put,
+    web::{Data, Path, Query, ServiceConfig},
+    HttpResponse,
+};
+use serde::Deserialize;
+
+use crate::{
+    auth::{permissions::Permissions, Authentication},
+    email_queue::QueuedEmailState,
+    email_service::EmailAccess,
+    Error,
+};
+
+pub fn init(service: &mut ServiceConfig) {
+    service
+        .service(list_queue)
+        .service(transport_state)
+        .service(retry_email)
+        .service(drop_email);
+}
+#[derive(Debug, Deserialize)]
+pub struct QueueQuery {
+    /// Only list the emails in this state
+    #[serde(default)]
+    pub state: Option<QueuedEmailState>,
+}
+/// Emails waiting to be sent and the ones that failed
+#[get("")]
+pub async fn list_queue(
+    auth: Authentication,
+    query: Query<QueueQuery>,
+    email: Data<EmailAccess>,
+) -> crate::Result<HttpResponse> {
+    if !auth.can_manage_system() {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    let emails = email.queue().list(query.state)?;
+    Ok(HttpResponse::Ok().json(emails))
+}
+/// If the SMTP server can be reached. Including the error when it can not
+#[get("/transport")]
+pub async fn transport_state(
+    auth: Authentication,
+    email: Data<EmailAccess>,
+) -> crate::Result<HttpResponse> {
+    if !auth.can_manage_system() {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    Ok(HttpResponse::Ok().json(email.transport_state()))
+}
+/// Sends the email again with a fresh set of attempts
+#[put("/{id}/retry")]
+pub async fn retry_email(
+    auth: Authentication,
+    id: Path<u64>,
+    email: Data<EmailAccess>,
+) -> crate::Result<HttpResponse> {
+    if !auth.can_manage_system() {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    let queued = email
+        .queue()
+        .retry(id.into_inner())?
+        .ok_or(Error::NotFound)?;
+    email.wake();
+    Ok(HttpResponse::Ok().json(queued))
+}
+/// Removes the email without sending it
+#[delete("/{id}")]
+pub async fn drop_email(
+    auth: Authentication,
+    id: Path<u64>,
+    email: Data<EmailAccess>,
+) -> crate::Result<HttpResponse> {
+    if !auth.can_manage_system() {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    if !email.queue().remove(id.into_inner())? {
+        return Err(Error::NotFound);
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
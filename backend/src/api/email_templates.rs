@@ -0,0 +1,179 @@
+use actix_web::{
+    get,
+    web::{Data, Path, Query, ServiceConfig},
+    HttpResponse,
+};
+use chrono::Local;
+use handlebars::RenderError;
+use serde::Deserialize;
+use utils::database::EmailAddress;
+
+use crate::{
+    auth::{
+        backup_email::BackupEmailConfirmEmail, password_reset::PasswordResetEmail,
+        permissions::Permissions, Authentication,
+    },
+    email_service::{EmailAccess, EmailPreview},
+    headers::Origin,
+    notifications::{
+        AccountDeactivatedEmail, AliasAddedEmail, NewLoginEmail, PasswordChangedEmail, WelcomeEmail,
+    },
+    quota::QuotaAlertEmail,
+};
+
+/// Renders a template with sample data
+///
+/// # Arguments
+/// panel_url - Used in the links of the template
+/// locale - Replaces the configured locale
+type Preview = fn(&EmailAccess, &str, Option<&str>) -> Result<EmailPreview, RenderError>;
+/// Every template that can be previewed
+pub const TEMPLATES: [(&str, Preview); 8] = [
+    ("password_reset", |email, panel_url, locale| {
+        email.preview(
+            &PasswordResetEmail {
+                token: "sample-token",
+                panel_url: panel_url.to_owned(),
+                username: username(),
+                required: false,
+            },
+            locale,
+        )
+    }),
+    ("backup_email_confirm", |email, panel_url, locale| {
+        email.preview(
+            &BackupEmailConfirmEmail {
+                token: "sample-token",
+                panel_url: panel_url.to_owned(),
+                username: username(),
+            },
+            locale,
+        )
+    }),
+    ("quota_alert", |email, _, locale| {
+        email.preview(
+            &QuotaAlertEmail {
+                name: name(),
+                username: username(),
+                used: 4_294_967_296,
+                limit: 5_368_709_120,
+                percent: 80,
+            },
+            locale,
+        )
+    }),
+    ("welcome", |email, panel_url, locale| {
+        email.preview(
+            &WelcomeEmail {
+                name: name(),
+                username: username(),
+                panel_url: panel_url.to_owned(),
+                email_address: Some(address("alice@example.com")),
+            },
+            locale,
+        )
+    }),
+    ("password_changed", |email, _, locale| {
+        email.preview(
+            &PasswordChangedEmail {
+                name: name(),
+                username: username(),
+            },
+            locale,
+        )
+    }),
+    ("account_deactivated", |email, _, locale| {
+        email.preview(
+            &AccountDeactivatedEmail {
+                name: name(),
+                username: username(),
+            },
+            locale,
+        )
+    }),
+    ("alias_added", |email, _, locale| {
+        email.preview(
+            &AliasAddedEmail {
+                name: name(),
+                username: username(),
+                alias: address("alice.example@example.com"),
+            },
+            locale,
+        )
+    }),
+    ("new_login", |email, _, locale| {
+        email.preview(
+            &NewLoginEmail {
+                name: name(),
+                username: username(),
+                ip: "203.0.113.7".to_owned(),
+                time: Local::now().to_rfc2822(),
+            },
+            locale,
+        )
+    }),
+];
+fn name() -> String {
+    "Alice Example".to_owned()
+}
+fn username() -> String {
+    "alice".to_owned()
+}
+fn address(address: &str) -> EmailAddress {
+    EmailAddress::new(address).expect("Sample addresses are valid")
+}
+
+pub fn init(service: &mut ServiceConfig) {
+    service.service(list_templates).service(preview_template);
+}
+#[get("")]
+pub async fn list_templates(auth: Authentication) -> crate::Result<HttpResponse> {
+    if !auth.can_manage_system() {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    let names: Vec<&str> = TEMPLATES.iter().map(|(name, _)| *name).collect();
+    Ok(HttpResponse::Ok().json(names))
+}
+#[derive(Debug, Deserialize)]
+pub struct PreviewQuery {
+    /// Defaults to the configured locale
+    #[serde(default)]
+    pub locale: Option<String>,
+}
+/// Renders the template with sample data. Uses the templates directory if one is configured
+#[get("/{template}/preview")]
+pub async fn preview_template(
+    auth: Authentication,
+    template: Path<String>,
+    query: Query<PreviewQuery>,
+    email: Data<EmailAccess>,
+    origin: Origin,
+) -> crate::Result<HttpResponse> {
+    if !auth.can_manage_system() {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    let (_, preview) = TEMPLATES
+        .iter()
+        .find(|(name, _)| *name == template.as_str())
+        .ok_or(crate::Error::NotFound)?;
+    let preview = preview(&email, &origin.url, query.locale.as_deref())?;
+    Ok(HttpResponse::Ok().json(preview))
+}
+#[cfg(test)]
+mod tests {
+    use super::TEMPLATES;
+    use crate::test_support::TestEmail;
+
+    #[actix_rt::test]
+    pub async fn test_every_template_renders() {
+        let email = TestEmail::start().await;
+        for (name, preview) in TEMPLATES {
+            for locale in [None, Some("de")] {
+                let preview = preview(&email.access, "https://panel.example.com", locale)
+                    .unwrap_or_else(|error| panic!("{} {:?}: {}", name, locale, error));
+                assert!(!preview.html.is_empty(), "{}", name);
+                assert!(!preview.txt.is_empty(), "{}", name);
+            }
+        }
+    }
+}
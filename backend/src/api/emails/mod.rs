@@ -1,4 +1,8 @@
-use actix_web::{delete, put, web, web::ServiceConfig, HttpResponse};
+use actix_web::{
+    delete, put, web,
+    web::{Data, ServiceConfig},
+    HttpResponse,
+};
 use entities::{
     emails,
     emails::{ActiveModel, EmailType},
@@ -6,7 +10,7 @@ use entities::{
 };
 use sea_orm::{
     ActiveModelTrait, ActiveValue, ColumnTrait, DeleteResult, EntityTrait, IntoActiveModel,
-    PaginatorTrait, QueryFilter, TryIntoModel,
+    QueryFilter, TryIntoModel,
 };
 use serde::Deserialize;
 use tracing::debug;
@@ -14,7 +18,9 @@ use utils::database::EmailAddress;
 
 use crate::{
     auth::{permissions::Permissions, Authentication},
+    email_service::EmailAccess,
     error::WebsiteError,
+    notifications::{notify_account, AliasAddedEmail},
     DatabaseConnection, Result,
 };
 pub fn init(service: &mut ServiceConfig) {
@@ -34,24 +40,51 @@ pub async fn add_or_update(
     account_id: web::Path<i64>,
     email: web::Json<AddOrUpdateEmail>,
     auth: Authentication,
+    email_access: Data<EmailAccess>,
 ) -> Result<HttpResponse> {
     if !auth.can_manage_users() {
         return Err(WebsiteError::Unauthorized);
     }
     let user = account_id.into_inner();
-    if AccountEntity::find_by_id(user)
-        .count(connection.as_ref())
+    let account = AccountEntity::find_by_id(user)
+        .one(connection.as_ref())
         .await?
-        == 0
-    {
-        return Err(WebsiteError::NotFound);
-    }
+        .ok_or(WebsiteError::NotFound)?;
 
     let AddOrUpdateEmail {
         id,
         email_address,
         email_type,
     } = email.into_inner();
+    match (
+        email_type == EmailType::CatchAll,
+        email_address.is_catch_all(),
+    ) {
+        (true, false) => {
+            return Err(WebsiteError::BadRequest(
+                "A catch-all address must be `*@{domain}`",
+            ))
+        }
+        (false, true) => {
+            return Err(WebsiteError::BadRequest(
+                "`*@{domain}` can only be used as a catch-all",
+            ))
+        }
+        _ => {}
+    }
+    let existing = EmailEntity::find()
+        .filter(entities::emails::Column::EmailAddress.eq(email_address.clone()))
+        .one(connection.as_ref())
+        .await?;
+    if existing.map_or(false, |existing| existing.account != user) {
+        return Err(WebsiteError::Conflict(
+            if email_type == EmailType::CatchAll {
+                "Another account is the catch-all for the domain"
+            } else {
+                "Email address belongs to another account"
+            },
+        ));
+    }
 
     let email: ActiveModel = if let Some(id) = id {
         let email = EmailEntity::find_by_id(id)
@@ -104,7 +137,21 @@ pub async fn add_or_update(
         }
     }
     debug!("Saving email: {:?}", email);
+    let new_alias = email.id.is_not_set() && email_type == EmailType::Alias;
     let active = email.save(connection.as_ref()).await?.try_into_model()?;
+    if new_alias {
+        notify_account(
+            connection.as_ref(),
+            &email_access,
+            &account,
+            AliasAddedEmail {
+                name: account.name.clone(),
+                username: account.username.clone(),
+                alias: active.email_address.clone(),
+            },
+        )
+        .await;
+    }
     Ok(HttpResponse::Ok().json(active))
 }
 #[derive(Debug, Deserialize)]
@@ -1,10 +1,15 @@
-use actix_web::{get, web::ServiceConfig, HttpResponse};
+use actix_web::{get, put, web, web::ServiceConfig, HttpResponse};
 use entities::GroupEntity;
-use sea_orm::prelude::*;
+use sea_orm::{prelude::*, ActiveValue, IntoActiveModel};
+use serde::Deserialize;
+use utils::quota_size::QuotaSize;
 
 use crate::auth::permissions::Permissions;
 pub fn init(service: &mut ServiceConfig) {
-    service.service(get_groups);
+    service
+        .service(get_groups)
+        .service(update_quotas)
+        .service(update_alias_limit);
 }
 
 #[get("/list")]
@@ -18,3 +23,75 @@ pub async fn get_groups(
     let groups = GroupEntity::find().all(database.as_ref()).await?;
     Ok(HttpResponse::Ok().json(groups))
 }
+
+/// Bytes or sizes such as `5GiB`. Leaving one out removes it
+#[derive(Debug, Deserialize)]
+pub struct GroupQuotas {
+    pub default_quota: Option<QuotaSize>,
+    pub max_quota: Option<QuotaSize>,
+}
+#[put("/{group}/quotas")]
+pub async fn update_quotas(
+    group: web::Path<i64>,
+    quotas: web::Json<GroupQuotas>,
+    database: crate::DatabaseConnection,
+    auth: crate::auth::Authentication,
+) -> crate::Result<HttpResponse> {
+    if !auth.can_manage_system() {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    let GroupQuotas {
+        default_quota,
+        max_quota,
+    } = quotas.into_inner();
+    if let (Some(default_quota), Some(max_quota)) = (default_quota, max_quota) {
+        if default_quota == QuotaSize(0) {
+            return Err(crate::Error::BadRequest(
+                "The default quota of 0 (unlimited) is above the max quota",
+            ));
+        }
+        if default_quota > max_quota {
+            return Err(crate::Error::BadRequest(
+                "The default quota is larger than the max quota",
+            ));
+        }
+    }
+    let mut group = GroupEntity::find_by_id(group.into_inner())
+        .one(database.as_ref())
+        .await?
+        .ok_or(crate::Error::NotFound)?
+        .into_active_model();
+    group.default_quota = ActiveValue::Set(default_quota.map(i64::from));
+    group.max_quota = ActiveValue::Set(max_quota.map(i64::from));
+    let group = group.update(database.as_ref()).await?;
+    Ok(HttpResponse::Ok().json(group))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GroupAliasLimit {
+    pub alias_limit: i32,
+}
+#[put("/{group}/alias-limit")]
+pub async fn update_alias_limit(
+    group: web::Path<i64>,
+    body: web::Json<GroupAliasLimit>,
+    database: crate::DatabaseConnection,
+    auth: crate::auth::Authentication,
+) -> crate::Result<HttpResponse> {
+    if !auth.can_manage_system() {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    if body.alias_limit < 0 {
+        return Err(crate::Error::BadRequest(
+            "The alias limit can not be negative",
+        ));
+    }
+    let mut group = GroupEntity::find_by_id(group.into_inner())
+        .one(database.as_ref())
+        .await?
+        .ok_or(crate::Error::NotFound)?
+        .into_active_model();
+    group.alias_limit = ActiveValue::Set(body.alias_limit);
+    let group = group.update(database.as_ref()).await?;
+    Ok(HttpResponse::Ok().json(group))
+}
@@ -0,0 +1,126 @@
+use actix_web::{delete, get, put, web, web::ServiceConfig, HttpResponse};
+use entities::{
+    list_members::{self, database_helper::get_list},
+    AccountEntity, ListMemberActiveModel, ListMemberEntity,
+};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, EntityTrait, PaginatorTrait, QueryFilter,
+};
+use serde::Deserialize;
+use utils::database::EmailAddress;
+
+use crate::{
+    auth::{permissions::Permissions, Authentication},
+    error::WebsiteError,
+    DatabaseConnection, Result,
+};
+pub fn init(service: &mut ServiceConfig) {
+    service
+        .service(get_members)
+        .service(add_member)
+        .service(remove_member);
+}
+
+#[get("/{list}/members")]
+pub async fn get_members(
+    connection: DatabaseConnection,
+    list: web::Path<i64>,
+    auth: Authentication,
+) -> Result<HttpResponse> {
+    if !auth.can_manage_users() {
+        return Err(WebsiteError::Unauthorized);
+    }
+    let list = get_list(connection.as_ref(), list.into_inner())
+        .await?
+        .ok_or(WebsiteError::NotFound)?;
+    let members = list_members::database_helper::get_members(connection.as_ref(), list.id).await?;
+    Ok(HttpResponse::Ok().json(members))
+}
+
+/// Either an account or an external address
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NewListMember {
+    Account(i64),
+    ExternalAddress(EmailAddress),
+}
+#[put("/{list}/members")]
+pub async fn add_member(
+    connection: DatabaseConnection,
+    list: web::Path<i64>,
+    member: web::Json<NewListMember>,
+    auth: Authentication,
+) -> Result<HttpResponse> {
+    if !auth.can_manage_users() {
+        return Err(WebsiteError::Unauthorized);
+    }
+    let list = get_list(connection.as_ref(), list.into_inner())
+        .await?
+        .ok_or(WebsiteError::NotFound)?;
+    let (account, external_address) = match member.into_inner() {
+        NewListMember::Account(account) => {
+            if AccountEntity::find_by_id(account)
+                .count(connection.as_ref())
+                .await?
+                == 0
+            {
+                return Err(WebsiteError::BadRequest("Account does not exist"));
+            }
+            (Some(account), None)
+        }
+        NewListMember::ExternalAddress(address) => {
+            if address == list.email_address {
+                return Err(WebsiteError::BadRequest(
+                    "A list can not be a member of itself",
+                ));
+            }
+            (None, Some(address))
+        }
+    };
+    if list_members::database_helper::is_member(
+        connection.as_ref(),
+        list.id,
+        account,
+        external_address.as_ref(),
+    )
+    .await?
+    {
+        return Err(WebsiteError::Conflict("Already a member of the list"));
+    }
+    let member = ListMemberActiveModel {
+        id: ActiveValue::NotSet,
+        list: ActiveValue::Set(list.id),
+        account: ActiveValue::Set(account),
+        external_address: ActiveValue::Set(external_address),
+        created: entities::now(),
+    }
+    .insert(connection.as_ref())
+    .await?;
+    Ok(HttpResponse::Created().json(member))
+}
+
+#[delete("/{list}/members/{member}")]
+pub async fn remove_member(
+    connection: DatabaseConnection,
+    path: web::Path<(i64, i64)>,
+    auth: Authentication,
+) -> Result<HttpResponse> {
+    use entities::list_members::Column as ListMemberColumn;
+    if !auth.can_manage_users() {
+        return Err(WebsiteError::Unauthorized);
+    }
+    let (list, member) = path.into_inner();
+    let result = ListMemberEntity::delete_many()
+        .filter(
+            ListMemberColumn::Id
+                .eq(member)
+                .and(ListMemberColumn::List.eq(list)),
+        )
+        .exec(connection.as_ref())
+        .await?;
+    if result.rows_affected == 0 {
+        Err(WebsiteError::NotFound)
+    } else {
+        Ok(HttpResponse::NoContent().finish())
+    }
+}
@@ -1,4 +1,10 @@
 pub mod accounts;
+pub mod config;
+pub mod domains;
+pub mod email_queue;
+pub mod email_templates;
 pub mod emails;
 pub mod groups;
+pub mod lists;
+pub mod shared_mailboxes;
 pub mod user;
@@ -0,0 +1,130 @@
+use actix_web::{delete, get, put, web, web::ServiceConfig, HttpResponse};
+use entities::{
+    account::AccountType,
+    shared_mailboxes::{database_helper::get_members, AccessLevel, Column as SharedMailboxColumn},
+    AccountEntity, AccountModel, SharedMailboxMemberActiveModel, SharedMailboxMemberEntity,
+};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, EntityTrait, IntoActiveModel,
+    QueryFilter,
+};
+use serde::Deserialize;
+
+use crate::{
+    auth::{permissions::Permissions, Authentication},
+    error::WebsiteError,
+    DatabaseConnection, Result,
+};
+pub fn init(service: &mut ServiceConfig) {
+    service
+        .service(list_members)
+        .service(set_member)
+        .service(remove_member);
+}
+/// Gets the account if it has the type group
+async fn get_mailbox(connection: &impl ConnectionTrait, mailbox: i64) -> Result<AccountModel> {
+    let mailbox = AccountEntity::find_by_id(mailbox)
+        .one(connection)
+        .await?
+        .ok_or(WebsiteError::NotFound)?;
+    if mailbox.account_type != AccountType::Group {
+        return Err(WebsiteError::BadRequest(
+            "Only group accounts can be shared mailboxes",
+        ));
+    }
+    Ok(mailbox)
+}
+
+#[get("/{mailbox}/members")]
+pub async fn list_members(
+    connection: DatabaseConnection,
+    mailbox: web::Path<i64>,
+    auth: Authentication,
+) -> Result<HttpResponse> {
+    if !auth.can_manage_users() {
+        return Err(WebsiteError::Unauthorized);
+    }
+    let mailbox = get_mailbox(connection.as_ref(), mailbox.into_inner()).await?;
+    let members = get_members(connection.as_ref(), mailbox.id).await?;
+    Ok(HttpResponse::Ok().json(members))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetMember {
+    pub access: AccessLevel,
+}
+/// Adds the account to the mailbox or changes its access level
+#[put("/{mailbox}/members/{member}")]
+pub async fn set_member(
+    connection: DatabaseConnection,
+    path: web::Path<(i64, i64)>,
+    body: web::Json<SetMember>,
+    auth: Authentication,
+) -> Result<HttpResponse> {
+    if !auth.can_manage_users() {
+        return Err(WebsiteError::Unauthorized);
+    }
+    let (mailbox, member) = path.into_inner();
+    let mailbox = get_mailbox(connection.as_ref(), mailbox).await?;
+    let member = AccountEntity::find_by_id(member)
+        .one(connection.as_ref())
+        .await?
+        .ok_or(WebsiteError::NotFound)?;
+    if member.account_type != AccountType::Individual {
+        return Err(WebsiteError::BadRequest(
+            "Only individual accounts can be members of a shared mailbox",
+        ));
+    }
+    let existing = SharedMailboxMemberEntity::find()
+        .filter(
+            SharedMailboxColumn::Mailbox
+                .eq(mailbox.id)
+                .and(SharedMailboxColumn::Member.eq(member.id)),
+        )
+        .one(connection.as_ref())
+        .await?;
+    let membership = match existing {
+        Some(existing) => {
+            let mut existing = existing.into_active_model();
+            existing.access = ActiveValue::Set(body.access);
+            existing.update(connection.as_ref()).await?
+        }
+        None => {
+            SharedMailboxMemberActiveModel {
+                id: ActiveValue::NotSet,
+                mailbox: ActiveValue::Set(mailbox.id),
+                member: ActiveValue::Set(member.id),
+                access: ActiveValue::Set(body.access),
+                created: entities::now(),
+            }
+            .insert(connection.as_ref())
+            .await?
+        }
+    };
+    Ok(HttpResponse::Ok().json(membership))
+}
+
+#[delete("/{mailbox}/members/{member}")]
+pub async fn remove_member(
+    connection: DatabaseConnection,
+    path: web::Path<(i64, i64)>,
+    auth: Authentication,
+) -> Result<HttpResponse> {
+    if !auth.can_manage_users() {
+        return Err(WebsiteError::Unauthorized);
+    }
+    let (mailbox, member) = path.into_inner();
+    let result = SharedMailboxMemberEntity::delete_many()
+        .filter(
+            SharedMailboxColumn::Mailbox
+                .eq(mailbox)
+                .and(SharedMailboxColumn::Member.eq(member)),
+        )
+        .exec(connection.as_ref())
+        .await?;
+    if result.rows_affected == 0 {
+        Err(WebsiteError::NotFound)
+    } else {
+        Ok(HttpResponse::NoContent().finish())
+    }
+}
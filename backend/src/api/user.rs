@@ -1,16 +1,50 @@
 use actix_web::{
-    get, put,
-    web::{Data, ServiceConfig},
+    delete, get, put,
+    web::{Data, Json, Path, ServiceConfig},
     HttpResponse,
 };
-use entities::account::panel_user::PanelUser;
-use sea_orm::{ActiveModelTrait, ActiveValue, IntoActiveModel};
+use entities::{
+    account::panel_user::PanelUser,
+    emails::{Column as EmailColumn, EmailType, Emails},
+    forwarding::database_helper::{
+        get_forwarding, remove_forwarding, set_forwarding, NewForwarding,
+    },
+    vacation::database_helper::{get_vacation, remove_vacation, set_vacation, NewVacation},
+    AccountEntity, EmailActiveModel, EmailEntity, GroupEntity,
+};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, EntityTrait, IntoActiveModel, PaginatorTrait,
+    QueryFilter, TransactionTrait,
+};
+use serde_json::json;
 use utils::database::{EmailAddress, Password};
 
-use crate::{auth::Authentication, DatabaseConnection, Error, SharedConfig};
+use crate::{
+    auth::{backup_email::BackupEmailVerifications, Authentication},
+    email_service::EmailAccess,
+    headers::Origin,
+    notifications::{notify_panel_user, AliasAddedEmail, PasswordChangedEmail},
+    quota::QuotaUsage,
+    sieve::SieveScripts,
+    DatabaseConnection, Error, SharedConfig,
+};
 
 pub fn init(service: &mut ServiceConfig) {
-    service.service(me).service(change_password);
+    service
+        .service(me)
+        .service(change_password)
+        .service(backup_email)
+        .service(change_name)
+        .service(my_quota)
+        .service(my_aliases)
+        .service(add_alias)
+        .service(remove_alias)
+        .service(my_forwarding)
+        .service(set_my_forwarding)
+        .service(remove_my_forwarding)
+        .service(my_vacation)
+        .service(set_my_vacation)
+        .service(remove_my_vacation);
 }
 #[get("/me")]
 pub async fn me(auth: Authentication) -> crate::Result<HttpResponse> {
@@ -27,6 +61,7 @@ pub async fn change_password(
     body: actix_web::web::Form<ChangePassword>,
     database: DatabaseConnection,
     settings: Data<SharedConfig>,
+    email: Data<EmailAccess>,
 ) -> crate::Result<HttpResponse> {
     let user: PanelUser = auth.into();
     if !user
@@ -36,27 +71,283 @@ pub async fn change_password(
     {
         return Err(Error::Unauthorized);
     }
-    let mut user = user.into_active_model();
-    user.password = ActiveValue::Set(
-        Password::new_hash(&body.new_password, settings.password_hash)
+    let mut active = user.clone().into_active_model();
+    active.password = ActiveValue::Set(
+        Password::new_hash(&body.new_password, settings.password_hash())
             .map_err(|_| Error::BadRequest("Unable to Hash Password"))?,
     );
-    user.save(database.as_ref()).await?;
+    active.save(database.as_ref()).await?;
+    notify_panel_user(
+        &email,
+        &user,
+        PasswordChangedEmail {
+            name: user.name.clone(),
+            username: user.username.clone(),
+        },
+    );
     Ok(HttpResponse::NoContent().finish())
 }
 #[derive(serde::Deserialize)]
 pub struct BackupEmail {
     pub backup_email: Option<EmailAddress>,
 }
+/// Sends a confirmation link to the new backup email. It is saved once the link is opened.
+///
+/// Removing the backup email does not need to be confirmed
 #[put("/backup-email")]
 pub async fn backup_email(
     auth: Authentication,
     body: actix_web::web::Form<BackupEmail>,
     database: DatabaseConnection,
+    verifications: Data<BackupEmailVerifications>,
+    origin: Origin,
 ) -> crate::Result<HttpResponse> {
+    let user: PanelUser = auth.into();
+    match body.into_inner().backup_email {
+        Some(backup_email) if backup_email.is_catch_all() => Err(crate::Error::BadRequest(
+            "A catch-all can not be a backup email",
+        )),
+        Some(backup_email) => {
+            verifications.request(user.username, user.id, backup_email, origin, false);
+            Ok(HttpResponse::Accepted().finish())
+        }
+        None => {
+            let mut user = user.into_active_model();
+            user.backup_email = ActiveValue::Set(None);
+            user.backup_email_verified = ActiveValue::Set(None);
+            user.save(database.as_ref()).await?;
+            Ok(HttpResponse::NoContent().finish())
+        }
+    }
+}
+#[derive(serde::Deserialize)]
+pub struct ChangeName {
+    pub name: String,
+}
+#[put("/name")]
+pub async fn change_name(
+    auth: Authentication,
+    body: Json<ChangeName>,
+    database: DatabaseConnection,
+) -> crate::Result<HttpResponse> {
+    let name = body.into_inner().name.trim().to_owned();
+    if name.is_empty() {
+        return Err(Error::BadRequest("The name can not be empty"));
+    }
     let user: PanelUser = auth.into();
     let mut user = user.into_active_model();
-    user.backup_email = ActiveValue::Set(body.backup_email.clone());
+    user.name = ActiveValue::Set(name);
     user.save(database.as_ref()).await?;
     Ok(HttpResponse::NoContent().finish())
 }
+#[get("/quota")]
+pub async fn my_quota(
+    auth: Authentication,
+    database: DatabaseConnection,
+    quota_usage: Data<QuotaUsage>,
+) -> crate::Result<HttpResponse> {
+    let user: PanelUser = auth.into();
+    let account = AccountEntity::find_by_id(user.id)
+        .one(database.as_ref())
+        .await?
+        .ok_or(Error::NotFound)?;
+    let used = quota_usage.get(&account.username).await;
+    Ok(HttpResponse::Ok().json(json!({
+        "quota": account.quota,
+        "quota_used": used,
+    })))
+}
+
+/// Every address of the account
+#[get("/aliases")]
+pub async fn my_aliases(
+    auth: Authentication,
+    database: DatabaseConnection,
+) -> crate::Result<HttpResponse> {
+    let user: PanelUser = auth.into();
+    let emails = Emails::get_by_user_id(database.as_ref(), user.id).await?;
+    Ok(HttpResponse::Ok().json(emails))
+}
+#[derive(serde::Deserialize)]
+pub struct NewAlias {
+    pub email_address: EmailAddress,
+}
+/// Adds an alias on a domain the account already has an address on.
+///
+/// Limited by [GroupModel::alias_limit](entities::GroupModel::alias_limit)
+#[put("/aliases")]
+pub async fn add_alias(
+    auth: Authentication,
+    body: Json<NewAlias>,
+    database: DatabaseConnection,
+    email: Data<EmailAccess>,
+) -> crate::Result<HttpResponse> {
+    let user: PanelUser = auth.into();
+    let email_address = body.into_inner().email_address;
+    let group = GroupEntity::find_by_id(user.group_id)
+        .one(database.as_ref())
+        .await?
+        .ok_or(Error::NotFound)?;
+    if group.alias_limit <= 0 {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    if email_address.is_catch_all() {
+        return Err(Error::BadRequest("A catch-all can not be an alias"));
+    }
+    let emails = Emails::get_by_user_id(database.as_ref(), user.id).await?;
+    if emails.get_aliases().len() >= group.alias_limit as usize {
+        return Err(Error::BadRequest(
+            "You have reached the alias limit of your group",
+        ));
+    }
+    if !emails.iter().any(|email| {
+        email
+            .email_address
+            .domain()
+            .eq_ignore_ascii_case(email_address.domain())
+    }) {
+        return Err(Error::BadRequest(
+            "Aliases must be on a domain you already have an address on",
+        ));
+    }
+    let taken = EmailEntity::find()
+        .filter(EmailColumn::EmailAddress.eq(email_address.clone()))
+        .count(database.as_ref())
+        .await?;
+    if taken > 0 {
+        return Err(Error::Conflict("The address is already in use"));
+    }
+    let alias = EmailActiveModel {
+        id: ActiveValue::NotSet,
+        account: ActiveValue::Set(user.id),
+        email_address: ActiveValue::Set(email_address),
+        email_type: ActiveValue::Set(EmailType::Alias),
+        created: entities::now(),
+    }
+    .insert(database.as_ref())
+    .await?;
+    notify_panel_user(
+        &email,
+        &user,
+        AliasAddedEmail {
+            name: user.name.clone(),
+            username: user.username.clone(),
+            alias: alias.email_address.clone(),
+        },
+    );
+    Ok(HttpResponse::Ok().json(alias))
+}
+#[delete("/aliases/{email_id}")]
+pub async fn remove_alias(
+    auth: Authentication,
+    email_id: Path<i64>,
+    database: DatabaseConnection,
+) -> crate::Result<HttpResponse> {
+    let user: PanelUser = auth.into();
+    let result = EmailEntity::delete_many()
+        .filter(
+            EmailColumn::Id
+                .eq(email_id.into_inner())
+                .and(EmailColumn::Account.eq(user.id))
+                .and(EmailColumn::EmailType.eq(EmailType::Alias)),
+        )
+        .exec(database.as_ref())
+        .await?;
+    if result.rows_affected == 0 {
+        Err(Error::NotFound)
+    } else {
+        Ok(HttpResponse::NoContent().finish())
+    }
+}
+
+#[get("/forwarding")]
+pub async fn my_forwarding(
+    auth: Authentication,
+    database: DatabaseConnection,
+) -> crate::Result<HttpResponse> {
+    let user: PanelUser = auth.into();
+    match get_forwarding(database.as_ref(), user.id).await? {
+        Some(forwarding) => Ok(HttpResponse::Ok().json(forwarding)),
+        None => Err(Error::NotFound),
+    }
+}
+#[put("/forwarding")]
+pub async fn set_my_forwarding(
+    auth: Authentication,
+    body: Json<NewForwarding>,
+    database: DatabaseConnection,
+    sieve: Data<SieveScripts>,
+) -> crate::Result<HttpResponse> {
+    body.validate().map_err(Error::BadRequest)?;
+    let user: PanelUser = auth.into();
+    let transaction = database.begin().await?;
+    let forwarding = set_forwarding(&transaction, user.id, body.into_inner()).await?;
+    sieve
+        .update_account(&transaction, user.id, &user.username)
+        .await?;
+    transaction.commit().await?;
+    Ok(HttpResponse::Ok().json(forwarding))
+}
+#[delete("/forwarding")]
+pub async fn remove_my_forwarding(
+    auth: Authentication,
+    database: DatabaseConnection,
+    sieve: Data<SieveScripts>,
+) -> crate::Result<HttpResponse> {
+    let user: PanelUser = auth.into();
+    let transaction = database.begin().await?;
+    if !remove_forwarding(&transaction, user.id).await? {
+        return Err(Error::NotFound);
+    }
+    sieve
+        .update_account(&transaction, user.id, &user.username)
+        .await?;
+    transaction.commit().await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[get("/vacation")]
+pub async fn my_vacation(
+    auth: Authentication,
+    database: DatabaseConnection,
+) -> crate::Result<HttpResponse> {
+    let user: PanelUser = auth.into();
+    match get_vacation(database.as_ref(), user.id).await? {
+        Some(vacation) => Ok(HttpResponse::Ok().json(vacation)),
+        None => Err(Error::NotFound),
+    }
+}
+#[put("/vacation")]
+pub async fn set_my_vacation(
+    auth: Authentication,
+    body: Json<NewVacation>,
+    database: DatabaseConnection,
+    sieve: Data<SieveScripts>,
+) -> crate::Result<HttpResponse> {
+    body.validate().map_err(Error::BadRequest)?;
+    let user: PanelUser = auth.into();
+    let transaction = database.begin().await?;
+    let vacation = set_vacation(&transaction, user.id, body.into_inner()).await?;
+    sieve
+        .update_account(&transaction, user.id, &user.username)
+        .await?;
+    transaction.commit().await?;
+    Ok(HttpResponse::Ok().json(vacation))
+}
+#[delete("/vacation")]
+pub async fn remove_my_vacation(
+    auth: Authentication,
+    database: DatabaseConnection,
+    sieve: Data<SieveScripts>,
+) -> crate::Result<HttpResponse> {
+    let user: PanelUser = auth.into();
+    let transaction = database.begin().await?;
+    if !remove_vacation(&transaction, user.id).await? {
+        return Err(Error::NotFound);
+    }
+    sieve
+        .update_account(&transaction, user.id, &user.username)
+        .await?;
+    transaction.commit().await?;
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,245 @@
+use std::sync::Arc;
+
+use chrono::{Duration, Local};
+use entities::{AccountEntity, AccountModel};
+use sea_orm::{ActiveValue, ConnectionTrait, DbErr, EntityTrait, IntoActiveModel};
+use serde::Serialize;
+use tracing::{debug, warn};
+use utils::database::EmailAddress;
+
+use crate::{
+    auth::{
+        password_reset::PasswordResetManager,
+        token_store::{TokenRequest, TokenStore},
+    },
+    email_service::{template, Email, EmailAccess, EmailDebug},
+    headers::Origin,
+};
+
+#[derive(Debug, Serialize)]
+pub struct BackupEmailConfirmEmail<'a> {
+    pub token: &'a str,
+    pub panel_url: String,
+    pub username: String,
+}
+
+impl Email for BackupEmailConfirmEmail<'_> {
+    template!("backup_email_confirm");
+
+    fn subject() -> &'static str {
+        "Confirm your backup email"
+    }
+
+    fn expires_on_restart() -> bool {
+        true
+    }
+
+    fn debug_info(self) -> EmailDebug {
+        EmailDebug {
+            to: self.username,
+            subject: Self::subject(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BackupEmailRequest {
+    pub backup_email: EmailAddress,
+    /// A password reset link is sent once the address is confirmed
+    pub send_password_reset: bool,
+}
+/// Backup email changes waiting for the new address to be confirmed
+#[derive(Debug)]
+pub struct BackupEmailVerifications {
+    pub email_access: Arc<EmailAccess>,
+    pub tokens: TokenStore<BackupEmailRequest>,
+}
+
+impl BackupEmailVerifications {
+    /// How long a confirmation link works for
+    pub fn lifespan() -> Duration {
+        Duration::days(1)
+    }
+    pub fn new(email_access: Arc<EmailAccess>) -> Self {
+        Self {
+            email_access,
+            tokens: TokenStore::new(Self::lifespan()),
+        }
+    }
+    /// Sends a confirmation link to the new address.
+    ///
+    /// Replaces any change the account was already waiting on.
+    /// Reset links are only sent to confirmed addresses. So `send_password_reset` holds one until the link is opened
+    pub fn request(
+        &self,
+        username: String,
+        id: i64,
+        backup_email: EmailAddress,
+        panel_url: Origin,
+        send_password_reset: bool,
+    ) {
+        let token = self.tokens.insert(
+            id,
+            BackupEmailRequest {
+                backup_email: backup_email.clone(),
+                send_password_reset,
+            },
+        );
+        self.email_access.send_one_fn(
+            backup_email,
+            BackupEmailConfirmEmail {
+                token: &token,
+                panel_url: panel_url.into(),
+                username,
+            },
+        );
+    }
+    /// Removes the request. None if the token is unknown or expired
+    pub fn take_request(&self, token: impl AsRef<str>) -> Option<TokenRequest<BackupEmailRequest>> {
+        self.tokens.take(token)
+    }
+    /// Saves the backup email as verified and sends the password reset that was waiting on it.
+    ///
+    /// None if the token is unknown or expired or the account no longer exists
+    pub async fn confirm(
+        &self,
+        database: &impl ConnectionTrait,
+        password_reset: &PasswordResetManager,
+        token: impl AsRef<str>,
+        origin: Origin,
+    ) -> Result<Option<AccountModel>, DbErr> {
+        let Some(TokenRequest {
+            account_id,
+            request,
+            ..
+        }) = self.take_request(token)
+        else {
+            return Ok(None);
+        };
+        let Some(account) = AccountEntity::find_by_id(account_id).one(database).await? else {
+            warn!("Failed to find account with id {}", account_id);
+            return Ok(None);
+        };
+        let mut account = account.into_active_model();
+        account.backup_email = ActiveValue::set(Some(request.backup_email.clone()));
+        account.backup_email_verified = ActiveValue::set(Some(Local::now().into()));
+        let account = AccountEntity::update(account).exec(database).await?;
+        if request.send_password_reset {
+            debug!("Sending the password reset waiting on {}", account.username);
+            password_reset.request(
+                account.username.clone(),
+                account.id,
+                request.backup_email,
+                origin,
+                account.require_password_change,
+            );
+        }
+        Ok(Some(account))
+    }
+}
+#[cfg(test)]
+mod tests {
+    use entities::{groups::GroupPermissions, AccountEntity};
+    use sea_orm::EntityTrait;
+
+    use super::BackupEmailVerifications;
+    use crate::{
+        auth::password_reset::PasswordResetManager,
+        test_support::{
+            add_account, add_group, address, database, origin, unfold_quoted_printable, TestEmail,
+        },
+    };
+
+    #[actix_rt::test]
+    pub async fn test_password_reset_waits_for_confirmation() {
+        let database = database().await;
+        let group = add_group(&database, "user", GroupPermissions::default()).await;
+        let account = add_account(&database, "alice", group.id).await;
+        let email = TestEmail::start().await;
+        let verifications = BackupEmailVerifications::new(email.access.clone());
+        let password_reset = PasswordResetManager::new(email.access.clone());
+
+        verifications.request(
+            account.username.clone(),
+            account.id,
+            address("alice@backup.example.com"),
+            origin(),
+            true,
+        );
+        let confirmation = email.take(1).await;
+        assert_eq!(confirmation.len(), 1);
+        assert_eq!(
+            confirmation[0].to(),
+            vec!["alice@backup.example.com".to_owned()]
+        );
+        assert_eq!(
+            confirmation[0].header("Subject").as_deref(),
+            Some("Confirm your backup email")
+        );
+        email.assert_none_sent().await;
+        assert!(password_reset.tokens.tokens_for(account.id).is_empty());
+
+        let token = verifications.tokens.tokens_for(account.id).pop().unwrap();
+        assert!(
+            unfold_quoted_printable(&confirmation[0].message()).contains(&format!(
+                "https://panel.example.com/confirm-backup-email?token={}",
+                token
+            ))
+        );
+        let confirmed = verifications
+            .confirm(&database, &password_reset, &token, origin())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(confirmed.backup_email_verified.is_some());
+        let reset = email.take(1).await;
+        assert_eq!(reset.len(), 1);
+        assert_eq!(reset[0].to(), vec!["alice@backup.example.com".to_owned()]);
+        assert_eq!(
+            reset[0].header("Subject").as_deref(),
+            Some("Password Reset")
+        );
+        assert_eq!(password_reset.tokens.tokens_for(account.id).len(), 1);
+
+        let stored = AccountEntity::find_by_id(account.id)
+            .one(&database)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            stored.backup_email,
+            Some(address("alice@backup.example.com"))
+        );
+    }
+    #[actix_rt::test]
+    pub async fn test_confirm_without_reset() {
+        let database = database().await;
+        let group = add_group(&database, "user", GroupPermissions::default()).await;
+        let account = add_account(&database, "bob", group.id).await;
+        let email = TestEmail::start().await;
+        let verifications = BackupEmailVerifications::new(email.access.clone());
+        let password_reset = PasswordResetManager::new(email.access.clone());
+
+        verifications.request(
+            account.username.clone(),
+            account.id,
+            address("bob@backup.example.com"),
+            origin(),
+            false,
+        );
+        assert_eq!(email.take(1).await.len(), 1);
+        let token = verifications.tokens.tokens_for(account.id).pop().unwrap();
+        assert!(verifications
+            .confirm(&database, &password_reset, &token, origin())
+            .await
+            .unwrap()
+            .is_some());
+        email.assert_none_sent().await;
+        // The token only works once
+        assert!(verifications
+            .confirm(&database, &password_reset, &token, origin())
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
@@ -1,9 +1,11 @@
 //pub mod middleware;
 
+pub mod backup_email;
 pub mod middleware;
 pub mod password_reset;
 pub mod permissions;
 pub mod session;
+pub mod token_store;
 
 use actix_web::{dev::Payload, FromRequest, HttpMessage, HttpRequest};
 use entities::account::panel_user::PanelUser;
@@ -35,7 +37,14 @@ impl Into<PanelUser> for Authentication {
     }
 }
 
-impl Authentication {}
+impl Authentication {
+    /// The id of the account that is logged in
+    pub fn user_id(&self) -> i64 {
+        match self {
+            Authentication::Session { user, .. } => user.id,
+        }
+    }
+}
 impl Permissions for Authentication {
     fn can_manage_users(&self) -> bool {
         match self {
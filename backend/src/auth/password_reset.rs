@@ -1,14 +1,12 @@
-use std::{ops::DerefMut, sync::Arc};
+use std::sync::Arc;
 
-use ahash::HashMap;
-use chrono::{DateTime, Local};
-use parking_lot::{Mutex, MutexGuard};
-use rand::distributions::Distribution;
+use chrono::Duration;
 use serde::Serialize;
 use tracing::debug;
 use utils::database::EmailAddress;
 
 use crate::{
+    auth::token_store::{TokenRequest, TokenStore},
     email_service::{template, Email, EmailAccess, EmailDebug},
     headers::Origin,
 };
@@ -16,7 +14,7 @@ use crate::{
 #[derive(Debug, Serialize)]
 pub struct PasswordResetEmail<'a> {
     pub token: &'a str,
-    pub panel_origin: Origin,
+    pub panel_url: String,
     pub username: String,
     pub required: bool,
 }
@@ -28,6 +26,10 @@ impl Email for PasswordResetEmail<'_> {
         "Password Reset"
     }
 
+    fn expires_on_restart() -> bool {
+        true
+    }
+
     fn debug_info(self) -> EmailDebug {
         EmailDebug {
             to: self.username,
@@ -36,67 +38,79 @@ impl Email for PasswordResetEmail<'_> {
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
-pub struct PasswordResetRequest {
-    pub account_id: i64,
-    pub token: String,
-    pub created: DateTime<Local>,
-}
-
 #[derive(Debug)]
 pub struct PasswordResetManager {
     pub email_access: Arc<EmailAccess>,
-    pub requests: Mutex<HashMap<String, PasswordResetRequest>>,
+    pub tokens: TokenStore<()>,
 }
 
 impl PasswordResetManager {
+    /// How long a reset link works for
+    pub fn lifespan() -> Duration {
+        Duration::days(1)
+    }
+    pub fn new(email_access: Arc<EmailAccess>) -> Self {
+        Self {
+            email_access,
+            tokens: TokenStore::new(Self::lifespan()),
+        }
+    }
+    /// Sends a reset link. Replaces any link the account was already sent
     pub fn request(
         &self,
         username: String,
         id: i64,
         email: EmailAddress,
-        panel_origin: Origin,
+        panel_url: Origin,
         required: bool,
     ) {
-        let token = self.generate_token();
+        let token = self.tokens.insert(id, ());
+        debug!("Sending password reset email for {}", username);
         self.email_access.send_one_fn(
             email,
             PasswordResetEmail {
                 token: &token,
-                panel_origin,
+                panel_url: panel_url.into(),
                 username,
                 required,
             },
         );
-
-        let mut guard = self.requests.lock();
-        let request = PasswordResetRequest {
-            account_id: id,
-            token,
-            created: Local::now(),
-        };
-        guard.insert(request.token.clone(), request);
-
-        debug!("{:?}", guard);
     }
-
-    pub fn get_request(
-        &self,
-        token: impl AsRef<str>,
-    ) -> Option<impl DerefMut<Target = PasswordResetRequest> + '_> {
-        MutexGuard::try_map(self.requests.lock(), |r| r.get_mut(token.as_ref())).ok()
+    pub fn is_valid(&self, token: impl AsRef<str>) -> bool {
+        self.tokens.is_valid(token)
     }
-    pub fn remove_request(&self, request: impl AsRef<str>) {
-        let mut guard = self.requests.lock();
-        guard.remove(request.as_ref());
+    /// Removes the request. None if the token is unknown or expired
+    pub fn take_request(&self, token: impl AsRef<str>) -> Option<TokenRequest<()>> {
+        self.tokens.take(token)
     }
+}
 
-    fn generate_token(&self) -> String {
-        let mut rng = rand::rngs::OsRng::default();
-        rand::distributions::Alphanumeric
-            .sample_iter(&mut rng)
-            .take(36)
-            .map(char::from)
-            .collect()
+#[cfg(test)]
+mod tests {
+    use super::PasswordResetManager;
+    use crate::test_support::{address, origin, unfold_quoted_printable, TestEmail};
+
+    #[actix_rt::test]
+    pub async fn test_request_sends_email() {
+        let email = TestEmail::start().await;
+        let manager = PasswordResetManager::new(email.access.clone());
+        manager.request(
+            "alice".to_owned(),
+            1,
+            address("alice@example.com"),
+            origin(),
+            false,
+        );
+        let token = manager.tokens.tokens_for(1).pop().unwrap();
+
+        let emails = email.take(1).await;
+        assert_eq!(emails.len(), 1);
+        assert_eq!(emails[0].to(), vec!["alice@example.com".to_owned()]);
+        assert_eq!(
+            emails[0].header("Subject").as_deref(),
+            Some("Password Reset")
+        );
+        let link = format!("https://panel.example.com/reset-password?token={}", token);
+        assert!(unfold_quoted_printable(&emails[0].message()).contains(&link));
     }
 }
@@ -4,6 +4,7 @@ use std::sync::{
 };
 
 use chrono::{DateTime, Duration, Local, NaiveDateTime, Utc};
+use parking_lot::RwLock;
 use rand::{distributions::Alphanumeric, rngs::StdRng, Rng, SeedableRng};
 use redb::{CommitError, Database, Error, ReadableTable, TableDefinition};
 use serde::Serialize;
@@ -65,9 +66,12 @@ impl Session {
     }
 }
 const TABLE: TableDefinition<&str, SessionTuple> = TableDefinition::new("sessions");
+/// `{user_id}/{ip}` to the last time the user logged in from the IP
+const KNOWN_IPS: TableDefinition<&str, i64> = TableDefinition::new("known_ips");
 
 pub struct SessionManager {
-    config: SessionConfig,
+    /// Replaced when the config is reloaded. The database location is only read on startup
+    config: RwLock<SessionConfig>,
     sessions: Database,
     running: AtomicBool,
 }
@@ -88,12 +92,19 @@ impl SessionManager {
         };
 
         Ok(Self {
-            config: session_config,
+            config: RwLock::new(session_config),
             sessions,
             running: AtomicBool::new(false),
         })
     }
 
+    /// How long new sessions last
+    pub fn lifespan(&self) -> Duration {
+        self.config.read().lifespan
+    }
+    pub fn reload(&self, config: SessionConfig) {
+        *self.config.write() = config;
+    }
     pub async fn clean_inner(&self) -> Result<u32, SessionError> {
         let mut sessions_removed = 0u32;
         let sessions = self.sessions.begin_write()?;
@@ -121,14 +132,17 @@ impl SessionManager {
         Ok(sessions_removed)
     }
     pub fn start_cleaner(this: Arc<Self>) {
+        this.running.store(true, Ordering::Relaxed);
         actix_rt::spawn(async move {
             let this = this;
-            let how_often = this
-                .config
-                .cleanup_interval
-                .to_std()
-                .expect("Duration is too large");
             while this.running.load(Ordering::Relaxed) {
+                // Read every time so a reload changes it
+                let how_often = this
+                    .config
+                    .read()
+                    .cleanup_interval
+                    .to_std()
+                    .expect("Duration is too large");
                 info!("Cleaning sessions");
                 match this.clean_inner().await {
                     Ok(value) => {
@@ -181,6 +195,24 @@ impl SessionManager {
         sessions.commit()?;
         Ok(session)
     }
+
+    /// Saves the IP as one the user has logged in from.
+    ///
+    /// Returns true if the user has logged in before but never from this IP
+    pub fn remember_ip(&self, user_id: i64, ip: &str) -> Result<bool, SessionError> {
+        let sessions = self.sessions.begin_write()?;
+        let mut table = sessions.open_table(KNOWN_IPS)?;
+        let key = format!("{}/{}", user_id, ip);
+        // `0` comes right after `/` so this covers every key of the user
+        let (start, end) = (format!("{}/", user_id), format!("{}0", user_id));
+        let logged_in_before = table.range(start.as_str()..end.as_str())?.next().is_some();
+        let known = table
+            .insert(key.as_str(), Local::now().timestamp_millis())?
+            .is_some();
+        drop(table);
+        sessions.commit()?;
+        Ok(logged_in_before && !known)
+    }
 }
 
 #[inline(always)]
@@ -0,0 +1,118 @@
+//! Requests that are confirmed by opening a link with a random token. Such as password resets
+use ahash::HashMap;
+use chrono::{DateTime, Duration, Local};
+use parking_lot::Mutex;
+use rand::distributions::Distribution;
+use tracing::debug;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TokenRequest<T> {
+    pub account_id: i64,
+    pub created: DateTime<Local>,
+    pub request: T,
+}
+/// Holds at most one token per account. The tokens only live in memory
+#[derive(Debug)]
+pub struct TokenStore<T> {
+    lifespan: Duration,
+    requests: Mutex<HashMap<String, TokenRequest<T>>>,
+}
+impl<T> TokenStore<T> {
+    pub fn new(lifespan: Duration) -> Self {
+        Self {
+            lifespan,
+            requests: Default::default(),
+        }
+    }
+    /// How long a token works for
+    pub fn lifespan(&self) -> Duration {
+        self.lifespan
+    }
+    /// Creates a token for the request. Replaces the token the account was already waiting on
+    pub fn insert(&self, account_id: i64, request: T) -> String {
+        let token = generate_token();
+        let mut guard = self.requests.lock();
+        guard.retain(|_, request| request.account_id != account_id);
+        guard.insert(
+            token.clone(),
+            TokenRequest {
+                account_id,
+                created: Local::now(),
+                request,
+            },
+        );
+        debug!("{} tokens are waiting", guard.len());
+        token
+    }
+    /// If the token exists and has not expired
+    pub fn is_valid(&self, token: impl AsRef<str>) -> bool {
+        self.requests
+            .lock()
+            .get(token.as_ref())
+            .is_some_and(|request| !self.is_expired(request))
+    }
+    /// Removes the request. None if the token is unknown or expired
+    pub fn take(&self, token: impl AsRef<str>) -> Option<TokenRequest<T>> {
+        let request = self.requests.lock().remove(token.as_ref())?;
+        if self.is_expired(&request) {
+            debug!("Token for {} expired", request.account_id);
+            return None;
+        }
+        Some(request)
+    }
+    /// The tokens waiting for an account
+    #[cfg(test)]
+    pub fn tokens_for(&self, account_id: i64) -> Vec<String> {
+        self.requests
+            .lock()
+            .iter()
+            .filter(|(_, request)| request.account_id == account_id)
+            .map(|(token, _)| token.clone())
+            .collect()
+    }
+    fn is_expired(&self, request: &TokenRequest<T>) -> bool {
+        Local::now() - request.created > self.lifespan
+    }
+}
+fn generate_token() -> String {
+    let mut rng = rand::rngs::OsRng::default();
+    rand::distributions::Alphanumeric
+        .sample_iter(&mut rng)
+        .take(36)
+        .map(char::from)
+        .collect()
+}
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::TokenStore;
+
+    #[test]
+    pub fn test_take_once() {
+        let store = TokenStore::new(Duration::days(1));
+        let token = store.insert(1, "first");
+        assert!(store.is_valid(&token));
+        let request = store.take(&token).unwrap();
+        assert_eq!((request.account_id, request.request), (1, "first"));
+        assert!(!store.is_valid(&token));
+        assert!(store.take(&token).is_none());
+    }
+    #[test]
+    pub fn test_replaces_token_of_account() {
+        let store = TokenStore::new(Duration::days(1));
+        let first = store.insert(1, "first");
+        let other = store.insert(2, "other");
+        let second = store.insert(1, "second");
+        assert!(!store.is_valid(&first));
+        assert!(store.is_valid(&other));
+        assert_eq!(store.take(&second).unwrap().request, "second");
+    }
+    #[test]
+    pub fn test_expired() {
+        let store = TokenStore::new(Duration::zero() - Duration::seconds(1));
+        let token = store.insert(1, ());
+        assert!(!store.is_valid(&token));
+        assert!(store.take(&token).is_none());
+    }
+}
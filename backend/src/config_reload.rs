@@ -0,0 +1,355 @@
+//! Applies changes to the config file without a restart.
+//!
+//! Triggered by `SIGHUP` or `POST /api/config/reload`.
+//! Settings that are only read on startup, such as the bind address, are reported as needing a restart
+use std::path::PathBuf;
+
+use actix_web::web::Data;
+use parking_lot::Mutex;
+use serde::Serialize;
+use thiserror::Error;
+use tracing::{error, info, warn};
+use utils::{config::Settings, config_loader::ConfigErrors};
+
+use crate::{
+    auth::session::SessionManager,
+    email_service::{EmailAccess, EmailReload},
+    PanelSettings, SharedConfig,
+};
+
+#[derive(Debug, Error)]
+pub enum ReloadError {
+    #[error(transparent)]
+    Config(#[from] ConfigErrors),
+    #[error("Unable to apply the email settings: {0}")]
+    Email(#[from] std::io::Error),
+}
+/// What a reload changed
+#[derive(Debug, Default, Serialize)]
+pub struct ReloadReport {
+    /// Settings that are now in use
+    pub reloaded: Vec<&'static str>,
+    /// Settings that changed but are only read on startup
+    pub requires_restart: Vec<&'static str>,
+}
+
+pub struct ConfigReloader {
+    config: PathBuf,
+    /// The settings in use. Settings that need a restart keep their startup value
+    current: Mutex<Settings>,
+    shared_config: Data<SharedConfig>,
+    email: Data<EmailAccess>,
+    session_manager: Data<SessionManager>,
+}
+impl ConfigReloader {
+    pub fn new(
+        config: PathBuf,
+        current: Settings,
+        shared_config: Data<SharedConfig>,
+        email: Data<EmailAccess>,
+        session_manager: Data<SessionManager>,
+    ) -> Self {
+        Self {
+            config,
+            current: Mutex::new(current),
+            shared_config,
+            email,
+            session_manager,
+        }
+    }
+    /// Reads the config file again and applies what changed.
+    ///
+    /// Nothing is applied if the config is invalid or the new email settings can not be used
+    pub fn reload(&self) -> Result<ReloadReport, ReloadError> {
+        let new = Settings::load(&self.config)?;
+        let mut current = self.current.lock();
+        let mut applied = current.clone();
+        let mut report = ReloadReport::default();
+        // Takes every field so a new setting has to be handled here
+        let Settings {
+            bind_address,
+            number_of_workers,
+            database,
+            tls,
+            mut email,
+            postmaster_address,
+            default_group,
+            root_group,
+            password_hash_for_new_passwords,
+            require_password_reset,
+            mut session_manager,
+            mut quota,
+            sieve,
+            is_https,
+            trusted_proxies,
+        } = new;
+
+        needs_restart(
+            "email.queue",
+            &current.email.queue,
+            &email.queue,
+            &mut report,
+        );
+        email.queue = current.email.queue.clone();
+        let email_changed = update("email", &mut applied.email, email, &mut report);
+        needs_restart(
+            "session_manager.database_location",
+            &current.session_manager.database_location,
+            &session_manager.database_location,
+            &mut report,
+        );
+        session_manager.database_location = current.session_manager.database_location.clone();
+        let session_changed = update(
+            "session_manager",
+            &mut applied.session_manager,
+            session_manager,
+            &mut report,
+        );
+        let panel_changed = [
+            update(
+                "password_hash_for_new_passwords",
+                &mut applied.password_hash_for_new_passwords,
+                password_hash_for_new_passwords,
+                &mut report,
+            ),
+            update("is_https", &mut applied.is_https, is_https, &mut report),
+            update(
+                "default_group",
+                &mut applied.default_group,
+                default_group,
+                &mut report,
+            ),
+            update(
+                "trusted_proxies",
+                &mut applied.trusted_proxies,
+                trusted_proxies,
+                &mut report,
+            ),
+            update(
+                "quota.domain_allocations",
+                &mut applied.quota.domain_allocations,
+                std::mem::take(&mut quota.domain_allocations),
+                &mut report,
+            ),
+        ]
+        .contains(&true);
+        // Nothing in the panel reads these yet. So a change is not reported as reloaded
+        applied.postmaster_address = postmaster_address;
+        applied.root_group = root_group;
+        applied.require_password_reset = require_password_reset;
+
+        quota.domain_allocations = current.quota.domain_allocations.clone();
+        needs_restart(
+            "bind_address",
+            &current.bind_address,
+            &bind_address,
+            &mut report,
+        );
+        needs_restart(
+            "number_of_workers",
+            &current.number_of_workers,
+            &number_of_workers,
+            &mut report,
+        );
+        needs_restart("database", &current.database, &database, &mut report);
+        needs_restart("tls", &current.tls, &tls, &mut report);
+        needs_restart("quota", &current.quota, &quota, &mut report);
+        needs_restart("sieve", &current.sieve, &sieve, &mut report);
+
+        // Built before anything is replaced so a bad email setting changes nothing
+        let email = if email_changed {
+            Some(EmailReload::new(&applied.email)?)
+        } else {
+            None
+        };
+        if panel_changed {
+            self.shared_config.replace(PanelSettings::new(&applied));
+        }
+        if session_changed {
+            self.session_manager.reload(applied.session_manager.clone());
+        }
+        if let Some(email) = email {
+            self.email.reload(email);
+        }
+        *current = applied;
+        Ok(report)
+    }
+    /// Reloads and logs the outcome
+    pub fn reload_and_log(&self) {
+        match self.reload() {
+            Ok(report) => {
+                info!("Reloaded the config. Changed: {:?}", report.reloaded);
+                for setting in report.requires_restart {
+                    warn!("`{}` changed. Restart the panel to apply it", setting);
+                }
+            }
+            Err(ReloadError::Config(errors)) => {
+                error!("The config was not reloaded. {}", errors);
+            }
+            Err(error) => error!("The config was not reloaded. {}", error),
+        }
+    }
+    /// Reloads the config every time the process receives `SIGHUP`
+    #[cfg(unix)]
+    pub fn reload_on_sighup(this: Data<Self>) {
+        use tokio::signal::unix::{signal, SignalKind};
+        actix_rt::spawn(async move {
+            let mut hangup = match signal(SignalKind::hangup()) {
+                Ok(ok) => ok,
+                Err(error) => {
+                    error!("Unable to listen for SIGHUP: {}", error);
+                    return;
+                }
+            };
+            while hangup.recv().await.is_some() {
+                info!("SIGHUP received. Reloading the config");
+                this.reload_and_log();
+            }
+        });
+    }
+    #[cfg(not(unix))]
+    pub fn reload_on_sighup(_: Data<Self>) {}
+}
+/// Replaces `current` with `new` if it changed
+fn update<T: Serialize>(
+    setting: &'static str,
+    current: &mut T,
+    new: T,
+    report: &mut ReloadReport,
+) -> bool {
+    if !changed(current, &new) {
+        return false;
+    }
+    *current = new;
+    report.reloaded.push(setting);
+    true
+}
+fn needs_restart<T: Serialize>(
+    setting: &'static str,
+    current: &T,
+    new: &T,
+    report: &mut ReloadReport,
+) {
+    if changed(current, new) {
+        report.requires_restart.push(setting);
+    }
+}
+/// The settings do not implement PartialEq. So they are compared as JSON
+fn changed<T: Serialize>(current: &T, new: &T) -> bool {
+    serde_json::to_value(current).ok() != serde_json::to_value(new).ok()
+}
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use actix_web::web::Data;
+    use utils::config::{EmailTransport, Settings};
+
+    use super::{ConfigReloader, ReloadError};
+    use crate::{
+        auth::session::SessionManager,
+        test_support::{settings, TestEmail},
+        SharedConfig,
+    };
+
+    /// The config file and the session database. Removed on drop
+    struct TestFiles {
+        config: PathBuf,
+        sessions: PathBuf,
+    }
+    impl TestFiles {
+        fn new(name: &str) -> Self {
+            let file = |kind: &str| {
+                std::env::temp_dir().join(format!(
+                    "stalwart-panel-reload-{}-{}-{}",
+                    std::process::id(),
+                    name,
+                    kind
+                ))
+            };
+            Self {
+                config: file("config.toml"),
+                sessions: file("sessions.redb"),
+            }
+        }
+        fn settings(&self) -> Settings {
+            let mut settings = settings();
+            settings.email.transport = EmailTransport::File {
+                directory: std::env::temp_dir(),
+            };
+            settings.session_manager.database_location = self.sessions.clone();
+            settings
+        }
+        fn write(&self, settings: &Settings) {
+            std::fs::write(&self.config, toml::to_string(settings).unwrap()).unwrap();
+        }
+    }
+    impl Drop for TestFiles {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.config);
+            let _ = std::fs::remove_file(&self.sessions);
+        }
+    }
+    async fn reloader(files: &TestFiles, email: &TestEmail) -> ConfigReloader {
+        let current = files.settings();
+        files.write(&current);
+        let session_manager = SessionManager::new(current.session_manager.clone()).unwrap();
+        ConfigReloader::new(
+            files.config.clone(),
+            current.clone(),
+            Data::new(SharedConfig::new(&current)),
+            Data::from(email.access.clone()),
+            Data::new(session_manager),
+        )
+    }
+
+    #[actix_rt::test]
+    pub async fn test_reload_report() {
+        let files = TestFiles::new("report");
+        let email = TestEmail::start().await;
+        let reloader = reloader(&files, &email).await;
+
+        let report = reloader.reload().unwrap();
+        assert!(report.reloaded.is_empty());
+        assert!(report.requires_restart.is_empty());
+
+        let mut changed = files.settings();
+        changed.postmaster_address = "admin@example.com".to_owned();
+        changed.root_group = 5;
+        changed.require_password_reset.how_often_to_force_reset = chrono::Duration::days(90);
+        changed.default_group = 3;
+        changed.trusted_proxies = vec!["10.0.0.1".parse().unwrap()];
+        changed.bind_address = "127.0.0.1:8080".to_owned();
+        changed.email.queue.max_attempts = 10;
+        files.write(&changed);
+
+        let report = reloader.reload().unwrap();
+        assert_eq!(report.reloaded, vec!["default_group", "trusted_proxies"]);
+        assert_eq!(report.requires_restart, vec!["email.queue", "bind_address"]);
+        assert_eq!(reloader.shared_config.default_group(), 3);
+        let current = reloader.current.lock().clone();
+        assert_eq!(current.postmaster_address, "admin@example.com");
+        assert_eq!(current.root_group, 5);
+        assert_eq!(current.bind_address, files.settings().bind_address);
+
+        // Restart only settings keep being reported until the restart
+        let report = reloader.reload().unwrap();
+        assert!(report.reloaded.is_empty());
+        assert_eq!(report.requires_restart, vec!["email.queue", "bind_address"]);
+    }
+    #[actix_rt::test]
+    pub async fn test_invalid_config_changes_nothing() {
+        let files = TestFiles::new("invalid");
+        let email = TestEmail::start().await;
+        let reloader = reloader(&files, &email).await;
+
+        let mut invalid = files.settings();
+        invalid.default_group = 3;
+        invalid.number_of_workers = 0;
+        files.write(&invalid);
+
+        assert!(matches!(reloader.reload(), Err(ReloadError::Config(_))));
+        assert_eq!(reloader.shared_config.default_group(), 1);
+        assert_eq!(reloader.current.lock().default_group, 1);
+    }
+}
@@ -0,0 +1,511 @@
+//! Outgoing emails are saved before they are sent so they are not lost on a crash.
+//!
+//! Sends that fail are retried with an exponential backoff.
+//! After [EmailQueueSettings::max_attempts] the email is kept as [QueuedEmailState::Failed] until an admin retries or drops it
+use chrono::{DateTime, Duration, Local};
+use lettre::{address::Envelope, Address, Message};
+use redb::{CommitError, Database, ReadableTable, TableDefinition, WriteTransaction};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{debug, warn};
+use utils::config::EmailQueueSettings;
+
+#[derive(Debug, Error)]
+pub enum EmailQueueError {
+    #[error(transparent)]
+    RedbError(#[from] redb::Error),
+    #[error(transparent)]
+    DatabaseError(#[from] redb::DatabaseError),
+    #[error(transparent)]
+    TableError(#[from] redb::TableError),
+    #[error(transparent)]
+    TransactionError(#[from] redb::TransactionError),
+    #[error(transparent)]
+    StorageError(#[from] redb::StorageError),
+    #[error(transparent)]
+    CommitError(#[from] CommitError),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum QueuedEmailState {
+    /// Waiting to be sent or retried
+    Queued,
+    /// Gave up on sending it
+    Failed,
+}
+impl QueuedEmailState {
+    /// The key in [COUNTS]
+    fn key(&self) -> &'static str {
+        match self {
+            QueuedEmailState::Queued => "queued",
+            QueuedEmailState::Failed => "failed",
+        }
+    }
+}
+/// Everything about a queued email other than the message itself
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueuedEmail {
+    pub id: u64,
+    pub from: Option<String>,
+    pub to: Vec<String>,
+    pub subject: Option<String>,
+    pub state: QueuedEmailState,
+    /// See [crate::email_service::Email::expires_on_restart]
+    #[serde(default)]
+    pub expires_on_restart: bool,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub next_attempt: DateTime<Local>,
+    pub created: DateTime<Local>,
+}
+impl QueuedEmail {
+    /// Rebuilds the envelope the message was queued with
+    pub fn envelope(&self) -> Option<Envelope> {
+        let from = match &self.from {
+            Some(from) => Some(from.parse::<Address>().ok()?),
+            None => None,
+        };
+        let to = self
+            .to
+            .iter()
+            .map(|to| to.parse::<Address>())
+            .collect::<Result<Vec<_>, _>>()
+            .ok()?;
+        Envelope::new(from, to).ok()
+    }
+}
+/// `id` to the [QueuedEmail] as JSON
+const EMAILS: TableDefinition<u64, &str> = TableDefinition::new("emails");
+/// `id` to the formatted message
+const MESSAGES: TableDefinition<u64, &[u8]> = TableDefinition::new("messages");
+/// Holds the last id given out so ids are not reused after an email is removed
+const LAST_ID: TableDefinition<&str, u64> = TableDefinition::new("last_id");
+/// The number of emails in each state. So they can be counted without reading every email
+const COUNTS: TableDefinition<&str, u64> = TableDefinition::new("counts");
+
+pub struct EmailQueue {
+    settings: EmailQueueSettings,
+    database: Database,
+}
+impl std::fmt::Debug for EmailQueue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EmailQueue")
+            .field("settings", &self.settings)
+            .finish()
+    }
+}
+impl EmailQueue {
+    pub fn new(settings: EmailQueueSettings) -> Result<Self, EmailQueueError> {
+        let database = if settings.database_location.exists() {
+            Database::open(&settings.database_location)?
+        } else {
+            Database::create(&settings.database_location)?
+        };
+        // Creates the tables so reads do not fail before the first email
+        let transaction = database.begin_write()?;
+        let emails = transaction.open_table(EMAILS)?;
+        transaction.open_table(MESSAGES)?;
+        transaction.open_table(LAST_ID)?;
+        let mut counts = transaction.open_table(COUNTS)?;
+        let counted: u64 = counts
+            .iter()?
+            .map(|entry| entry.map(|(_, count)| count.value()))
+            .sum::<Result<u64, _>>()?;
+        // Queues from before the counts were kept
+        if counted != emails.len()? {
+            let (mut queued, mut failed) = (0, 0);
+            for entry in emails.iter()? {
+                let (_, email) = entry?;
+                let email: QueuedEmail = serde_json::from_str(email.value())?;
+                match email.state {
+                    QueuedEmailState::Queued => queued += 1,
+                    QueuedEmailState::Failed => failed += 1,
+                }
+            }
+            counts.insert(QueuedEmailState::Queued.key(), queued)?;
+            counts.insert(QueuedEmailState::Failed.key(), failed)?;
+        }
+        drop(counts);
+        drop(emails);
+        transaction.commit()?;
+        let queue = Self { settings, database };
+        queue.remove_expired()?;
+        Ok(queue)
+    }
+    /// Removes the emails with tokens from before the restart
+    fn remove_expired(&self) -> Result<(), EmailQueueError> {
+        let mut removed = 0;
+        for email in self.list(None)? {
+            if email.expires_on_restart && self.remove(email.id)? {
+                removed += 1;
+            }
+        }
+        if removed > 0 {
+            warn!(
+                "Dropped {} emails with links that stopped working on restart",
+                removed
+            );
+        }
+        Ok(())
+    }
+    /// Saves the message to be sent as soon as possible
+    pub fn push(
+        &self,
+        message: &Message,
+        expires_on_restart: bool,
+    ) -> Result<QueuedEmail, EmailQueueError> {
+        let envelope = message.envelope();
+        let transaction = self.database.begin_write()?;
+        let mut last_id = transaction.open_table(LAST_ID)?;
+        let id = last_id.get("emails")?.map(|id| id.value()).unwrap_or(0) + 1;
+        last_id.insert("emails", id)?;
+        drop(last_id);
+        let mut emails = transaction.open_table(EMAILS)?;
+        let now = Local::now();
+        let email = QueuedEmail {
+            id,
+            from: envelope.from().map(ToString::to_string),
+            to: envelope.to().iter().map(ToString::to_string).collect(),
+            subject: message.headers().get_raw("Subject").map(ToOwned::to_owned),
+            state: QueuedEmailState::Queued,
+            expires_on_restart,
+            attempts: 0,
+            last_error: None,
+            next_attempt: now,
+            created: now,
+        };
+        emails.insert(id, serde_json::to_string(&email)?.as_str())?;
+        drop(emails);
+        let mut messages = transaction.open_table(MESSAGES)?;
+        messages.insert(id, message.formatted().as_slice())?;
+        drop(messages);
+        change_count(&transaction, email.state, 1)?;
+        transaction.commit()?;
+        Ok(email)
+    }
+    /// The number of emails in the state
+    pub fn count(&self, state: QueuedEmailState) -> Result<u64, EmailQueueError> {
+        let transaction = self.database.begin_read()?;
+        let counts = transaction.open_table(COUNTS)?;
+        let count = counts.get(state.key())?.map(|count| count.value());
+        Ok(count.unwrap_or(0))
+    }
+    /// Every email in the queue. Optionally only the ones in a state
+    pub fn list(
+        &self,
+        state: Option<QueuedEmailState>,
+    ) -> Result<Vec<QueuedEmail>, EmailQueueError> {
+        let transaction = self.database.begin_read()?;
+        let emails = transaction.open_table(EMAILS)?;
+        let mut result = Vec::new();
+        for entry in emails.iter()? {
+            let (_, email) = entry?;
+            let email: QueuedEmail = serde_json::from_str(email.value())?;
+            if state.map_or(true, |state| state == email.state) {
+                result.push(email);
+            }
+        }
+        Ok(result)
+    }
+    pub fn get(&self, id: u64) -> Result<Option<QueuedEmail>, EmailQueueError> {
+        let transaction = self.database.begin_read()?;
+        let emails = transaction.open_table(EMAILS)?;
+        let email = match emails.get(id)? {
+            Some(email) => Some(serde_json::from_str(email.value())?),
+            None => None,
+        };
+        Ok(email)
+    }
+    pub fn get_message(&self, id: u64) -> Result<Option<Vec<u8>>, EmailQueueError> {
+        let transaction = self.database.begin_read()?;
+        let messages = transaction.open_table(MESSAGES)?;
+        let message = messages.get(id)?.map(|message| message.value().to_vec());
+        Ok(message)
+    }
+    /// Queued emails that are due to be sent
+    pub fn due(&self) -> Result<Vec<QueuedEmail>, EmailQueueError> {
+        let now = Local::now();
+        Ok(self
+            .list(Some(QueuedEmailState::Queued))?
+            .into_iter()
+            .filter(|email| email.next_attempt <= now)
+            .collect())
+    }
+    /// When the next queued email is due
+    pub fn next_attempt(&self) -> Result<Option<DateTime<Local>>, EmailQueueError> {
+        Ok(self
+            .list(Some(QueuedEmailState::Queued))?
+            .into_iter()
+            .map(|email| email.next_attempt)
+            .min())
+    }
+    fn save(&self, email: &QueuedEmail) -> Result<(), EmailQueueError> {
+        let transaction = self.database.begin_write()?;
+        let mut emails = transaction.open_table(EMAILS)?;
+        let previous = emails
+            .insert(email.id, serde_json::to_string(email)?.as_str())?
+            .map(|previous| serde_json::from_str::<QueuedEmail>(previous.value()))
+            .transpose()?;
+        drop(emails);
+        let previous_state = previous.map(|previous| previous.state);
+        if previous_state != Some(email.state) {
+            if let Some(previous_state) = previous_state {
+                change_count(&transaction, previous_state, -1)?;
+            }
+            change_count(&transaction, email.state, 1)?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+    /// Removes the email from the queue. Returns false if it was not in the queue
+    pub fn remove(&self, id: u64) -> Result<bool, EmailQueueError> {
+        let transaction = self.database.begin_write()?;
+        let mut emails = transaction.open_table(EMAILS)?;
+        let removed = emails
+            .remove(id)?
+            .map(|removed| serde_json::from_str::<QueuedEmail>(removed.value()))
+            .transpose()?;
+        drop(emails);
+        let mut messages = transaction.open_table(MESSAGES)?;
+        messages.remove(id)?;
+        drop(messages);
+        if let Some(removed) = &removed {
+            change_count(&transaction, removed.state, -1)?;
+        }
+        transaction.commit()?;
+        Ok(removed.is_some())
+    }
+    /// Records a failed attempt.
+    ///
+    /// The email is retried later unless the error is permanent or it has run out of attempts
+    pub fn attempt_failed(
+        &self,
+        mut email: QueuedEmail,
+        error: String,
+        permanent: bool,
+    ) -> Result<QueuedEmail, EmailQueueError> {
+        email.attempts += 1;
+        email.last_error = Some(error);
+        if permanent || email.attempts >= self.settings.max_attempts {
+            warn!(
+                "Giving up on email {} after {} attempts",
+                email.id, email.attempts
+            );
+            email.state = QueuedEmailState::Failed;
+        } else {
+            email.next_attempt = Local::now() + self.retry_delay(email.attempts);
+            debug!("Retrying email {} at {}", email.id, email.next_attempt);
+        }
+        self.save(&email)?;
+        Ok(email)
+    }
+    /// Doubles the delay for every attempt up to [EmailQueueSettings::max_retry_delay]
+    pub fn retry_delay(&self, attempts: u32) -> Duration {
+        2i32.checked_pow(attempts.saturating_sub(1))
+            .and_then(|multiplier| self.settings.retry_delay.checked_mul(multiplier))
+            .unwrap_or(self.settings.max_retry_delay)
+            .min(self.settings.max_retry_delay)
+    }
+    /// Queues the email to be sent right away with a fresh set of attempts.
+    ///
+    /// Returns None if the email is not in the queue
+    pub fn retry(&self, id: u64) -> Result<Option<QueuedEmail>, EmailQueueError> {
+        let Some(mut email) = self.get(id)? else {
+            return Ok(None);
+        };
+        email.state = QueuedEmailState::Queued;
+        email.attempts = 0;
+        email.next_attempt = Local::now();
+        self.save(&email)?;
+        Ok(Some(email))
+    }
+}
+fn change_count(
+    transaction: &WriteTransaction,
+    state: QueuedEmailState,
+    change: i64,
+) -> Result<(), EmailQueueError> {
+    let mut counts = transaction.open_table(COUNTS)?;
+    let count = counts.get(state.key())?.map(|count| count.value());
+    let count = count.unwrap_or(0).saturating_add_signed(change);
+    counts.insert(state.key(), count)?;
+    Ok(())
+}
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use chrono::{Duration, Local};
+    use lettre::Message;
+    use utils::config::EmailQueueSettings;
+
+    use super::{EmailQueue, QueuedEmailState};
+
+    /// A queue file in the temp directory. Removed on drop
+    pub struct QueueFile(PathBuf);
+    impl QueueFile {
+        pub fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "stalwart-panel-queue-{}-{}.redb",
+                std::process::id(),
+                name
+            ));
+            let _ = std::fs::remove_file(&path);
+            Self(path)
+        }
+        /// Only one queue can have the file open at a time
+        pub fn open(&self) -> EmailQueue {
+            self.open_with(EmailQueueSettings::default())
+        }
+        pub fn open_with(&self, settings: EmailQueueSettings) -> EmailQueue {
+            EmailQueue::new(EmailQueueSettings {
+                database_location: self.0.clone(),
+                ..settings
+            })
+            .unwrap()
+        }
+    }
+    impl Drop for QueueFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+    pub fn message(subject: &str) -> Message {
+        Message::builder()
+            .from("panel@example.com".parse().unwrap())
+            .to("user@example.com".parse().unwrap())
+            .subject(subject)
+            .body(String::from("Body"))
+            .unwrap()
+    }
+    fn counts(queue: &EmailQueue) -> (u64, u64) {
+        (
+            queue.count(QueuedEmailState::Queued).unwrap(),
+            queue.count(QueuedEmailState::Failed).unwrap(),
+        )
+    }
+
+    #[test]
+    pub fn test_counts() {
+        let file = QueueFile::new("counts");
+        let queue = file.open();
+        let first = queue.push(&message("First"), false).unwrap();
+        let second = queue.push(&message("Second"), false).unwrap();
+        queue.push(&message("Third"), false).unwrap();
+        queue
+            .attempt_failed(first, "Rejected".to_owned(), true)
+            .unwrap();
+        assert!(queue.remove(second.id).unwrap());
+        assert!(!queue.remove(second.id).unwrap());
+        assert_eq!(counts(&queue), (1, 1));
+        drop(queue);
+
+        let queue = file.open();
+        assert_eq!(counts(&queue), (1, 1));
+        let failed = queue.list(Some(QueuedEmailState::Failed)).unwrap();
+        queue.retry(failed[0].id).unwrap();
+        assert_eq!(counts(&queue), (2, 0));
+    }
+    #[test]
+    pub fn test_push_and_due() {
+        let file = QueueFile::new("push");
+        let queue = file.open();
+        let pushed = queue.push(&message("Hello"), false).unwrap();
+        assert_eq!(pushed.state, QueuedEmailState::Queued);
+        assert_eq!(pushed.to, vec!["user@example.com".to_owned()]);
+        assert_eq!(pushed.subject.as_deref(), Some("Hello"));
+        assert!(pushed.envelope().is_some());
+        let message = String::from_utf8(queue.get_message(pushed.id).unwrap().unwrap()).unwrap();
+        assert!(message.contains("Subject: Hello"));
+
+        let due = queue.due().unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].id, pushed.id);
+
+        let failed = queue
+            .attempt_failed(pushed, "Connection refused".to_owned(), false)
+            .unwrap();
+        assert_eq!(failed.state, QueuedEmailState::Queued);
+        assert_eq!(failed.attempts, 1);
+        assert_eq!(failed.last_error.as_deref(), Some("Connection refused"));
+        assert!(failed.next_attempt > Local::now());
+        assert!(queue.due().unwrap().is_empty());
+        assert_eq!(queue.next_attempt().unwrap(), Some(failed.next_attempt));
+
+        // Ids are not reused after a removal
+        assert!(queue.remove(failed.id).unwrap());
+        let next = queue.push(&message("Again"), false).unwrap();
+        assert!(next.id > failed.id);
+    }
+    #[test]
+    pub fn test_retry_delay_backoff() {
+        let file = QueueFile::new("backoff");
+        let queue = file.open_with(EmailQueueSettings {
+            retry_delay: Duration::minutes(1),
+            max_retry_delay: Duration::minutes(10),
+            ..Default::default()
+        });
+        let delays: Vec<_> = [1, 2, 3, 4, 5, 40]
+            .into_iter()
+            .map(|attempts| queue.retry_delay(attempts).num_minutes())
+            .collect();
+        assert_eq!(delays, vec![1, 2, 4, 8, 10, 10]);
+    }
+    #[test]
+    pub fn test_dead_lettering() {
+        let file = QueueFile::new("dead");
+        let queue = file.open_with(EmailQueueSettings {
+            max_attempts: 2,
+            ..Default::default()
+        });
+        let email = queue.push(&message("First"), false).unwrap();
+        let email = queue
+            .attempt_failed(email, "Timeout".to_owned(), false)
+            .unwrap();
+        assert_eq!(email.state, QueuedEmailState::Queued);
+        let email = queue
+            .attempt_failed(email, "Timeout".to_owned(), false)
+            .unwrap();
+        assert_eq!(email.state, QueuedEmailState::Failed);
+        assert!(queue.due().unwrap().is_empty());
+
+        // Permanent errors are not retried
+        let permanent = queue.push(&message("Second"), false).unwrap();
+        let permanent = queue
+            .attempt_failed(permanent, "No such user".to_owned(), true)
+            .unwrap();
+        assert_eq!(permanent.state, QueuedEmailState::Failed);
+        assert_eq!(permanent.attempts, 1);
+
+        let retried = queue.retry(email.id).unwrap().unwrap();
+        assert_eq!(retried.state, QueuedEmailState::Queued);
+        assert_eq!(retried.attempts, 0);
+        assert_eq!(queue.due().unwrap().len(), 1);
+        assert!(queue.retry(404).unwrap().is_none());
+    }
+    #[test]
+    pub fn test_expires_on_restart() {
+        let file = QueueFile::new("expires");
+        let queue = file.open();
+        let kept = queue.push(&message("Welcome"), false).unwrap();
+        let expired = queue.push(&message("Password Reset"), true).unwrap();
+        let failed = queue.push(&message("Confirm"), true).unwrap();
+        queue
+            .attempt_failed(failed, "Rejected".to_owned(), true)
+            .unwrap();
+        drop(queue);
+
+        let queue = file.open();
+        let ids: Vec<_> = queue
+            .list(None)
+            .unwrap()
+            .iter()
+            .map(|email| email.id)
+            .collect();
+        assert_eq!(ids, vec![kept.id]);
+        assert!(queue.get_message(expired.id).unwrap().is_none());
+        assert_eq!(counts(&queue), (1, 0));
+    }
+}
@@ -1,22 +1,37 @@
 use std::{
+    borrow::Cow,
     fmt::{Debug, Formatter},
     io,
+    path::{Path, PathBuf},
+    sync::Arc,
 };
 
+use ahash::HashSet;
+use chrono::{DateTime, Local};
 use flume::{Receiver, Sender};
-use futures_util::{FutureExt, StreamExt};
-use handlebars::Handlebars;
+use futures_util::FutureExt;
+use handlebars::{handlebars_helper, Handlebars, RenderError};
 use lettre::{
+    address::AddressError,
     message::{header, MessageBuilder, MultiPart, SinglePart},
-    transport::smtp::{authentication::Credentials, Error as SmtpError},
-    AsyncSmtpTransport, AsyncTransport, Message,
+    Message,
+};
+use parking_lot::{
+    MappedRwLockReadGuard, Mutex, RwLock, RwLockReadGuard, RwLockUpgradableReadGuard,
+    RwLockWriteGuard,
 };
 use rust_embed::RustEmbed;
 use serde::Serialize;
 use tracing::{debug, error, info, log::log_enabled, warn};
 use utils::{
-    config::{EmailEncryption, EmailSetting},
+    config::{EmailNotifications, EmailSetting},
     database::EmailAddress,
+    quota_size::QuotaSize,
+};
+
+use crate::{
+    email_queue::EmailQueue,
+    email_transport::{EmailCapture, SendError, Transport},
 };
 
 #[derive(RustEmbed)]
@@ -33,19 +48,6 @@ impl Debug for EmailDebug {
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct EmailRequest {
-    pub debug_info: Option<EmailDebug>,
-    pub message: Message,
-}
-impl EmailRequest {
-    pub fn new(debug_info: Option<EmailDebug>, message: Message) -> Self {
-        Self {
-            debug_info,
-            message,
-        }
-    }
-}
 macro_rules! template {
     ($template:expr) => {
         fn template_html() -> &'static str {
@@ -65,35 +67,194 @@ pub trait Email: Serialize {
 
     fn subject() -> &'static str;
 
+    /// The subject in the configured locale. Falls back to [Email::subject]
+    fn localized_subject(_locale: &str) -> Option<&'static str> {
+        None
+    }
+    /// Notifications can be turned off in the config. Emails the panel needs are always sent
+    fn enabled(_notifications: &EmailNotifications) -> bool {
+        true
+    }
+
+    /// The email has a link with a token that only lives in memory.
+    ///
+    /// It is dropped from the queue on startup instead of sending a link that no longer works
+    fn expires_on_restart() -> bool {
+        false
+    }
+
     fn debug_info(self) -> EmailDebug;
 }
 
+#[derive(Debug, Serialize)]
+pub struct EmailPreview {
+    pub subject: &'static str,
+    pub html: String,
+    pub txt: String,
+}
+
+/// If the panel can reach the SMTP server
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "state")]
+pub enum TransportState {
+    /// The first connection has not been tested yet
+    Connecting,
+    Connected {
+        since: DateTime<Local>,
+    },
+    /// Emails are queued until the connection comes back
+    Disconnected {
+        since: DateTime<Local>,
+        error: String,
+    },
+}
+impl TransportState {
+    pub fn is_connected(&self) -> bool {
+        matches!(self, TransportState::Connected { .. })
+    }
+    /// The state without the details. The error can contain the server's address
+    pub fn name(&self) -> &'static str {
+        match self {
+            TransportState::Connecting => "Connecting",
+            TransportState::Connected { .. } => "Connected",
+            TransportState::Disconnected { .. } => "Disconnected",
+        }
+    }
+    fn disconnected(&self, error: String) -> Self {
+        let since = match self {
+            TransportState::Disconnected { since, .. } => *since,
+            _ => Local::now(),
+        };
+        TransportState::Disconnected { since, error }
+    }
+}
+
+/// What [EmailAccess] builds from the [EmailSetting]. Replaced as a whole when the config is reloaded
 #[derive(Debug)]
-pub struct EmailAccess {
-    queue: Sender<EmailRequest>,
+struct EmailRendering {
     message_builder: MessageBuilder,
     email_handlebars: Handlebars<'static>,
+    locale: Option<String>,
+    notifications: EmailNotifications,
+    templates_directory: Option<PathBuf>,
+    /// The files from the templates directory that are registered
+    overrides: HashSet<String>,
 }
-impl EmailAccess {
-    /// Adds a new Email to the queue to be sent
-    ///
-    /// # Arguments
-    /// debug_info - If Debug Logging is is enabled this should be Some(EmailDebug). Otherwise it should be None
-    /// message - The message to be sent
-    #[inline]
-    pub fn send(&self, debug_info: Option<EmailDebug>, message: Message) {
-        let request = EmailRequest::new(debug_info, message);
-        if let Err(error) = self.queue.send(request) {
-            warn!("Email Queue Error: {}", error);
+impl EmailRendering {
+    fn new(email: &EmailSetting) -> io::Result<Self> {
+        let invalid = |setting: &str, error: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid {}: {}", setting, error),
+            )
         };
+        let mut message_builder = Message::builder().from(
+            email
+                .from
+                .parse()
+                .map_err(|e: AddressError| invalid("email.from", e.to_string()))?,
+        );
+        if let Some(reply_to) = &email.reply_to {
+            message_builder = message_builder.reply_to(
+                reply_to
+                    .parse()
+                    .map_err(|e: AddressError| invalid("email.reply_to", e.to_string()))?,
+            );
+        }
+
+        let mut email_handlebars = Handlebars::new();
+        // Reloads the templates from the directory when they are rendered
+        email_handlebars.set_dev_mode(email.templates_directory.is_some());
+        email_handlebars
+            .register_embed_templates::<EmailTemplates>()
+            .map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::Other,
+                    format!("Email Handlebars Error: {:?}", e),
+                )
+            })?;
+        let mut overrides = HashSet::default();
+        if let Some(directory) = &email.templates_directory {
+            EmailService::register_overrides(&mut email_handlebars, directory, &mut overrides)?;
+        }
+        register_helpers(&mut email_handlebars);
+        Ok(Self {
+            message_builder,
+            email_handlebars,
+            locale: email.locale.clone(),
+            notifications: email.notifications.clone(),
+            templates_directory: email.templates_directory.clone(),
+            overrides,
+        })
     }
-    pub fn get_handlebars(&self) -> &Handlebars<'static> {
-        &self.email_handlebars
+    /// If files were added to the templates directory since they were registered
+    fn has_new_overrides(&self) -> bool {
+        let Some(directory) = &self.templates_directory else {
+            return false;
+        };
+        override_files(directory).map_or(false, |files| {
+            files.iter().any(|(name, _)| !self.overrides.contains(name))
+        })
     }
-    #[inline]
-    pub fn build_body<E: Email>(&self, data: &E) -> MultiPart {
+    fn register_new_overrides(&mut self) {
+        let Some(directory) = &self.templates_directory else {
+            return;
+        };
+        if let Err(error) = EmailService::register_overrides(
+            &mut self.email_handlebars,
+            directory,
+            &mut self.overrides,
+        ) {
+            error!("{}", error);
+        }
+    }
+    /// Uses `{template}.{locale}.{extension}` if it exists
+    fn localized_template(
+        &self,
+        template: &'static str,
+        locale: Option<&str>,
+    ) -> Cow<'static, str> {
+        let Some(locale) = locale else {
+            return Cow::Borrowed(template);
+        };
+        let Some((name, extension)) = template.rsplit_once('.') else {
+            return Cow::Borrowed(template);
+        };
+        let localized = format!("{}.{}.{}", name, locale, extension);
+        if self.email_handlebars.has_template(&localized) {
+            Cow::Owned(localized)
+        } else {
+            Cow::Borrowed(template)
+        }
+    }
+    fn localized_subject<E: Email>(locale: Option<&str>) -> &'static str {
+        locale
+            .and_then(E::localized_subject)
+            .unwrap_or_else(E::subject)
+    }
+    fn subject<E: Email>(&self) -> &'static str {
+        Self::localized_subject::<E>(self.locale.as_deref())
+    }
+    fn preview<E: Email>(
+        &self,
+        data: &E,
+        locale: Option<&str>,
+    ) -> Result<EmailPreview, RenderError> {
+        let locale = locale.or(self.locale.as_deref());
+        Ok(EmailPreview {
+            subject: Self::localized_subject::<E>(locale),
+            html: self
+                .email_handlebars
+                .render(&self.localized_template(E::template_html(), locale), data)?,
+            txt: self
+                .email_handlebars
+                .render(&self.localized_template(E::template_txt(), locale), data)?,
+        })
+    }
+    fn build_body<E: Email>(&self, data: &E) -> MultiPart {
         let multipart = MultiPart::alternative();
-        let mut multipart = match self.email_handlebars.render(E::template_txt(), &data) {
+        let template_txt = self.localized_template(E::template_txt(), self.locale.as_deref());
+        let mut multipart = match self.email_handlebars.render(&template_txt, &data) {
             Ok(ok) => multipart.singlepart(
                 SinglePart::builder()
                     .header(header::ContentType::TEXT_PLAIN)
@@ -104,7 +265,8 @@ impl EmailAccess {
                 multipart.build()
             }
         };
-        match self.email_handlebars.render(E::template_html(), &data) {
+        let template_html = self.localized_template(E::template_html(), self.locale.as_deref());
+        match self.email_handlebars.render(&template_html, &data) {
             Ok(ok) => {
                 multipart = multipart.singlepart(
                     SinglePart::builder()
@@ -119,18 +281,153 @@ impl EmailAccess {
 
         multipart
     }
+}
+/// A new transport and templates built from changed [EmailSetting]. Applied with [EmailAccess::reload]
+pub struct EmailReload {
+    transport: Transport,
+    rendering: EmailRendering,
+}
+impl EmailReload {
+    /// Fails without changing anything if the settings are invalid
+    pub fn new(email: &EmailSetting) -> io::Result<Self> {
+        Ok(Self {
+            transport: Transport::new(email)?,
+            rendering: EmailRendering::new(email)?,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct EmailAccess {
+    queue: Arc<EmailQueue>,
+    wake: Sender<()>,
+    /// Hands a new transport to the email service
+    reload: Sender<Transport>,
+    transport_state: Arc<Mutex<TransportState>>,
+    rendering: RwLock<EmailRendering>,
+    capture: Mutex<Option<EmailCapture>>,
+}
+impl EmailAccess {
+    /// Adds a new Email to the queue to be sent
+    ///
+    /// # Arguments
+    /// debug_info - If Debug Logging is is enabled this should be Some(EmailDebug). Otherwise it should be None
+    /// message - The message to be sent
+    /// expires_on_restart - See [Email::expires_on_restart]
+    ///
+    /// # Returns
+    /// false if the email could not be queued
+    #[inline]
+    pub fn send(
+        &self,
+        debug_info: Option<EmailDebug>,
+        message: Message,
+        expires_on_restart: bool,
+    ) -> bool {
+        match self.queue.push(&message, expires_on_restart) {
+            Ok(queued) => {
+                if let Some(debug_info) = &debug_info {
+                    debug!("Queued Email {}: {:?}", queued.id, debug_info);
+                }
+                self.wake();
+                true
+            }
+            Err(error) => {
+                error!("Email Queue Error: {} for {:?}", error, debug_info);
+                false
+            }
+        }
+    }
+    pub fn queue(&self) -> &EmailQueue {
+        &self.queue
+    }
+    pub fn transport_state(&self) -> TransportState {
+        self.transport_state.lock().clone()
+    }
+    /// The sent emails when the capture transport is used
+    pub fn capture(&self) -> Option<EmailCapture> {
+        self.capture.lock().clone()
+    }
+    /// Tells the email service to check the queue
+    pub fn wake(&self) {
+        // A full channel means the service is already going to check the queue
+        let _ = self.wake.try_send(());
+    }
+    /// Replaces the transport and the templates.
+    ///
+    /// Emails already in the queue are sent with the new transport
+    pub fn reload(&self, reload: EmailReload) {
+        let EmailReload {
+            transport,
+            rendering,
+        } = reload;
+        *self.capture.lock() = transport.capture();
+        *self.rendering.write() = rendering;
+        if self.reload.send(transport).is_err() {
+            error!("The email service has stopped. The new transport will not be used");
+        }
+    }
+    /// Registers the files added to the templates directory first.
+    /// The dev mode of Handlebars only reloads the files it already knows about
+    fn rendering(&self) -> RwLockReadGuard<'_, EmailRendering> {
+        let rendering = self.rendering.upgradable_read();
+        if !rendering.has_new_overrides() {
+            return RwLockUpgradableReadGuard::downgrade(rendering);
+        }
+        let mut rendering = RwLockUpgradableReadGuard::upgrade(rendering);
+        rendering.register_new_overrides();
+        RwLockWriteGuard::downgrade(rendering)
+    }
+    pub fn get_handlebars(&self) -> MappedRwLockReadGuard<'_, Handlebars<'static>> {
+        RwLockReadGuard::map(self.rendering(), |rendering| &rendering.email_handlebars)
+    }
+    pub fn subject<E: Email>(&self) -> &'static str {
+        self.rendering().subject::<E>()
+    }
+    /// Renders the email without sending it
+    ///
+    /// # Arguments
+    /// locale - Replaces the configured locale
+    pub fn preview<E: Email>(
+        &self,
+        data: &E,
+        locale: Option<&str>,
+    ) -> Result<EmailPreview, RenderError> {
+        self.rendering().preview(data, locale)
+    }
+    #[inline]
+    pub fn build_body<E: Email>(&self, data: &E) -> MultiPart {
+        self.rendering().build_body(data)
+    }
     #[inline]
     pub fn prep_builder(&self) -> MessageBuilder {
-        self.message_builder.clone()
+        self.rendering().message_builder.clone()
     }
-    pub fn send_one_fn(&self, to: EmailAddress, data: impl Email) {
-        let body = self.build_body(&data);
+    /// Builds the email and adds it to the queue
+    ///
+    /// # Returns
+    /// false if the email could not be built or queued. A disabled email is not a failure
+    pub fn send_one_fn<E: Email>(&self, to: EmailAddress, data: E) -> bool {
+        let message = {
+            let rendering = self.rendering();
+            if !E::enabled(&rendering.notifications) {
+                debug!("{} emails are disabled", E::template_html());
+                return true;
+            }
+            let body = rendering.build_body(&data);
 
-        let message = match self.prep_builder().to(to.into()).multipart(body) {
+            rendering
+                .message_builder
+                .clone()
+                .to(to.into())
+                .subject(rendering.subject::<E>())
+                .multipart(body)
+        };
+        let message = match message {
             Ok(ok) => ok,
             Err(value) => {
                 error!("Email Error: {}", value);
-                return;
+                return false;
             }
         };
         let debug = if log_enabled!(tracing::log::Level::Debug) {
@@ -138,117 +435,394 @@ impl EmailAccess {
         } else {
             None
         };
-        self.send(debug, message);
+        self.send(debug, message, E::expires_on_restart())
     }
 }
 
-type Transport = AsyncSmtpTransport<lettre::Tokio1Executor>;
+const RECONNECT_DELAY: std::time::Duration = std::time::Duration::from_secs(5);
+const MAX_RECONNECT_DELAY: std::time::Duration = std::time::Duration::from_secs(300);
 #[derive(Debug)]
 pub struct EmailService;
 impl EmailService {
-    pub async fn start(email: EmailSetting) -> io::Result<Option<EmailAccess>> {
-        let Some(transport) = Self::build_connection(email.clone()).await.map_err(|e| {
-            io::Error::new(
-                io::ErrorKind::Other,
-                format!("Email Transport Error: {}", e),
-            )
-        })?
-        else {
-            return Ok(None);
-        };
-
-        let mut message_builder = Message::builder().from(email.from.parse().unwrap());
-        if let Some(reply_to) = &email.reply_to {
-            message_builder = message_builder.reply_to(reply_to.parse().unwrap());
-        }
+    /// Starts sending emails in the background.
+    ///
+    /// The SMTP server does not need to be up. Emails are queued until it can be reached
+    pub async fn start(email: EmailSetting) -> io::Result<EmailAccess> {
+        let EmailReload {
+            transport,
+            rendering,
+        } = EmailReload::new(&email)?;
+        let capture = transport.capture();
 
-        let mut email_handlebars = Handlebars::new();
-        email_handlebars
-            .register_embed_templates::<EmailTemplates>()
+        let queue = EmailQueue::new(email.queue.clone())
+            .map(Arc::new)
             .map_err(|e| {
-                io::Error::new(
-                    io::ErrorKind::Other,
-                    format!("Email Handlebars Error: {:?}", e),
-                )
+                io::Error::new(io::ErrorKind::Other, format!("Email Queue Error: {}", e))
             })?;
-
-        let (sender, receiver) = flume::bounded(100);
+        let (sender, receiver) = flume::bounded(1);
+        let (reload_sender, reload_receiver) = flume::unbounded();
+        let transport_state = Arc::new(Mutex::new(TransportState::Connecting));
+        let service_queue = queue.clone();
+        let service_state = transport_state.clone();
         actix_rt::spawn(async move {
-            Self::run(transport, receiver).await;
+            Self::run(
+                transport,
+                service_queue,
+                receiver,
+                reload_receiver,
+                service_state,
+            )
+            .await;
         });
-        Ok(Some(EmailAccess {
-            queue: sender,
-            message_builder,
-            email_handlebars,
-        }))
+        Ok(EmailAccess {
+            queue,
+            wake: sender,
+            reload: reload_sender,
+            transport_state,
+            rendering: RwLock::new(rendering),
+            capture: Mutex::new(capture),
+        })
+    }
+
+    /// Registers the `.html` and `.txt` files in the directory over the embedded templates
+    ///
+    /// # Arguments
+    /// registered - The files already registered. They are skipped and the new ones are added
+    fn register_overrides(
+        handlebars: &mut Handlebars<'static>,
+        directory: &Path,
+        registered: &mut HashSet<String>,
+    ) -> io::Result<()> {
+        for (name, path) in override_files(directory)? {
+            if registered.contains(&name) {
+                continue;
+            }
+            if !handlebars.has_template(&name) {
+                warn!("{} does not replace a built in template", path.display());
+            }
+            info!("Using {} for the {} template", path.display(), name);
+            handlebars
+                .register_template_file(&name, &path)
+                .map_err(|e| {
+                    io::Error::new(
+                        io::ErrorKind::Other,
+                        format!("Email Template Error in {}: {}", path.display(), e),
+                    )
+                })?;
+            registered.insert(name);
+        }
+        Ok(())
     }
 
-    async fn run(connection: Transport, queue: Receiver<EmailRequest>) {
+    /// Sends the queued emails as they become due.
+    ///
+    /// While the SMTP server can not be reached it is retried with a backoff and the emails stay in the queue.
+    /// Emails still in the queue on shutdown are sent on the next start
+    async fn run(
+        mut connection: Transport,
+        queue: Arc<EmailQueue>,
+        wake: Receiver<()>,
+        reloads: Receiver<Transport>,
+        state: Arc<Mutex<TransportState>>,
+    ) {
         let mut shutdown_hook = Box::pin(tokio::signal::ctrl_c().fuse());
-        let mut queue_async = queue.stream().fuse();
+        let mut reconnect_delay = RECONNECT_DELAY;
         loop {
-            futures_util::select! {
+            let connected = state.lock().is_connected();
+            let wait = if connected {
+                reconnect_delay = RECONNECT_DELAY;
+                if let Err(error) = Self::send_due(&connection, &queue).await {
+                    warn!("Lost the connection to the SMTP server: {}", error);
+                    let mut state = state.lock();
+                    *state = state.disconnected(error);
+                    continue;
+                }
+                match queue.next_attempt() {
+                    Ok(next_attempt) => next_attempt.map(|next_attempt| {
+                        (next_attempt - Local::now()).to_std().unwrap_or_default()
+                    }),
+                    Err(error) => {
+                        error!("Email Queue Error: {}", error);
+                        Some(RECONNECT_DELAY)
+                    }
+                }
+            } else {
+                match connection.test_connection().await {
+                    Ok(()) => {
+                        info!("Connected to the SMTP server");
+                        *state.lock() = TransportState::Connected {
+                            since: Local::now(),
+                        };
+                        continue;
+                    }
+                    Err(error) => {
+                        warn!(
+                            "Unable to connect to the SMTP server: {}. Retrying in {:?}",
+                            error, reconnect_delay
+                        );
+                        warn!("Please ensure that stalwart has already been configured");
+                        let mut state = state.lock();
+                        *state = state.disconnected(error);
+                    }
+                }
+                let wait = reconnect_delay;
+                reconnect_delay = (reconnect_delay * 2).min(MAX_RECONNECT_DELAY);
+                Some(wait)
+            };
+            let sleep = async {
+                match wait {
+                    Some(wait) => actix_rt::time::sleep(wait).await,
+                    None => std::future::pending::<()>().await,
+                }
+            }
+            .fuse();
+            let woken = wake.recv_async().fuse();
+            let reload = reloads.recv_async().fuse();
+            futures_util::pin_mut!(sleep, woken, reload);
+            let woken_early = futures_util::select! {
                 _ = shutdown_hook => {
                     debug!("Shutdown Signal Received. Stopping Email Service");
                     break;
                 }
-                value = queue_async.next() => {
-                    if let Some(value) = value {
-                        Self::send_email(&connection, value).await;
-                    }
+                transport = reload => {
+                    // The sender is only dropped with the EmailAccess
+                    let Ok(transport) = transport else {
+                        break;
+                    };
+                    info!("Switching to the reloaded email transport");
+                    connection = transport;
+                    reconnect_delay = RECONNECT_DELAY;
+                    *state.lock() = TransportState::Connecting;
+                    continue;
                 }
-            }
-        }
-        drop(queue_async);
-        // This could be a problem.
-        // I don't know if once Sigkill has been sent Tokio runtime will still be running
-        // Also the active Password Reset Tokens are stored in memory.
-        // So sending these emails is unnecessary
-        if !queue.is_empty() {
-            info!("Email Queue is not empty. Sending remaining emails");
-            while let Ok(value) = queue.try_recv() {
-                Self::send_email(&connection, value).await;
+                _ = woken => true,
+                _ = sleep => false,
+            };
+            // New emails wait for the reconnect
+            if woken_early && !connected {
+                sleep.await;
             }
         }
         info!("Email Service has been stopped")
     }
 
-    async fn send_email(connection: &Transport, value: EmailRequest) {
-        let EmailRequest {
-            debug_info,
-            message,
-        } = value;
-        if let Some(debug_info) = &debug_info {
-            debug!("Sending Email: {:?}", debug_info);
-        }
-        match connection.send(message).await {
-            Ok(ok) => {
-                if ok.is_positive() {
-                    debug!("Email Sent Successfully");
-                } else {
-                    error!("Email Send Error for {:?}", debug_info);
-                }
+    /// Sends the emails that are due.
+    ///
+    /// Stops with an error if the SMTP server can not be reached. The emails stay in the queue without using an attempt
+    async fn send_due(connection: &Transport, queue: &EmailQueue) -> Result<(), String> {
+        let due = match queue.due() {
+            Ok(due) => due,
+            Err(error) => {
+                error!("Email Queue Error: {}", error);
+                return Ok(());
             }
-            Err(err) => {
-                error!("Email Send Error: {} for {:?}", err, debug_info);
+        };
+        for email in due {
+            let id = email.id;
+            let result = match (email.envelope(), queue.get_message(id)) {
+                (Some(envelope), Ok(Some(message))) => {
+                    debug!("Sending Email {} to {:?}", id, email.to);
+                    connection.send_raw(&envelope, &message).await
+                }
+                (_, Err(error)) => {
+                    error!("Email Queue Error: {}", error);
+                    continue;
+                }
+                _ => Err(SendError::Permanent(
+                    "The message or its envelope is invalid".to_owned(),
+                )),
+            };
+            let saved = match result {
+                Ok(()) => queue.remove(id).map(|_| ()),
+                Err(SendError::Connection(error)) => return Err(error),
+                Err(SendError::Permanent(error)) => {
+                    error!("Email Send Error: {} for Email {}", error, id);
+                    queue.attempt_failed(email, error, true).map(|_| ())
+                }
+                Err(SendError::Transient(error)) => {
+                    error!("Email Send Error: {} for Email {}", error, id);
+                    queue.attempt_failed(email, error, false).map(|_| ())
+                }
+            };
+            if let Err(error) = saved {
+                error!("Email Queue Error: {}", error);
             }
         }
+        Ok(())
     }
-    async fn build_connection(email: EmailSetting) -> Result<Option<Transport>, SmtpError> {
-        let credentials = Credentials::new(email.username.clone(), email.password.clone());
-        let transport = match email.encryption {
-            EmailEncryption::StartTLS => Transport::starttls_relay(email.host.as_str())?
-                .credentials(credentials)
-                .build(),
-            _ => Transport::relay(email.host.as_str())?
-                .credentials(credentials)
-                .build(),
+}
+
+/// The `.html` and `.txt` files in the templates directory. Named by their file name
+fn override_files(directory: &Path) -> io::Result<Vec<(String, PathBuf)>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(directory)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
         };
-        if !transport.test_connection().await? {
-            warn!("Email Transport Test Connection Failed");
-            warn!("Please ensure that stalwart has already been configured");
-            return Ok(None);
+        if path.is_file() && (name.ends_with(".html") || name.ends_with(".txt")) {
+            files.push((name.to_owned(), path.clone()));
         }
-        Ok(Some(transport))
+    }
+    Ok(files)
+}
+handlebars_helper!(format_bytes: |bytes: i64| QuotaSize(bytes).to_human());
+handlebars_helper!(format_date: |date: str, { format: str = "%Y-%m-%d %H:%M %Z" }| {
+    DateTime::parse_from_rfc3339(date)
+        .or_else(|_| DateTime::parse_from_rfc2822(date))
+        .map(|date| date.format(format).to_string())
+        .unwrap_or_else(|_| date.to_owned())
+});
+handlebars_helper!(uppercase: |value: str| value.to_uppercase());
+handlebars_helper!(lowercase: |value: str| value.to_lowercase());
+/// Helpers available to every template.
+///
+/// - `{{format_bytes 1073741824}}` is `1 GiB` and `{{format_bytes 1536}}` is `1.5 KiB`
+/// - `{{format_date created format="%d.%m.%Y"}}` formats RFC 3339 and RFC 2822 dates
+/// - `{{uppercase name}}` and `{{lowercase name}}`
+fn register_helpers(handlebars: &mut Handlebars<'static>) {
+    handlebars.register_helper("format_bytes", Box::new(format_bytes));
+    handlebars.register_helper("format_date", Box::new(format_date));
+    handlebars.register_helper("uppercase", Box::new(uppercase));
+    handlebars.register_helper("lowercase", Box::new(lowercase));
+}
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use ahash::HashSet;
+    use handlebars::Handlebars;
+    use serde_json::json;
+
+    use super::{register_helpers, EmailService, EmailTemplates};
+    use crate::{notifications::WelcomeEmail, test_support::TestEmail};
+
+    /// A templates directory in the temp directory. Removed on drop
+    struct TemplatesDirectory(PathBuf);
+    impl TemplatesDirectory {
+        fn new(name: &str) -> Self {
+            let directory = std::env::temp_dir().join(format!(
+                "stalwart-panel-templates-{}-{}",
+                std::process::id(),
+                name
+            ));
+            let _ = std::fs::remove_dir_all(&directory);
+            std::fs::create_dir_all(&directory).unwrap();
+            Self(directory)
+        }
+        fn write(&self, file: &str, content: &str) {
+            std::fs::write(self.0.join(file), content).unwrap();
+        }
+    }
+    impl Drop for TemplatesDirectory {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+    fn welcome() -> WelcomeEmail {
+        WelcomeEmail {
+            name: "Alice".to_owned(),
+            username: "alice".to_owned(),
+            panel_url: "https://panel.example.com".to_owned(),
+            email_address: None,
+        }
+    }
+    fn render(template: &str, data: serde_json::Value) -> String {
+        let mut handlebars = Handlebars::new();
+        register_helpers(&mut handlebars);
+        handlebars.render_template(template, &data).unwrap()
+    }
+
+    #[test]
+    pub fn test_register_overrides() {
+        let directory = TemplatesDirectory::new("register");
+        directory.write("welcome.txt", "Custom welcome for {{ username }}");
+        directory.write("custom.html", "<p>{{ name }}</p>");
+        directory.write("notes.md", "Not a template");
+        let mut handlebars = Handlebars::new();
+        handlebars
+            .register_embed_templates::<EmailTemplates>()
+            .unwrap();
+        let mut registered = HashSet::default();
+
+        EmailService::register_overrides(&mut handlebars, &directory.0, &mut registered).unwrap();
+        let mut names: Vec<_> = registered.iter().map(String::as_str).collect();
+        names.sort_unstable();
+        assert_eq!(names, vec!["custom.html", "welcome.txt"]);
+        assert_eq!(
+            handlebars.render("welcome.txt", &welcome()).unwrap(),
+            "Custom welcome for alice"
+        );
+        assert!(handlebars.has_template("welcome.html"));
+        assert!(!handlebars.has_template("notes.md"));
+
+        directory.write("broken.txt", "{{#if}}");
+        assert!(
+            EmailService::register_overrides(&mut handlebars, &directory.0, &mut registered)
+                .is_err()
+        );
+    }
+    #[actix_rt::test]
+    pub async fn test_new_overrides_are_picked_up() {
+        let directory = TemplatesDirectory::new("hot");
+        let email = TestEmail::start_with(|settings| {
+            settings.templates_directory = Some(directory.0.clone());
+        })
+        .await;
+        let built_in = email.access.preview(&welcome(), None).unwrap();
+        assert!(built_in.txt.starts_with("Hello Alice"));
+
+        directory.write("welcome.txt", "Added for {{ username }}");
+        let added = email.access.preview(&welcome(), None).unwrap();
+        assert_eq!(added.txt, "Added for alice");
+        assert_eq!(added.html, built_in.html);
+
+        directory.write("welcome.txt", "Edited for {{ username }}");
+        assert_eq!(
+            email.access.preview(&welcome(), None).unwrap().txt,
+            "Edited for alice"
+        );
+
+        directory.write("welcome.de.txt", "Hallo {{ username }}");
+        assert_eq!(
+            email.access.preview(&welcome(), Some("de")).unwrap().txt,
+            "Hallo alice"
+        );
+    }
+    #[test]
+    pub fn test_format_bytes() {
+        let cases = [
+            (0, "0 B"),
+            (1536, "1.5 KiB"),
+            (2048, "2 KiB"),
+            (1_073_741_824, "1 GiB"),
+            (4_500_000_000, "4.2 GiB"),
+            (5_368_709_120, "5 GiB"),
+        ];
+        for (bytes, expected) in cases {
+            assert_eq!(
+                render("{{format_bytes bytes}}", json!({ "bytes": bytes })),
+                expected
+            );
+        }
+    }
+    #[test]
+    pub fn test_format_date() {
+        let date = |date: &str| json!({ "date": date });
+        assert_eq!(
+            render("{{format_date date}}", date("2024-01-02T03:04:05+00:00")),
+            "2024-01-02 03:04 +00:00"
+        );
+        assert_eq!(
+            render(
+                r#"{{format_date date format="%d.%m.%Y"}}"#,
+                date("Tue, 2 Jan 2024 03:04:05 +0100")
+            ),
+            "02.01.2024"
+        );
+        assert_eq!(
+            render("{{format_date date}}", date("yesterday")),
+            "yesterday"
+        );
     }
 }
@@ -0,0 +1,421 @@
+//! The ways emails can leave the panel. Picked with [EmailTransport]
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use lettre::{
+    address::Envelope,
+    transport::smtp::{
+        authentication::{Credentials, Mechanism},
+        client::{Certificate, Tls, TlsParameters},
+    },
+    AsyncFileTransport, AsyncSendmailTransport, AsyncSmtpTransport, AsyncTransport, Tokio1Executor,
+};
+use parking_lot::Mutex;
+use tokio::sync::Notify;
+use tracing::debug;
+use utils::config::{EmailAuthMechanism, EmailEncryption, EmailSetting, EmailTransport};
+
+/// Why an email was not sent
+pub enum SendError {
+    /// The server rejected the email and will keep doing so
+    Permanent(String),
+    /// The server rejected the email for now
+    Transient(String),
+    /// The server could not be reached
+    Connection(String),
+}
+
+/// Built from [EmailSetting::transport]
+pub enum Transport {
+    Smtp(AsyncSmtpTransport<Tokio1Executor>),
+    Sendmail(AsyncSendmailTransport<Tokio1Executor>),
+    File(AsyncFileTransport<Tokio1Executor>),
+    Maildir(PathBuf),
+    Capture(EmailCapture),
+}
+impl Transport {
+    pub fn new(email: &EmailSetting) -> io::Result<Self> {
+        let transport = match &email.transport {
+            EmailTransport::Smtp => Transport::Smtp(Self::build_smtp(email)?),
+            EmailTransport::Sendmail { command } => Transport::Sendmail(match command {
+                Some(command) => AsyncSendmailTransport::new_with_command(command),
+                None => AsyncSendmailTransport::new(),
+            }),
+            EmailTransport::File { directory } => {
+                std::fs::create_dir_all(directory)?;
+                Transport::File(AsyncFileTransport::new(directory))
+            }
+            EmailTransport::Maildir { directory } => {
+                for folder in ["tmp", "new", "cur"] {
+                    std::fs::create_dir_all(directory.join(folder))?;
+                }
+                Transport::Maildir(directory.clone())
+            }
+            EmailTransport::Capture => Transport::Capture(EmailCapture::default()),
+        };
+        Ok(transport)
+    }
+    fn build_smtp(email: &EmailSetting) -> io::Result<AsyncSmtpTransport<Tokio1Executor>> {
+        let smtp_error = |e: lettre::transport::smtp::Error| {
+            io::Error::new(
+                io::ErrorKind::Other,
+                format!("Email Transport Error: {}", e),
+            )
+        };
+        let mut tls_parameters = TlsParameters::builder(email.host.clone());
+        if let Some(ca_bundle) = &email.ca_bundle {
+            let pem = std::fs::read(ca_bundle)?;
+            tls_parameters = tls_parameters
+                .add_root_certificate(Certificate::from_pem(&pem).map_err(smtp_error)?);
+        }
+        let tls = match email.encryption {
+            EmailEncryption::NONE => Tls::None,
+            EmailEncryption::StartTLS => Tls::Required(tls_parameters.build().map_err(smtp_error)?),
+            EmailEncryption::TLS => Tls::Wrapper(tls_parameters.build().map_err(smtp_error)?),
+        };
+        let mut builder =
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(email.host.as_str())
+                .port(smtp_port(email))
+                .tls(tls);
+        if email.username.is_empty() {
+            debug!("No SMTP username. Sending without logging in");
+        } else {
+            builder = builder.credentials(Credentials::new(
+                email.username.clone(),
+                email.password.clone(),
+            ));
+            if let Some(mechanism) = smtp_mechanism(email) {
+                builder = builder.authentication(vec![mechanism]);
+            }
+        }
+        Ok(builder.build())
+    }
+    /// The captured emails if this is the capture transport
+    pub fn capture(&self) -> Option<EmailCapture> {
+        match self {
+            Transport::Capture(capture) => Some(capture.clone()),
+            _ => None,
+        }
+    }
+    /// Only the SMTP transport can be down
+    pub async fn test_connection(&self) -> Result<(), String> {
+        match self {
+            Transport::Smtp(smtp) => match smtp.test_connection().await {
+                Ok(true) => Ok(()),
+                Ok(false) => Err("The connection test failed".to_owned()),
+                Err(error) => Err(error.to_string()),
+            },
+            _ => Ok(()),
+        }
+    }
+    pub async fn send_raw(&self, envelope: &Envelope, message: &[u8]) -> Result<(), SendError> {
+        match self {
+            Transport::Smtp(smtp) => match smtp.send_raw(envelope, message).await {
+                Ok(ok) if ok.is_positive() => {
+                    debug!("Email Sent Successfully");
+                    Ok(())
+                }
+                Ok(ok) => Err(SendError::Transient(format!(
+                    "Unexpected response {}",
+                    ok.code()
+                ))),
+                Err(err) if err.is_permanent() => Err(SendError::Permanent(err.to_string())),
+                Err(err) if err.is_transient() => Err(SendError::Transient(err.to_string())),
+                Err(err) => Err(SendError::Connection(err.to_string())),
+            },
+            Transport::Sendmail(sendmail) => sendmail
+                .send_raw(envelope, message)
+                .await
+                .map_err(|err| SendError::Transient(err.to_string())),
+            Transport::File(file) => file
+                .send_raw(envelope, message)
+                .await
+                .map(|id| debug!("Wrote Email {}", id))
+                .map_err(|err| SendError::Transient(err.to_string())),
+            Transport::Maildir(directory) => deliver_to_maildir(directory, message)
+                .await
+                .map_err(|err| SendError::Transient(err.to_string())),
+            Transport::Capture(capture) => {
+                capture.push(CapturedEmail {
+                    envelope: envelope.clone(),
+                    message: message.to_vec(),
+                });
+                Ok(())
+            }
+        }
+    }
+}
+/// [EmailSetting::port] or the usual port of the encryption
+fn smtp_port(email: &EmailSetting) -> u16 {
+    email.port.unwrap_or(match email.encryption {
+        EmailEncryption::NONE => 25,
+        EmailEncryption::StartTLS => 587,
+        EmailEncryption::TLS => 465,
+    })
+}
+/// None lets lettre pick from what the server offers
+fn smtp_mechanism(email: &EmailSetting) -> Option<Mechanism> {
+    email.auth_mechanism.map(|mechanism| match mechanism {
+        EmailAuthMechanism::Plain => Mechanism::Plain,
+        EmailAuthMechanism::Login => Mechanism::Login,
+        EmailAuthMechanism::Xoauth2 => Mechanism::Xoauth2,
+    })
+}
+/// Writes the message to `tmp` and then moves it to `new` so readers never see half an email
+async fn deliver_to_maildir(directory: &Path, message: &[u8]) -> io::Result<()> {
+    static DELIVERIES: AtomicU64 = AtomicU64::new(0);
+    let name = format!(
+        "{}.{}_{}.stalwart-panel",
+        chrono::Utc::now().timestamp(),
+        std::process::id(),
+        DELIVERIES.fetch_add(1, Ordering::Relaxed)
+    );
+    let tmp = directory.join("tmp").join(&name);
+    tokio::fs::write(&tmp, message).await?;
+    tokio::fs::rename(&tmp, directory.join("new").join(&name)).await?;
+    debug!("Delivered Email {} to {}", name, directory.display());
+    Ok(())
+}
+
+/// An email sent with [Transport::Capture]
+#[derive(Debug, Clone)]
+pub struct CapturedEmail {
+    pub envelope: Envelope,
+    /// The formatted message. Including the headers
+    pub message: Vec<u8>,
+}
+impl CapturedEmail {
+    pub fn to(&self) -> Vec<String> {
+        self.envelope.to().iter().map(ToString::to_string).collect()
+    }
+    pub fn message(&self) -> String {
+        String::from_utf8_lossy(&self.message).into_owned()
+    }
+    /// The value of the header in the message. Such as `Subject`
+    pub fn header(&self, name: &str) -> Option<String> {
+        let message = self.message();
+        let headers = message.split("\r\n\r\n").next()?;
+        headers.split("\r\n").find_map(|line| {
+            let (header, value) = line.split_once(':')?;
+            header
+                .eq_ignore_ascii_case(name)
+                .then(|| value.trim().to_owned())
+        })
+    }
+}
+/// The emails sent with [Transport::Capture]. Clones share the same emails
+#[derive(Debug, Clone, Default)]
+pub struct EmailCapture {
+    emails: Arc<Mutex<Vec<CapturedEmail>>>,
+    sent: Arc<Notify>,
+}
+impl EmailCapture {
+    fn push(&self, email: CapturedEmail) {
+        self.emails.lock().push(email);
+        self.sent.notify_waiters();
+    }
+    pub fn emails(&self) -> Vec<CapturedEmail> {
+        self.emails.lock().clone()
+    }
+    /// Removes and returns the captured emails
+    pub fn take(&self) -> Vec<CapturedEmail> {
+        std::mem::take(&mut *self.emails.lock())
+    }
+    /// Waits until at least `count` emails have been captured.
+    ///
+    /// Returns what was captured so far if the timeout is hit
+    pub async fn wait_for(&self, count: usize, timeout: Duration) -> Vec<CapturedEmail> {
+        let wait = async {
+            loop {
+                let sent = self.sent.notified();
+                if self.emails.lock().len() >= count {
+                    break;
+                }
+                sent.await;
+            }
+        };
+        let _ = tokio::time::timeout(timeout, wait).await;
+        self.emails()
+    }
+}
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use lettre::{address::Envelope, transport::smtp::authentication::Mechanism};
+    use utils::config::{EmailAuthMechanism, EmailEncryption, EmailSetting, EmailTransport};
+
+    use super::{smtp_mechanism, smtp_port, Transport};
+    use crate::test_support::email_settings;
+
+    /// A directory in the temp directory. Removed on drop
+    struct TestDirectory(PathBuf);
+    impl TestDirectory {
+        fn new(name: &str) -> Self {
+            let directory = std::env::temp_dir().join(format!(
+                "stalwart-panel-transport-{}-{}",
+                std::process::id(),
+                name
+            ));
+            let _ = std::fs::remove_dir_all(&directory);
+            Self(directory)
+        }
+    }
+    impl Drop for TestDirectory {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+    fn settings(transport: EmailTransport) -> EmailSetting {
+        let mut settings = email_settings(&std::env::temp_dir().join("stalwart-panel-unused.redb"));
+        settings.transport = transport;
+        settings
+    }
+    fn envelope() -> Envelope {
+        Envelope::new(
+            Some("panel@example.com".parse().unwrap()),
+            vec!["user@example.com".parse().unwrap()],
+        )
+        .unwrap()
+    }
+    const MESSAGE: &[u8] =
+        b"From: panel@example.com\r\nTo: user@example.com\r\nSubject: Test\r\n\r\nBody\r\n";
+    /// The files in the directory
+    fn files(directory: &std::path::Path) -> Vec<PathBuf> {
+        std::fs::read_dir(directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect()
+    }
+
+    #[actix_rt::test]
+    pub async fn test_smtp_combinations() {
+        let encryptions = [
+            (EmailEncryption::NONE, 25),
+            (EmailEncryption::StartTLS, 587),
+            (EmailEncryption::TLS, 465),
+        ];
+        let mechanisms = [
+            (None, None),
+            (Some(EmailAuthMechanism::Plain), Some(Mechanism::Plain)),
+            (Some(EmailAuthMechanism::Login), Some(Mechanism::Login)),
+            (Some(EmailAuthMechanism::Xoauth2), Some(Mechanism::Xoauth2)),
+        ];
+        for (encryption, default_port) in encryptions {
+            for port in [None, Some(2525)] {
+                for username in ["", "panel"] {
+                    for (auth_mechanism, mechanism) in mechanisms {
+                        let mut email = settings(EmailTransport::Smtp);
+                        email.encryption = encryption.clone();
+                        email.port = port;
+                        email.username = username.to_owned();
+                        email.password = "password".to_owned();
+                        email.auth_mechanism = auth_mechanism;
+                        let combination = format!(
+                            "{:?} {:?} {:?} {:?}",
+                            encryption, port, username, auth_mechanism
+                        );
+
+                        let transport = Transport::new(&email);
+                        assert!(
+                            matches!(transport, Ok(Transport::Smtp(_))),
+                            "{}",
+                            combination
+                        );
+                        assert_eq!(
+                            smtp_port(&email),
+                            port.unwrap_or(default_port),
+                            "{}",
+                            combination
+                        );
+                        assert_eq!(smtp_mechanism(&email), mechanism, "{}", combination);
+                    }
+                }
+            }
+        }
+    }
+    #[actix_rt::test]
+    pub async fn test_missing_ca_bundle() {
+        let mut email = settings(EmailTransport::Smtp);
+        email.encryption = EmailEncryption::TLS;
+        email.ca_bundle = Some(std::env::temp_dir().join("stalwart-panel-missing-ca.pem"));
+        assert!(Transport::new(&email).is_err());
+    }
+    #[actix_rt::test]
+    pub async fn test_file_round_trip() {
+        let directory = TestDirectory::new("file");
+        let transport = Transport::new(&settings(EmailTransport::File {
+            directory: directory.0.clone(),
+        }))
+        .unwrap();
+        assert!(transport.test_connection().await.is_ok());
+        assert!(transport.send_raw(&envelope(), MESSAGE).await.is_ok());
+
+        let files = files(&directory.0);
+        assert_eq!(files.len(), 1, "{:?}", files);
+        assert_eq!(files[0].extension().unwrap(), "eml");
+        assert_eq!(std::fs::read(&files[0]).unwrap(), MESSAGE);
+    }
+    #[actix_rt::test]
+    pub async fn test_maildir_round_trip() {
+        let directory = TestDirectory::new("maildir");
+        let transport = Transport::new(&settings(EmailTransport::Maildir {
+            directory: directory.0.clone(),
+        }))
+        .unwrap();
+        assert!(transport.send_raw(&envelope(), MESSAGE).await.is_ok());
+
+        assert!(files(&directory.0.join("tmp")).is_empty());
+        let delivered = files(&directory.0.join("new"));
+        assert_eq!(delivered.len(), 1);
+        assert_eq!(std::fs::read(&delivered[0]).unwrap(), MESSAGE);
+    }
+    /// Uses a shell script as the sendmail command. It saves what sendmail would have been given
+    #[cfg(unix)]
+    #[actix_rt::test]
+    pub async fn test_sendmail_round_trip() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let directory = TestDirectory::new("sendmail");
+        std::fs::create_dir_all(&directory.0).unwrap();
+        let script = directory.0.join("sendmail");
+        let arguments = directory.0.join("arguments");
+        let message = directory.0.join("message");
+        std::fs::write(
+            &script,
+            format!(
+                "#!/bin/sh\necho \"$@\" > '{}'\ncat > '{}'\n",
+                arguments.display(),
+                message.display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let transport = Transport::new(&settings(EmailTransport::Sendmail {
+            command: Some(script.display().to_string()),
+        }))
+        .unwrap();
+        assert!(transport.send_raw(&envelope(), MESSAGE).await.is_ok());
+
+        assert_eq!(
+            std::fs::read_to_string(&arguments).unwrap().trim(),
+            "-i -f panel@example.com -- user@example.com"
+        );
+        assert_eq!(std::fs::read(&message).unwrap(), MESSAGE);
+    }
+    #[actix_rt::test]
+    pub async fn test_failing_sendmail() {
+        let transport = Transport::new(&settings(EmailTransport::Sendmail {
+            command: Some("/nonexistent/stalwart-panel-sendmail".to_owned()),
+        }))
+        .unwrap();
+        assert!(transport.send_raw(&envelope(), MESSAGE).await.is_err());
+    }
+}
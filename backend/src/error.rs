@@ -1,11 +1,16 @@
 use actix_web::{http::header::ToStrError, ResponseError};
 use either::Either;
+use entities::account::quota::QuotaError;
 use sea_orm::DbErr;
 use serde::{ser::SerializeStruct, Serialize};
 use this_actix_error::ActixError;
 use thiserror::Error;
 
-use crate::auth::session::SessionError;
+use crate::{
+    auth::session::SessionError,
+    email_queue::EmailQueueError,
+    sieve::{SieveClientError, SieveError},
+};
 
 #[derive(Debug, Error, ActixError)]
 pub enum WebsiteError {
@@ -27,9 +32,21 @@ pub enum WebsiteError {
     #[error("Not Found")]
     #[status_code(NOT_FOUND)]
     NotFound,
+    #[error("Conflict: {0}")]
+    #[status_code(CONFLICT)]
+    Conflict(&'static str),
+    #[error("Unable to update Stalwart: {0}")]
+    #[status_code(BAD_GATEWAY)]
+    SieveError(SieveClientError),
     #[error("Unable to hash password")]
     #[status_code(INTERNAL_SERVER_ERROR)]
     UnableToHashPassword,
+    #[error("Unable to render the email: {0}")]
+    #[status_code(INTERNAL_SERVER_ERROR)]
+    TemplateError(#[from] handlebars::RenderError),
+    #[error("Email Queue Error: {0}")]
+    #[status_code(INTERNAL_SERVER_ERROR)]
+    EmailQueueError(#[from] EmailQueueError),
 }
 
 /// Implemented for responses that can partially fail.
@@ -46,6 +63,27 @@ impl From<ToStrError> for WebsiteError {
         Self::BadRequest("Unable to parse Value")
     }
 }
+impl From<QuotaError> for WebsiteError {
+    fn from(error: QuotaError) -> Self {
+        match error {
+            QuotaError::Database(error) => error.into(),
+            QuotaError::AboveGroupMaximum(_) => {
+                Self::BadRequest("Quota is larger than the group maximum")
+            }
+            QuotaError::AboveDomainAllocation { .. } => {
+                Self::BadRequest("Quota is larger than what is left of the domain allocation")
+            }
+        }
+    }
+}
+impl From<SieveError> for WebsiteError {
+    fn from(error: SieveError) -> Self {
+        match error {
+            SieveError::Database(error) => error.into(),
+            SieveError::Client(error) => Self::SieveError(error),
+        }
+    }
+}
 impl From<DbErr> for WebsiteError {
     fn from(error: DbErr) -> Self {
         Self::DatabaseError(Either::Left(error))
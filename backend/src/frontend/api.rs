@@ -1,10 +1,12 @@
+use std::net::SocketAddr;
+
 use actix_web::{
     cookie::{CookieBuilder, Expiration, SameSite},
     get, post, web,
     web::{Data, ServiceConfig},
     HttpRequest, HttpResponse,
 };
-use chrono::Duration;
+use chrono::Local;
 use entities::{account::panel_user::PanelUser, AccountEntity};
 use sea_orm::{prelude::*, ActiveValue, IntoActiveModel};
 use serde::{Deserialize, Serialize};
@@ -13,10 +15,14 @@ use utils::database::Password;
 
 use crate::{
     auth::{
+        backup_email::BackupEmailVerifications,
         password_reset::PasswordResetManager,
         session::{Session, SessionManager},
     },
+    email_queue::QueuedEmailState,
+    email_service::EmailAccess,
     headers::Origin,
+    notifications::{notify_account, notify_panel_user, NewLoginEmail, PasswordChangedEmail},
     DatabaseConnection, Error, Result, SharedConfig,
 };
 
@@ -26,6 +32,8 @@ pub fn init(service: &mut ServiceConfig) {
         .service(request_password_reset)
         .service(verify_password_reset)
         .service(submit_password_reset)
+        .service(confirm_backup_email)
+        .service(health)
         .service(logout);
 }
 
@@ -39,6 +47,31 @@ pub struct LoginResponse {
     panel_user: PanelUser,
     session: Session,
 }
+#[derive(Serialize)]
+pub struct Health {
+    /// `degraded` while emails can not be sent
+    status: &'static str,
+    /// [crate::email_service::TransportState::name]. The full state is under `/api/email-queue/transport`
+    email: &'static str,
+    queued_emails: u64,
+    failed_emails: u64,
+}
+/// The panel can run without the SMTP server. This reports if emails are being sent
+#[get("/health")]
+pub async fn health(email: Data<EmailAccess>) -> Result<HttpResponse> {
+    let transport = email.transport_state();
+    let health = Health {
+        status: if transport.is_connected() {
+            "ok"
+        } else {
+            "degraded"
+        },
+        email: transport.name(),
+        queued_emails: email.queue().count(QueuedEmailState::Queued)?,
+        failed_emails: email.queue().count(QueuedEmailState::Failed)?,
+    };
+    Ok(HttpResponse::Ok().json(health))
+}
 #[get("/logout")]
 pub async fn logout(
     session_manager: Data<SessionManager>,
@@ -55,16 +88,19 @@ pub async fn logout(
 }
 #[post("/login")]
 pub async fn login(
+    request: HttpRequest,
     post: web::Form<LoginRequest>,
     database: DatabaseConnection,
     session_manager: Data<SessionManager>,
+    email: Data<EmailAccess>,
+    settings: Data<SharedConfig>,
 ) -> Result<HttpResponse> {
     let post = post.into_inner();
     let panel_user = PanelUser::get(database.as_ref(), &post.username)
         .await?
         .ok_or(Error::Unauthorized)?;
 
-    if panel_user
+    if !panel_user
         .password
         .check_password(post.password)
         .map_err(|e| {
@@ -74,9 +110,24 @@ pub async fn login(
     {
         return Err(Error::Unauthorized);
     }
-    let duration = Duration::days(1);
+    let session = session_manager.create_session(panel_user.id, session_manager.lifespan())?;
 
-    let session = session_manager.create_session(panel_user.id, duration)?;
+    if let Some(ip) = client_ip(&request, &settings) {
+        match session_manager.remember_ip(panel_user.id, &ip) {
+            Ok(true) => notify_panel_user(
+                &email,
+                &panel_user,
+                NewLoginEmail {
+                    name: panel_user.name.clone(),
+                    username: panel_user.username.clone(),
+                    ip,
+                    time: Local::now().to_rfc2822(),
+                },
+            ),
+            Ok(false) => {}
+            Err(error) => warn!("Failed to remember the login IP: {}", error),
+        }
+    }
 
     let new_cookie = CookieBuilder::new("session", session.session_id.clone())
         .path("/")
@@ -91,10 +142,29 @@ pub async fn login(
     }))
 }
 
+/// The IP of the client without the port.
+///
+/// The `Forwarded` headers are only used when the connection is from a trusted proxy. Anyone else could set them
+fn client_ip(request: &HttpRequest, settings: &SharedConfig) -> Option<String> {
+    let peer = request.peer_addr()?.ip();
+    if !settings.is_trusted_proxy(peer) {
+        return Some(peer.to_string());
+    }
+    let connection_info = request.connection_info();
+    let address = connection_info.realip_remote_addr()?;
+    Some(
+        address
+            .parse::<SocketAddr>()
+            .map(|address| address.ip().to_string())
+            .unwrap_or_else(|_| address.to_owned()),
+    )
+}
+
 #[derive(Deserialize)]
 pub struct PasswordReset {
     pub backup_email: String,
 }
+/// Reset links are only sent to verified backup emails
 #[post("/reset/password/request")]
 pub async fn request_password_reset(
     post: web::Form<PasswordReset>,
@@ -124,7 +194,7 @@ pub async fn verify_password_reset(
     get: web::Path<String>,
     password_reset: Data<PasswordResetManager>,
 ) -> HttpResponse {
-    if password_reset.get_request(get.as_ref()).is_some() {
+    if password_reset.is_valid(get.as_ref()) {
         HttpResponse::NoContent().finish()
     } else {
         HttpResponse::NotFound().finish()
@@ -144,7 +214,7 @@ pub async fn submit_password_reset(
     password_reset: Data<PasswordResetManager>,
     shared_settings: Data<SharedConfig>,
 ) -> Result<HttpResponse> {
-    if let Some(value) = password_reset.get_request(get.as_ref()) {
+    if let Some(value) = password_reset.take_request(get.as_ref()) {
         let password = post.into_inner().password;
 
         let Some(mut user_model) = AccountEntity::find_by_id(value.account_id)
@@ -157,19 +227,47 @@ pub async fn submit_password_reset(
         };
         user_model.require_password_change = ActiveValue::set(false);
         user_model.password = ActiveValue::set(
-            Password::new_hash(password, shared_settings.password_hash).map_err(|e| {
+            Password::new_hash(password, shared_settings.password_hash()).map_err(|e| {
                 warn!("Failed to hash password: {}", e);
                 Error::BadRequest("Failed to hash password")
             })?,
         );
 
-        drop(value);
-        password_reset.remove_request(get.as_ref());
-        AccountEntity::update(user_model)
+        let account = AccountEntity::update(user_model)
             .exec(database.as_ref())
             .await?;
+        notify_account(
+            database.as_ref(),
+            &password_reset.email_access,
+            &account,
+            PasswordChangedEmail {
+                name: account.name.clone(),
+                username: account.username.clone(),
+            },
+        )
+        .await;
         Ok(HttpResponse::NoContent().finish())
     } else {
         Ok(HttpResponse::NotFound().finish())
     }
 }
+
+/// Saves the backup email as verified once the link sent to it is opened.
+///
+/// Sends the password reset link that was waiting on the confirmation
+#[post("/backup-email/confirm/{token}")]
+pub async fn confirm_backup_email(
+    token: web::Path<String>,
+    database: DatabaseConnection,
+    verifications: Data<BackupEmailVerifications>,
+    password_reset: Data<PasswordResetManager>,
+    origin: Origin,
+) -> Result<HttpResponse> {
+    match verifications
+        .confirm(database.as_ref(), &password_reset, token.as_ref(), origin)
+        .await?
+    {
+        Some(_) => Ok(HttpResponse::NoContent().finish()),
+        None => Ok(HttpResponse::NotFound().finish()),
+    }
+}
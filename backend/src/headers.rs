@@ -48,7 +48,7 @@ impl Origin {
         } else if let Some(value) = req.headers().get(HOST) {
             let https = req
                 .app_data::<Data<SharedConfig>>()
-                .map(|v| v.as_ref().https)
+                .map(|v| v.https())
                 .unwrap_or_default();
             let value = value.to_str()?;
             let url = if https {
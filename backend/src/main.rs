@@ -1,33 +1,55 @@
 pub mod api;
 pub mod auth;
+pub mod config_reload;
+pub mod email_queue;
 pub mod email_service;
+pub mod email_transport;
 pub mod error;
 pub mod frontend;
 pub mod headers;
+pub mod notifications;
+pub mod quota;
+pub mod sieve;
+pub mod stalwart_client;
+#[cfg(test)]
+pub mod test_support;
 
-use std::{fs::File, io, io::BufReader, path::PathBuf};
+use std::{
+    fs::File,
+    io,
+    io::BufReader,
+    net::IpAddr,
+    path::{Path, PathBuf},
+};
 
 use actix_cors::Cors;
 use actix_web::{web::Data, App, HttpServer, Scope};
+use ahash::HashMap;
 use clap::Parser;
 pub use error::WebsiteError as Error;
-use parking_lot::Mutex;
+use migration::{Migrator, MigratorTrait};
+use parking_lot::{Mutex, RwLock};
 use sea_orm::{ConnectOptions, Database};
-use tokio::fs::read_to_string;
-use tracing::info;
+use toml_edit::{Document, TomlError};
+use tracing::{error, info};
 use tracing_actix_web::TracingLogger;
 use tracing_subscriber::prelude::*;
 use utils::{
     config::{Settings, TlsConfig},
+    config_loader::{self, AddedSetting},
     database::password::PasswordType,
     stalwart_manager::StalwartManager,
 };
 
 use crate::{
     auth::{
-        middleware::HandleSession, password_reset::PasswordResetManager, session::SessionManager,
+        backup_email::BackupEmailVerifications, middleware::HandleSession,
+        password_reset::PasswordResetManager, session::SessionManager,
     },
+    config_reload::ConfigReloader,
     email_service::EmailService,
+    quota::QuotaUsage,
+    sieve::SieveScripts,
 };
 
 #[cfg(not(any(feature = "rust-tls", feature = "native-tls")))]
@@ -42,9 +64,12 @@ struct Command {
     /// The stalwart-panel config file
     #[clap(short, long, default_value = "stalwart-panel.toml")]
     config: PathBuf,
-    // Comments will be destroyed by TOML
+    /// Adds the missing settings to the config file with their default values. Comments are kept
     #[clap(long, default_value = "false")]
     add_defaults_to_config: bool,
+    /// Checks the config and prints the settings `--add-defaults-to-config` would add without writing them
+    #[clap(long, default_value = "false")]
+    check_config: bool,
 }
 
 pub type DatabaseConnection = Data<sea_orm::DatabaseConnection>;
@@ -52,9 +77,100 @@ pub type DatabaseConnection = Data<sea_orm::DatabaseConnection>;
 /// This could be changed in the future if we need to have multiple readers
 pub type SlalwartManager = Data<Mutex<StalwartManager>>;
 #[derive(Clone)]
-pub struct SharedConfig {
+pub struct PanelSettings {
     password_hash: PasswordType,
     https: bool,
+    default_group: i64,
+    /// Lowercase domain to the bytes it can hand out
+    domain_allocations: HashMap<String, i64>,
+    trusted_proxies: Vec<IpAddr>,
+}
+impl PanelSettings {
+    pub fn new(settings: &Settings) -> Self {
+        Self {
+            password_hash: settings.password_hash_for_new_passwords,
+            https: settings.tls.is_some() || settings.is_https,
+            default_group: settings.default_group,
+            domain_allocations: settings
+                .quota
+                .domain_allocations
+                .iter()
+                .map(|(domain, allocation)| (domain.to_lowercase(), allocation.bytes()))
+                .collect(),
+            trusted_proxies: settings.trusted_proxies.clone(),
+        }
+    }
+}
+/// The settings shared with the handlers. Replaced as a whole when the config is reloaded
+pub struct SharedConfig(RwLock<PanelSettings>);
+impl SharedConfig {
+    pub fn new(settings: &Settings) -> Self {
+        Self(RwLock::new(PanelSettings::new(settings)))
+    }
+    pub fn password_hash(&self) -> PasswordType {
+        self.0.read().password_hash
+    }
+    pub fn https(&self) -> bool {
+        self.0.read().https
+    }
+    pub fn default_group(&self) -> i64 {
+        self.0.read().default_group
+    }
+    /// Lowercase domain to the bytes it can hand out
+    pub fn domain_allocations(&self) -> HashMap<String, i64> {
+        self.0.read().domain_allocations.clone()
+    }
+    pub fn domain_allocation(&self, domain: &str) -> Option<i64> {
+        self.0
+            .read()
+            .domain_allocations
+            .get(domain.to_lowercase().as_str())
+            .copied()
+    }
+    /// If the forwarded headers from this address are trusted
+    pub fn is_trusted_proxy(&self, address: IpAddr) -> bool {
+        self.0.read().trusted_proxies.contains(&address)
+    }
+    pub fn replace(&self, settings: PanelSettings) {
+        *self.0.write() = settings;
+    }
+}
+/// Adds the settings missing from the config file.
+///
+/// Settings from the environment, which may be secrets, are not saved
+fn add_missing_defaults(
+    config: &Path,
+    settings: &Settings,
+    write: bool,
+) -> io::Result<Vec<AddedSetting>> {
+    let invalid = |error: String| io::Error::new(io::ErrorKind::InvalidData, error);
+    let mut document: Document = std::fs::read_to_string(config)?
+        .parse()
+        .map_err(|error: TomlError| invalid(error.to_string()))?;
+    let from_environment = config_loader::environment_keys(std::env::vars());
+    let added = config_loader::add_missing_defaults(&mut document, settings, &from_environment)
+        .map_err(|error| invalid(error.to_string()))?;
+    if write && !added.is_empty() {
+        std::fs::write(config, document.to_string())?;
+    }
+    Ok(added)
+}
+/// Refuses to start on a database with pending migrations. They are applied with `setup-tool migrate up`
+async fn check_migrations(database: &sea_orm::DatabaseConnection) -> io::Result<()> {
+    let pending = Migrator::get_pending_migrations(database)
+        .await
+        .map_err(|error| io::Error::new(io::ErrorKind::Other, error))?;
+    if pending.is_empty() {
+        return Ok(());
+    }
+    for migration in &pending {
+        error!("Migration `{}` has not been applied", migration.name());
+    }
+    error!("Run `setup-tool migrate up` to apply the migrations");
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "The database has pending migrations",
+    ))
 }
 #[actix_web::main]
 async fn main() -> io::Result<()> {
@@ -70,14 +186,34 @@ async fn main() -> io::Result<()> {
 
     let command = Command::parse();
 
-    let config = read_to_string(&command.config).await?;
-
-    let server_config: Settings = toml::from_str(&config).expect("Failed to parse config");
+    let server_config = match Settings::load(&command.config) {
+        Ok(ok) => ok,
+        Err(errors) => {
+            error!("{}", errors);
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "The config is invalid",
+            ));
+        }
+    };
 
+    if command.check_config {
+        let added = add_missing_defaults(&command.config, &server_config, false)?;
+        if added.is_empty() {
+            info!("The config is valid and has every setting");
+        } else {
+            info!("The config is valid. `--add-defaults-to-config` would add:");
+            for added in added {
+                println!("{}", added);
+            }
+        }
+        return Ok(());
+    }
     if command.add_defaults_to_config {
-        info!("`--add-defaults-to-config` was passed, Config will be saved with missing values filled in. This will remove all comments from the config file.");
-        let config = toml::to_string_pretty(&server_config).expect("Failed to serialize config");
-        std::fs::write(command.config, config).expect("Failed to write config");
+        info!("`--add-defaults-to-config` was passed, Config will be saved with missing values filled in.");
+        for added in add_missing_defaults(&command.config, &server_config, true)? {
+            info!("Added `{}`", added.key);
+        }
     }
     let Settings {
         bind_address,
@@ -85,9 +221,9 @@ async fn main() -> io::Result<()> {
         database,
         tls,
         email,
-        password_hash_for_new_passwords,
         session_manager,
-        is_https,
+        quota,
+        sieve,
         ..
     } = server_config.clone();
     info!("Connecting to database `{}`", database.debug_message());
@@ -95,6 +231,7 @@ async fn main() -> io::Result<()> {
         .await
         .map(Data::new)
         .expect("Failed to connect to database");
+    check_migrations(&database).await?;
 
     let session_manager = SessionManager::new(session_manager)
         .map(Data::new)
@@ -104,19 +241,32 @@ async fn main() -> io::Result<()> {
 
     let email = EmailService::start(email)
         .await
-        .expect("Failed to start email service")
         .map(Data::new)
         .expect("Failed to start email service");
 
-    let password_reset = Data::new(PasswordResetManager {
-        email_access: email.clone().into_inner(),
-        requests: Default::default(),
-    });
+    let password_reset = Data::new(PasswordResetManager::new(email.clone().into_inner()));
+
+    let backup_email = Data::new(BackupEmailVerifications::new(email.clone().into_inner()));
+
+    let quota = Data::new(QuotaUsage::new(quota));
+    QuotaUsage::start_alerts(
+        quota.clone().into_inner(),
+        database.clone(),
+        email.clone().into_inner(),
+    );
+
+    let sieve = Data::new(SieveScripts::new(sieve));
+
+    let shared_config = Data::new(SharedConfig::new(&server_config));
 
-    let shared_config = Data::new(SharedConfig {
-        password_hash: password_hash_for_new_passwords,
-        https: if tls.is_some() { true } else { is_https },
-    });
+    let config_reloader = Data::new(ConfigReloader::new(
+        command.config,
+        server_config,
+        shared_config.clone(),
+        email.clone(),
+        session_manager.clone(),
+    ));
+    ConfigReloader::reload_on_sighup(config_reloader.clone());
 
     let server = HttpServer::new(move || {
         let cors = Cors::default()
@@ -131,6 +281,10 @@ async fn main() -> io::Result<()> {
             .app_data(email.clone())
             .app_data(shared_config.clone())
             .app_data(password_reset.clone())
+            .app_data(backup_email.clone())
+            .app_data(quota.clone())
+            .app_data(sieve.clone())
+            .app_data(config_reloader.clone())
             .wrap(TracingLogger::default())
             .wrap(cors)
             .service(
@@ -138,8 +292,16 @@ async fn main() -> io::Result<()> {
                     .wrap(HandleSession(session_manager.clone()))
                     .configure(api::user::init)
                     .service(Scope::new("/accounts").configure(api::accounts::init))
+                    .service(Scope::new("/config").configure(api::config::init))
+                    .service(Scope::new("/domains").configure(api::domains::init))
                     .service(Scope::new("/emails").configure(api::emails::init))
-                    .service(Scope::new("/groups").configure(api::groups::init)),
+                    .service(Scope::new("/email-queue").configure(api::email_queue::init))
+                    .service(Scope::new("/email-templates").configure(api::email_templates::init))
+                    .service(Scope::new("/groups").configure(api::groups::init))
+                    .service(Scope::new("/lists").configure(api::lists::init))
+                    .service(
+                        Scope::new("/shared-mailboxes").configure(api::shared_mailboxes::init),
+                    ),
             )
             .service(Scope::new("/frontend-api").configure(frontend::api::init))
     })
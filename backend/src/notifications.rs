@@ -0,0 +1,267 @@
+//! Emails that tell an account about changes made to it.
+//!
+//! Each one can be turned off with [EmailNotifications]
+use entities::{account::panel_user::PanelUser, emails, AccountModel};
+use sea_orm::{ConnectionTrait, DbErr};
+use serde::Serialize;
+use tracing::{debug, error};
+use utils::{config::EmailNotifications, database::EmailAddress};
+
+use crate::email_service::{template, Email, EmailAccess, EmailDebug};
+
+macro_rules! notification {
+    ($email:ident, $template:expr, $setting:ident, $subject:expr, { $($locale:expr => $localized:expr),* }) => {
+        impl Email for $email {
+            template!($template);
+
+            fn subject() -> &'static str {
+                $subject
+            }
+
+            fn localized_subject(locale: &str) -> Option<&'static str> {
+                match locale {
+                    $($locale => Some($localized),)*
+                    _ => None,
+                }
+            }
+
+            fn enabled(notifications: &EmailNotifications) -> bool {
+                notifications.$setting
+            }
+
+            fn debug_info(self) -> EmailDebug {
+                EmailDebug {
+                    to: self.username,
+                    subject: Self::subject(),
+                }
+            }
+        }
+    };
+}
+
+/// Sent to a new account with how to log in
+#[derive(Debug, Serialize)]
+pub struct WelcomeEmail {
+    pub name: String,
+    pub username: String,
+    pub panel_url: String,
+    pub email_address: Option<EmailAddress>,
+}
+notification!(WelcomeEmail, "welcome", welcome, "Welcome to your new email account", {
+    "de" => "Willkommen bei Ihrem neuen E-Mail-Konto"
+});
+
+#[derive(Debug, Serialize)]
+pub struct PasswordChangedEmail {
+    pub name: String,
+    pub username: String,
+}
+notification!(PasswordChangedEmail, "password_changed", password_changed, "Your password was changed", {
+    "de" => "Ihr Passwort wurde geändert"
+});
+
+#[derive(Debug, Serialize)]
+pub struct AccountDeactivatedEmail {
+    pub name: String,
+    pub username: String,
+}
+notification!(AccountDeactivatedEmail, "account_deactivated", account_deactivated, "Your account was deactivated", {
+    "de" => "Ihr Konto wurde deaktiviert"
+});
+
+#[derive(Debug, Serialize)]
+pub struct AliasAddedEmail {
+    pub name: String,
+    pub username: String,
+    pub alias: EmailAddress,
+}
+notification!(AliasAddedEmail, "alias_added", alias_added, "A new address was added to your account", {
+    "de" => "Ihrem Konto wurde eine neue Adresse hinzugefügt"
+});
+
+/// Sent when an account logs in from an IP it has not used before
+#[derive(Debug, Serialize)]
+pub struct NewLoginEmail {
+    pub name: String,
+    pub username: String,
+    pub ip: String,
+    pub time: String,
+}
+notification!(NewLoginEmail, "new_login", new_login, "New login to your account", {
+    "de" => "Neue Anmeldung bei Ihrem Konto"
+});
+
+/// The verified backup email of the account. Otherwise its primary email
+pub fn panel_user_address(user: &PanelUser) -> Option<EmailAddress> {
+    match (&user.backup_email, &user.backup_email_verified) {
+        (Some(backup_email), Some(_)) => Some(backup_email.clone()),
+        _ => user.primary_email.clone(),
+    }
+}
+/// The verified backup email of the account. Otherwise its primary email
+pub async fn account_address(
+    connection: &impl ConnectionTrait,
+    account: &AccountModel,
+) -> Result<Option<EmailAddress>, DbErr> {
+    if let (Some(backup_email), Some(_)) = (&account.backup_email, &account.backup_email_verified) {
+        return Ok(Some(backup_email.clone()));
+    }
+    let primary = emails::database_helper::get_primary_address(connection, account.id).await?;
+    Ok(primary.map(|email| email.email_address))
+}
+
+/// Sends the email to [panel_user_address]
+pub fn notify_panel_user(email: &EmailAccess, user: &PanelUser, data: impl Email) {
+    match panel_user_address(user) {
+        Some(address) => {
+            email.send_one_fn(address, data);
+        }
+        None => debug!("{} has no address to notify", user.username),
+    }
+}
+/// Sends the email to [account_address]
+///
+/// Called after a change is saved. So a failure is logged instead of failing the request
+///
+/// # Returns
+/// false if the account has no address, its address could not be read or the email could not be queued
+pub async fn notify_account(
+    connection: &impl ConnectionTrait,
+    email: &EmailAccess,
+    account: &AccountModel,
+    data: impl Email,
+) -> bool {
+    match account_address(connection, account).await {
+        Ok(Some(address)) => email.send_one_fn(address, data),
+        Ok(None) => {
+            debug!("{} has no address to notify", account.username);
+            false
+        }
+        Err(error) => {
+            error!(
+                "Unable to read the address to notify {}: {}",
+                account.username, error
+            );
+            false
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use entities::groups::GroupPermissions;
+    use sea_orm::{ActiveModelTrait, ActiveValue, ConnectionTrait, IntoActiveModel};
+
+    use super::{notify_account, PasswordChangedEmail, WelcomeEmail};
+    use crate::test_support::{
+        add_account, add_group, address, database, unfold_quoted_printable, TestEmail,
+    };
+
+    fn welcome() -> WelcomeEmail {
+        WelcomeEmail {
+            name: "Alice".to_owned(),
+            username: "alice".to_owned(),
+            panel_url: "https://panel.example.com".to_owned(),
+            email_address: Some(address("alice@example.com")),
+        }
+    }
+
+    #[actix_rt::test]
+    pub async fn test_welcome_email() {
+        let email = TestEmail::start().await;
+        email
+            .access
+            .send_one_fn(address("alice@example.com"), welcome());
+        let sent = email.take(1).await;
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].to(), vec!["alice@example.com".to_owned()]);
+        assert_eq!(
+            sent[0].header("Subject").as_deref(),
+            Some("Welcome to your new email account")
+        );
+        let message = unfold_quoted_printable(&sent[0].message());
+        assert!(message.contains("Your address is alice@example.com."));
+        assert!(message.contains("Log in to https://panel.example.com with the username alice"));
+    }
+    #[actix_rt::test]
+    pub async fn test_localized_welcome_email() {
+        let email = TestEmail::start_with(|settings| settings.locale = Some("de".to_owned())).await;
+        email
+            .access
+            .send_one_fn(address("alice@example.com"), welcome());
+        let sent = email.take(1).await;
+        assert_eq!(sent.len(), 1);
+        assert_eq!(
+            sent[0].header("Subject").as_deref(),
+            Some("Willkommen bei Ihrem neuen E-Mail-Konto")
+        );
+        assert!(unfold_quoted_printable(&sent[0].message()).contains("Hallo Alice"));
+    }
+    #[actix_rt::test]
+    pub async fn test_disabled_notification() {
+        let email = TestEmail::start_with(|settings| {
+            settings.notifications.password_changed = false;
+        })
+        .await;
+        let changed = || PasswordChangedEmail {
+            name: "Alice".to_owned(),
+            username: "alice".to_owned(),
+        };
+        email
+            .access
+            .send_one_fn(address("alice@example.com"), changed());
+        email.assert_none_sent().await;
+        // Other notifications are still sent
+        email
+            .access
+            .send_one_fn(address("alice@example.com"), welcome());
+        assert_eq!(email.take(1).await.len(), 1);
+    }
+    #[actix_rt::test]
+    pub async fn test_notify_account_prefers_verified_backup_email() {
+        let database = database().await;
+        let group = add_group(&database, "user", GroupPermissions::default()).await;
+        let account = add_account(&database, "alice", group.id).await;
+        let email = TestEmail::start().await;
+        let changed = || PasswordChangedEmail {
+            name: "Alice".to_owned(),
+            username: "alice".to_owned(),
+        };
+
+        // No verified backup email or primary email
+        let mut unverified = account.into_active_model();
+        unverified.backup_email = ActiveValue::Set(Some(address("alice@backup.example.com")));
+        let account = unverified.update(&database).await.unwrap();
+        assert!(!notify_account(&database, &email.access, &account, changed()).await);
+        email.assert_none_sent().await;
+
+        let mut verified = account.into_active_model();
+        verified.backup_email_verified = ActiveValue::Set(Some(chrono::Local::now().into()));
+        let account = verified.update(&database).await.unwrap();
+        assert!(notify_account(&database, &email.access, &account, changed()).await);
+        let sent = email.take(1).await;
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].to(), vec!["alice@backup.example.com".to_owned()]);
+        assert_eq!(
+            sent[0].header("Subject").as_deref(),
+            Some("Your password was changed")
+        );
+    }
+    #[actix_rt::test]
+    pub async fn test_notify_account_database_error() {
+        let database = database().await;
+        let group = add_group(&database, "user", GroupPermissions::default()).await;
+        let account = add_account(&database, "alice", group.id).await;
+        let email = TestEmail::start().await;
+        // The primary email can no longer be read
+        database
+            .execute_unprepared("DROP TABLE emails")
+            .await
+            .unwrap();
+        let changed = PasswordChangedEmail {
+            name: "Alice".to_owned(),
+            username: "alice".to_owned(),
+        };
+        assert!(!notify_account(&database, &email.access, &account, changed).await);
+        email.assert_none_sent().await;
+    }
+}
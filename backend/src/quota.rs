@@ -0,0 +1,473 @@
+use std::{sync::Arc, time::Instant};
+
+use actix_web::web::Data;
+use ahash::HashMap;
+use entities::{
+    account::{quota::check_domain_allocation, Column as AccountColumn},
+    quota_alerts::database_helper::{get_alerted, set_alerted},
+    AccountEntity, GroupModel,
+};
+use futures_util::{stream, StreamExt};
+use parking_lot::Mutex;
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
+use serde::Serialize;
+use tracing::{debug, error, info, warn};
+use utils::{config::QuotaSettings, database::EmailAddress};
+
+use crate::{
+    email_service::{template, Email, EmailAccess, EmailDebug},
+    notifications::notify_account,
+    stalwart_client::{new_client, StalwartClient},
+    SharedConfig,
+};
+
+/// How many accounts [QuotaUsage::get_many] asks Stalwart about at once
+const MAX_CONCURRENT_LOOKUPS: usize = 8;
+
+#[derive(Debug, Serialize)]
+pub struct QuotaAlertEmail {
+    pub name: String,
+    pub username: String,
+    pub used: i64,
+    pub limit: i64,
+    pub percent: i64,
+}
+impl Email for QuotaAlertEmail {
+    template!("quota_alert");
+
+    fn subject() -> &'static str {
+        "Your mailbox is almost full"
+    }
+
+    fn debug_info(self) -> EmailDebug {
+        EmailDebug {
+            to: self.username,
+            subject: Self::subject(),
+        }
+    }
+}
+
+/// Storage usage from Stalwart. Cached for [QuotaSettings::cache_lifespan]
+#[derive(Debug)]
+pub struct QuotaUsage {
+    client: Option<Box<dyn StalwartClient>>,
+    cache: Mutex<HashMap<String, (Instant, i64)>>,
+    settings: QuotaSettings,
+}
+impl QuotaUsage {
+    pub fn new(settings: QuotaSettings) -> Self {
+        Self::with_client(settings.stalwart_api.clone().map(new_client), settings)
+    }
+    pub fn with_client(client: Option<Box<dyn StalwartClient>>, settings: QuotaSettings) -> Self {
+        Self {
+            client,
+            cache: Default::default(),
+            settings,
+        }
+    }
+    /// The bytes used by the account. None if usage reporting is disabled or Stalwart could not tell us
+    pub async fn get(&self, username: &str) -> Option<i64> {
+        let client = self.client.as_ref()?;
+        let lifespan = self.settings.cache_lifespan.to_std().unwrap_or_default();
+        if let Some((fetched, used)) = self.cache.lock().get(username) {
+            if fetched.elapsed() < lifespan {
+                return Some(*used);
+            }
+        }
+        match client.get_used_quota(username).await {
+            Ok(Some(used)) => {
+                self.cache
+                    .lock()
+                    .insert(username.to_owned(), (Instant::now(), used));
+                Some(used)
+            }
+            Ok(None) => {
+                debug!("Stalwart does not know about {}", username);
+                None
+            }
+            Err(error) => {
+                warn!("Unable to get the quota usage of {}: {}", username, error);
+                None
+            }
+        }
+    }
+    /// Gets the usage of many accounts. At most [MAX_CONCURRENT_LOOKUPS] are asked for at once
+    pub async fn get_many<'a>(&self, usernames: impl Iterator<Item = &'a str>) -> Vec<Option<i64>> {
+        let mut usage: Vec<(usize, Option<i64>)> = stream::iter(usernames.enumerate())
+            .map(|(index, username)| async move { (index, self.get(username).await) })
+            .buffer_unordered(MAX_CONCURRENT_LOOKUPS)
+            .collect()
+            .await;
+        usage.sort_unstable_by_key(|(index, _)| *index);
+        usage.into_iter().map(|(_, used)| used).collect()
+    }
+
+    /// Checks the usage of every active account on an interval.
+    ///
+    /// An email is sent to the verified backup email or the primary email the first time an account passes each threshold
+    pub fn start_alerts(
+        this: Arc<Self>,
+        database: Data<sea_orm::DatabaseConnection>,
+        email: Arc<EmailAccess>,
+    ) {
+        if this.client.is_none() || this.settings.alert_thresholds.is_empty() {
+            return;
+        }
+        actix_rt::spawn(async move {
+            let how_often = this
+                .settings
+                .alert_check_interval
+                .to_std()
+                .expect("Duration is too large");
+            loop {
+                info!("Checking quota usage");
+                if let Err(err) = this.check_alerts(database.as_ref(), &email).await {
+                    error!("Failed to check quota usage: {:?}", err);
+                }
+                actix_rt::time::sleep(how_often).await;
+            }
+        });
+    }
+    async fn check_alerts(
+        &self,
+        database: &sea_orm::DatabaseConnection,
+        email: &EmailAccess,
+    ) -> Result<(), sea_orm::DbErr> {
+        let accounts = AccountEntity::find()
+            .filter(
+                AccountColumn::Active
+                    .eq(true)
+                    .and(AccountColumn::Quota.gt(0)),
+            )
+            .all(database)
+            .await?;
+        for account in accounts {
+            let Some(used) = self.get(&account.username).await else {
+                continue;
+            };
+            let percent = used.saturating_mul(100) / account.quota;
+            let threshold = self
+                .settings
+                .alert_thresholds
+                .iter()
+                .copied()
+                .filter(|threshold| percent >= *threshold as i64)
+                .max();
+            let previous = get_alerted(database, account.id).await?;
+            let raised = match (threshold, previous) {
+                (Some(threshold), Some(previous)) => threshold > previous,
+                (Some(_), None) => true,
+                (None, _) => false,
+            };
+            if raised {
+                debug!(
+                    "{} has used {}% of their quota. Sending an alert",
+                    account.username, percent
+                );
+                let sent = notify_account(
+                    database,
+                    email,
+                    &account,
+                    QuotaAlertEmail {
+                        name: account.name.clone(),
+                        username: account.username.clone(),
+                        used,
+                        limit: account.quota,
+                        percent,
+                    },
+                )
+                .await;
+                // Saved only once the alert is queued so the next check tries again
+                if !sent {
+                    warn!(
+                        "Unable to send the quota alert to {}. Trying again on the next check",
+                        account.username
+                    );
+                    continue;
+                }
+            }
+            // Also lowered when the usage drops so the alert is sent again
+            if previous != threshold {
+                set_alerted(database, account.id, threshold).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Checks the quota against the maximum of the group and the allocation of the domain
+///
+/// # Arguments
+/// account - The account being changed. None for new accounts
+/// primary_email - The primary email of the account. Decides which domain allocation applies
+pub async fn check_quota(
+    database: &sea_orm::DatabaseConnection,
+    settings: &SharedConfig,
+    group: &GroupModel,
+    account: Option<i64>,
+    primary_email: Option<&EmailAddress>,
+    quota: i64,
+) -> crate::Result<()> {
+    group.check_quota(quota)?;
+    let domain = primary_email.and_then(|email| email.split_once('@').map(|(_, domain)| domain));
+    if let Some(domain) = domain {
+        if let Some(allocation) = settings.domain_allocation(domain) {
+            check_domain_allocation(database, domain, allocation, account, quota).await?;
+        }
+    }
+    Ok(())
+}
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::{Duration, Local};
+    use entities::{
+        emails::EmailType, groups::GroupPermissions, AccountModel, EmailActiveModel, EmailEntity,
+    };
+    use futures_util::future::BoxFuture;
+    use sea_orm::{
+        ActiveModelTrait, ActiveValue, DatabaseConnection, EntityTrait, IntoActiveModel,
+    };
+    use utils::{config::QuotaSettings, quota_size::QuotaSize};
+
+    use super::{check_quota, get_alerted, QuotaUsage};
+    use crate::{
+        stalwart_client::{MockStalwartClient, StalwartClient, StalwartClientError},
+        test_support::{
+            add_account, add_group, address, database, settings, unfold_quoted_printable, TestEmail,
+        },
+        SharedConfig,
+    };
+
+    /// Lets the test change the usage after the client is handed to [QuotaUsage]
+    impl StalwartClient for Arc<MockStalwartClient> {
+        fn get_used_quota<'a>(
+            &'a self,
+            username: &'a str,
+        ) -> BoxFuture<'a, Result<Option<i64>, StalwartClientError>> {
+            self.as_ref().get_used_quota(username)
+        }
+    }
+    fn quota_usage(settings: QuotaSettings) -> (QuotaUsage, Arc<MockStalwartClient>) {
+        let client = Arc::new(MockStalwartClient::default());
+        let usage = QuotaUsage::with_client(Some(Box::new(client.clone())), settings);
+        (usage, client)
+    }
+    fn set_used(client: &MockStalwartClient, account: &AccountModel, used: i64) {
+        client.usage.lock().insert(account.username.clone(), used);
+    }
+    /// An account with a quota of 1000 bytes and a verified backup email
+    async fn limited_account(database: &DatabaseConnection) -> AccountModel {
+        let group = add_group(database, "user", GroupPermissions::default()).await;
+        let account = add_account(database, "alice", group.id).await;
+        let mut account = account.into_active_model();
+        account.quota = ActiveValue::Set(1000);
+        account.backup_email = ActiveValue::Set(Some(address("alice@backup.example.com")));
+        account.backup_email_verified = ActiveValue::Set(Some(Local::now().into()));
+        account.update(database).await.unwrap()
+    }
+    /// An account with the quota and a primary email of `{username}@{domain}`
+    async fn account_on_domain(
+        database: &DatabaseConnection,
+        group_id: i64,
+        username: &str,
+        domain: &str,
+        quota: i64,
+    ) -> AccountModel {
+        let mut account = add_account(database, username, group_id)
+            .await
+            .into_active_model();
+        account.quota = ActiveValue::Set(quota);
+        let account = account.update(database).await.unwrap();
+        let email = EmailActiveModel {
+            id: ActiveValue::NotSet,
+            account: ActiveValue::Set(account.id),
+            email_address: ActiveValue::Set(address(&format!("{username}@{domain}"))),
+            email_type: ActiveValue::Set(EmailType::Primary),
+            created: entities::now(),
+        };
+        EmailEntity::insert(email).exec(database).await.unwrap();
+        account
+    }
+
+    #[actix_rt::test]
+    pub async fn test_cache() {
+        let (usage, client) = quota_usage(QuotaSettings {
+            cache_lifespan: Duration::hours(1),
+            ..Default::default()
+        });
+        client.usage.lock().insert("alice".to_owned(), 100);
+        assert_eq!(usage.get("alice").await, Some(100));
+        client.usage.lock().insert("alice".to_owned(), 200);
+        assert_eq!(usage.get("alice").await, Some(100));
+        assert_eq!(usage.get("unknown").await, None);
+
+        let (usage, client) = quota_usage(QuotaSettings {
+            cache_lifespan: Duration::zero(),
+            ..Default::default()
+        });
+        client.usage.lock().insert("alice".to_owned(), 100);
+        assert_eq!(usage.get("alice").await, Some(100));
+        client.usage.lock().insert("alice".to_owned(), 200);
+        assert_eq!(usage.get("alice").await, Some(200));
+    }
+    #[actix_rt::test]
+    pub async fn test_get_many_keeps_order() {
+        let (usage, client) = quota_usage(QuotaSettings::default());
+        let usernames: Vec<String> = (0..20).map(|i| format!("user{}", i)).collect();
+        for (used, username) in usernames.iter().enumerate().skip(1) {
+            client.usage.lock().insert(username.clone(), used as i64);
+        }
+        let used = usage.get_many(usernames.iter().map(String::as_str)).await;
+        let expected: Vec<_> = (0..20).map(|i| (i > 0).then_some(i)).collect();
+        assert_eq!(used, expected);
+    }
+    #[actix_rt::test]
+    pub async fn test_alerts() {
+        let database = database().await;
+        let account = limited_account(&database).await;
+        let email = TestEmail::start().await;
+        let settings = QuotaSettings {
+            cache_lifespan: Duration::zero(),
+            alert_thresholds: vec![80, 95],
+            ..Default::default()
+        };
+        let (usage, client) = quota_usage(settings.clone());
+
+        set_used(&client, &account, 500);
+        usage.check_alerts(&database, &email.access).await.unwrap();
+        email.assert_none_sent().await;
+
+        set_used(&client, &account, 850);
+        usage.check_alerts(&database, &email.access).await.unwrap();
+        let sent = email.take(1).await;
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].to(), vec!["alice@backup.example.com".to_owned()]);
+        assert!(unfold_quoted_printable(&sent[0].message()).contains("85%"));
+        usage.check_alerts(&database, &email.access).await.unwrap();
+        email.assert_none_sent().await;
+
+        // A restart remembers the alert
+        let (usage, client) = quota_usage(settings);
+        set_used(&client, &account, 850);
+        usage.check_alerts(&database, &email.access).await.unwrap();
+        email.assert_none_sent().await;
+
+        set_used(&client, &account, 990);
+        usage.check_alerts(&database, &email.access).await.unwrap();
+        assert_eq!(email.take(1).await.len(), 1);
+
+        // Dropping below every threshold alerts again next time
+        set_used(&client, &account, 100);
+        usage.check_alerts(&database, &email.access).await.unwrap();
+        set_used(&client, &account, 900);
+        usage.check_alerts(&database, &email.access).await.unwrap();
+        assert_eq!(email.take(1).await.len(), 1);
+    }
+    #[actix_rt::test]
+    pub async fn test_unsent_alert_is_retried() {
+        let database = database().await;
+        let account = limited_account(&database).await;
+        // Nowhere to send the alert to
+        let mut unverified = account.into_active_model();
+        unverified.backup_email_verified = ActiveValue::Set(None);
+        let account = unverified.update(&database).await.unwrap();
+        let email = TestEmail::start().await;
+        let (usage, client) = quota_usage(QuotaSettings {
+            cache_lifespan: Duration::zero(),
+            alert_thresholds: vec![80],
+            ..Default::default()
+        });
+
+        set_used(&client, &account, 900);
+        usage.check_alerts(&database, &email.access).await.unwrap();
+        email.assert_none_sent().await;
+        assert_eq!(get_alerted(&database, account.id).await.unwrap(), None);
+
+        let mut verified = account.into_active_model();
+        verified.backup_email_verified = ActiveValue::Set(Some(Local::now().into()));
+        let account = verified.update(&database).await.unwrap();
+        usage.check_alerts(&database, &email.access).await.unwrap();
+        assert_eq!(email.take(1).await.len(), 1);
+        assert_eq!(get_alerted(&database, account.id).await.unwrap(), Some(80));
+    }
+    #[actix_rt::test]
+    pub async fn test_check_quota() {
+        let database = database().await;
+        let mut settings = settings();
+        settings
+            .quota
+            .domain_allocations
+            .insert("Example.com".to_owned(), QuotaSize(3000));
+        let config = SharedConfig::new(&settings);
+        let group = add_group(&database, "user", GroupPermissions::default()).await;
+        let alice = account_on_domain(&database, group.id, "alice", "example.com", 1000).await;
+        account_on_domain(&database, group.id, "bob", "example.com", 1000).await;
+        account_on_domain(&database, group.id, "carol", "other.com", 5000).await;
+        let is_rejected =
+            |result: crate::Result<()>| matches!(result, Err(crate::Error::BadRequest(_)));
+
+        let new = address("new@example.com");
+        assert!(
+            check_quota(&database, &config, &group, None, Some(&new), 1000)
+                .await
+                .is_ok()
+        );
+        assert!(is_rejected(
+            check_quota(&database, &config, &group, None, Some(&new), 1001).await
+        ));
+        // Unlimited never fits in an allocation
+        assert!(is_rejected(
+            check_quota(&database, &config, &group, None, Some(&new), 0).await
+        ));
+        // The current quota of the account is replaced
+        let alice_email = address("alice@example.com");
+        assert!(check_quota(
+            &database,
+            &config,
+            &group,
+            Some(alice.id),
+            Some(&alice_email),
+            2000
+        )
+        .await
+        .is_ok());
+        assert!(is_rejected(
+            check_quota(
+                &database,
+                &config,
+                &group,
+                Some(alice.id),
+                Some(&alice_email),
+                2001
+            )
+            .await
+        ));
+        // Domains without an allocation and accounts without a primary email are only limited by the group
+        let other = address("new@other.com");
+        assert!(
+            check_quota(&database, &config, &group, None, Some(&other), 0)
+                .await
+                .is_ok()
+        );
+        assert!(check_quota(&database, &config, &group, None, None, 0)
+            .await
+            .is_ok());
+
+        let mut limited = group.into_active_model();
+        limited.max_quota = ActiveValue::Set(Some(1500));
+        let limited = limited.update(&database).await.unwrap();
+        assert!(
+            check_quota(&database, &config, &limited, None, Some(&other), 1500)
+                .await
+                .is_ok()
+        );
+        assert!(is_rejected(
+            check_quota(&database, &config, &limited, None, Some(&other), 1501).await
+        ));
+        assert!(is_rejected(
+            check_quota(&database, &config, &limited, None, None, 0).await
+        ));
+    }
+}
@@ -0,0 +1,440 @@
+//! The Sieve script the panel manages in each account on Stalwart.
+//!
+//! Sieve only runs one active script per account so every rule the panel manages is generated into one script
+use std::fmt::{Debug, Write};
+
+use ahash::HashMap;
+use entities::{
+    forwarding::database_helper::get_forwarding, vacation::database_helper::get_vacation,
+    ForwardingModel, VacationModel,
+};
+use futures_util::future::BoxFuture;
+use parking_lot::Mutex;
+use reqwest::StatusCode;
+use sea_orm::{ConnectionTrait, DbErr};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use thiserror::Error;
+use tracing::debug;
+use utils::config::{JmapSieveApi, SieveApi, SieveSettings};
+
+/// The name of the script in Stalwart
+pub const SCRIPT_NAME: &str = "stalwart-panel";
+const SIEVE_CAPABILITY: &str = "urn:ietf:params:jmap:sieve";
+
+#[derive(Debug, Error)]
+pub enum SieveClientError {
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+    #[error("Stalwart responded with {0}")]
+    UnexpectedStatus(StatusCode),
+    #[error("Stalwart rejected the request: {0}")]
+    Rejected(String),
+}
+#[derive(Debug, Error)]
+pub enum SieveError {
+    #[error(transparent)]
+    Database(#[from] DbErr),
+    #[error(transparent)]
+    Client(#[from] SieveClientError),
+}
+
+/// Changes the Sieve scripts of the accounts in Stalwart
+pub trait SieveClient: Debug + Send + Sync {
+    /// Replaces the script and makes it the active script of the account.
+    ///
+    /// None removes the script
+    fn set_script<'a>(
+        &'a self,
+        username: &'a str,
+        script: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<(), SieveClientError>>;
+}
+
+pub fn new_client(api: SieveApi) -> Box<dyn SieveClient> {
+    match api {
+        SieveApi::Jmap(settings) => Box::new(JmapSieveClient::new(settings)),
+        SieveApi::Mock => Box::<MockSieveClient>::default(),
+    }
+}
+
+/// Generates the script for the account. None if the account has nothing to run
+pub fn generate_script(
+    forwarding: Option<&ForwardingModel>,
+    vacation: Option<&VacationModel>,
+) -> Option<String> {
+    let forwarding = forwarding.filter(|forwarding| !forwarding.destinations.0.is_empty());
+    let vacation = vacation.filter(|vacation| vacation.enabled);
+    if forwarding.is_none() && vacation.is_none() {
+        return None;
+    }
+    let mut requires = Vec::new();
+    let mut body = String::new();
+    if let Some(vacation) = vacation {
+        requires.push("vacation");
+        let mut conditions = Vec::new();
+        if let Some(start) = vacation.start_date {
+            conditions.push(format!("currentdate :value \"ge\" \"date\" \"{}\"", start));
+        }
+        if let Some(end) = vacation.end_date {
+            conditions.push(format!("currentdate :value \"le\" \"date\" \"{}\"", end));
+        }
+        let action = vacation_action(vacation);
+        if conditions.is_empty() {
+            body.push_str(&action);
+        } else {
+            requires.extend(["date", "relational"]);
+            writeln!(body, "if allof({}) {{\n{}}}", conditions.join(", "), action).unwrap();
+        }
+    }
+    if let Some(forwarding) = forwarding {
+        for destination in &forwarding.destinations.0 {
+            writeln!(body, "redirect {};", quote(destination.as_ref())).unwrap();
+        }
+        if forwarding.keep_copy {
+            body.push_str("keep;\n");
+        }
+    }
+    let mut script = String::from("# Generated by stalwart-panel. Changes will be overwritten\n");
+    if !requires.is_empty() {
+        let requires: Vec<_> = requires.into_iter().map(quote).collect();
+        writeln!(script, "require [{}];", requires.join(", ")).unwrap();
+    }
+    script.push_str(&body);
+    Some(script)
+}
+/// The `vacation` action. The reply is sent as `multipart/alternative` if it has an HTML body
+fn vacation_action(vacation: &VacationModel) -> String {
+    let mut action = format!("vacation :days {}", vacation.reply_interval);
+    let subject = vacation.subject.replace(['\r', '\n'], " ");
+    if !subject.trim().is_empty() {
+        write!(action, " :subject {}", quote(subject.trim())).unwrap();
+    }
+    let reply = match &vacation.html_body {
+        Some(html) => {
+            action.push_str(" :mime");
+            format!(
+                "Content-Type: multipart/alternative; boundary=\"{boundary}\"\n\n\
+                 --{boundary}\nContent-Type: text/plain; charset=utf-8\n\n{}\n\
+                 --{boundary}\nContent-Type: text/html; charset=utf-8\n\n{}\n\
+                 --{boundary}--",
+                vacation.text_body,
+                html,
+                boundary = MIME_BOUNDARY
+            )
+        }
+        None => vacation.text_body.clone(),
+    };
+    writeln!(action, " {};", multi_line(&reply)).unwrap();
+    action
+}
+const MIME_BOUNDARY: &str = "stalwart-panel-vacation";
+/// Makes the value a Sieve quoted string
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+/// Makes the value a Sieve multi-line string. Lines starting with a dot are dot-stuffed
+fn multi_line(value: &str) -> String {
+    let mut string = String::from("text:\n");
+    for line in value.lines() {
+        if line.starts_with('.') {
+            string.push('.');
+        }
+        string.push_str(line);
+        string.push('\n');
+    }
+    string.push_str(".\n");
+    string
+}
+
+/// Keeps the scripts in Stalwart up to date with the database
+#[derive(Debug)]
+pub struct SieveScripts {
+    client: Option<Box<dyn SieveClient>>,
+}
+impl SieveScripts {
+    pub fn new(settings: SieveSettings) -> Self {
+        Self::with_client(settings.api.map(new_client))
+    }
+    pub fn with_client(client: Option<Box<dyn SieveClient>>) -> Self {
+        Self { client }
+    }
+    /// Regenerates the script of the account and pushes it to Stalwart.
+    ///
+    /// Call this inside the transaction that changed the rules so they are rolled back if Stalwart fails
+    pub async fn update_account(
+        &self,
+        connection: &impl ConnectionTrait,
+        account: i64,
+        username: &str,
+    ) -> Result<(), SieveError> {
+        let Some(client) = self.client.as_ref() else {
+            debug!("No Sieve API is configured. Not updating {}", username);
+            return Ok(());
+        };
+        let forwarding = get_forwarding(connection, account).await?;
+        let vacation = get_vacation(connection, account).await?;
+        let script = generate_script(forwarding.as_ref(), vacation.as_ref());
+        client.set_script(username, script.as_deref()).await?;
+        Ok(())
+    }
+}
+
+/// Uses JMAP for Sieve Scripts (RFC 9661) while logged in as the account through the master user
+#[derive(Debug)]
+pub struct JmapSieveClient {
+    client: reqwest::Client,
+    settings: JmapSieveApi,
+}
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JmapSession {
+    api_url: String,
+    upload_url: String,
+    primary_accounts: HashMap<String, String>,
+}
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JmapUpload {
+    blob_id: String,
+}
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JmapResponse {
+    method_responses: Vec<(String, Value, String)>,
+}
+impl JmapSieveClient {
+    pub fn new(settings: JmapSieveApi) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            settings,
+        }
+    }
+    fn request(
+        &self,
+        method: reqwest::Method,
+        url: &str,
+        username: &str,
+    ) -> reqwest::RequestBuilder {
+        self.client.request(method, url).basic_auth(
+            format!("{}%{}", username, self.settings.master_user),
+            Some(&self.settings.master_password),
+        )
+    }
+    async fn call(
+        &self,
+        session: &JmapSession,
+        username: &str,
+        method_calls: Value,
+    ) -> Result<Vec<(String, Value, String)>, SieveClientError> {
+        let response = self
+            .request(reqwest::Method::POST, &session.api_url, username)
+            .json(&json!({
+                "using": ["urn:ietf:params:jmap:core", SIEVE_CAPABILITY],
+                "methodCalls": method_calls,
+            }))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(SieveClientError::UnexpectedStatus(response.status()));
+        }
+        let response: JmapResponse = response.json().await?;
+        for (name, arguments, _) in &response.method_responses {
+            if name == "error" {
+                return Err(SieveClientError::Rejected(arguments.to_string()));
+            }
+            for key in ["notCreated", "notUpdated", "notDestroyed"] {
+                if let Some(errors) = arguments.get(key).filter(|errors| !errors.is_null()) {
+                    return Err(SieveClientError::Rejected(errors.to_string()));
+                }
+            }
+        }
+        Ok(response.method_responses)
+    }
+    async fn set_script_inner(
+        &self,
+        username: &str,
+        script: Option<&str>,
+    ) -> Result<(), SieveClientError> {
+        let session_url = format!(
+            "{}/.well-known/jmap",
+            self.settings.url.trim_end_matches('/')
+        );
+        let response = self
+            .request(reqwest::Method::GET, &session_url, username)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(SieveClientError::UnexpectedStatus(response.status()));
+        }
+        let session: JmapSession = response.json().await?;
+        let account_id = session
+            .primary_accounts
+            .get(SIEVE_CAPABILITY)
+            .cloned()
+            .ok_or_else(|| SieveClientError::Rejected("Sieve is not enabled".to_owned()))?;
+
+        let query = self
+            .call(
+                &session,
+                username,
+                json!([[
+                    "SieveScript/query",
+                    { "accountId": account_id, "filter": { "name": SCRIPT_NAME } },
+                    "0"
+                ]]),
+            )
+            .await?;
+        let existing = query
+            .first()
+            .and_then(|(_, arguments, _)| arguments["ids"].get(0))
+            .and_then(Value::as_str)
+            .map(str::to_owned);
+
+        let Some(script) = script else {
+            if let Some(existing) = existing {
+                // An active script can not be destroyed
+                self.call(
+                    &session,
+                    username,
+                    json!([
+                        ["SieveScript/set", { "accountId": account_id, "onSuccessDeactivateScript": true }, "0"],
+                        ["SieveScript/set", { "accountId": account_id, "destroy": [existing] }, "1"]
+                    ]),
+                )
+                .await?;
+            }
+            return Ok(());
+        };
+
+        let upload_url = session.upload_url.replace("{accountId}", &account_id);
+        let response = self
+            .request(reqwest::Method::POST, &upload_url, username)
+            .header(reqwest::header::CONTENT_TYPE, "application/sieve")
+            .body(script.to_owned())
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(SieveClientError::UnexpectedStatus(response.status()));
+        }
+        let upload: JmapUpload = response.json().await?;
+        let set = match existing {
+            Some(existing) => {
+                let mut update = Map::new();
+                update.insert(existing.clone(), json!({ "blobId": upload.blob_id }));
+                json!({
+                    "accountId": account_id,
+                    "update": update,
+                    "onSuccessActivateScript": existing,
+                })
+            }
+            None => json!({
+                "accountId": account_id,
+                "create": { "panel": { "name": SCRIPT_NAME, "blobId": upload.blob_id } },
+                "onSuccessActivateScript": "#panel",
+            }),
+        };
+        self.call(&session, username, json!([["SieveScript/set", set, "0"]]))
+            .await?;
+        Ok(())
+    }
+}
+impl SieveClient for JmapSieveClient {
+    fn set_script<'a>(
+        &'a self,
+        username: &'a str,
+        script: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<(), SieveClientError>> {
+        Box::pin(self.set_script_inner(username, script))
+    }
+}
+
+/// Keeps the active script of each account in memory
+#[derive(Debug, Default)]
+pub struct MockSieveClient {
+    pub scripts: Mutex<HashMap<String, String>>,
+}
+impl SieveClient for MockSieveClient {
+    fn set_script<'a>(
+        &'a self,
+        username: &'a str,
+        script: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<(), SieveClientError>> {
+        let mut scripts = self.scripts.lock();
+        match script {
+            Some(script) => scripts.insert(username.to_owned(), script.to_owned()),
+            None => scripts.remove(username),
+        };
+        Box::pin(async { Ok(()) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use entities::{forwarding::Destinations, ForwardingModel, VacationModel};
+    use utils::database::EmailAddress;
+
+    use super::{generate_script, MockSieveClient, SieveClient};
+
+    fn vacation() -> VacationModel {
+        VacationModel {
+            id: 1,
+            account: 1,
+            enabled: true,
+            start_date: NaiveDate::from_ymd_opt(2024, 7, 1),
+            end_date: NaiveDate::from_ymd_opt(2024, 7, 14),
+            subject: "Out of \"office\"".to_owned(),
+            text_body: "I am away.\n.Back soon".to_owned(),
+            html_body: None,
+            reply_interval: 3,
+            created: chrono::Utc::now().into(),
+        }
+    }
+    #[test]
+    pub fn test_generate_script() {
+        assert_eq!(generate_script(None, None), None);
+        let forwarding = ForwardingModel {
+            id: 1,
+            account: 1,
+            destinations: Destinations(vec![EmailAddress::new("away@example.org").unwrap()]),
+            keep_copy: true,
+            created: chrono::Utc::now().into(),
+        };
+        let script = generate_script(Some(&forwarding), Some(&vacation())).unwrap();
+        assert!(script.contains("require [\"vacation\", \"date\", \"relational\"];\n"));
+        assert!(script.contains(
+            "if allof(currentdate :value \"ge\" \"date\" \"2024-07-01\", currentdate :value \"le\" \"date\" \"2024-07-14\") {\n\
+             vacation :days 3 :subject \"Out of \\\"office\\\"\" text:\nI am away.\n..Back soon\n.\n;\n}"
+        ));
+        assert!(script.ends_with("redirect \"away@example.org\";\nkeep;\n"));
+
+        let disabled = VacationModel {
+            enabled: false,
+            ..vacation()
+        };
+        assert_eq!(generate_script(None, Some(&disabled)), None);
+    }
+    #[test]
+    pub fn test_html_reply() {
+        let vacation = VacationModel {
+            start_date: None,
+            end_date: None,
+            html_body: Some("<p>I am away</p>".to_owned()),
+            ..vacation()
+        };
+        let script = generate_script(None, Some(&vacation)).unwrap();
+        assert!(script.contains("require [\"vacation\"];\nvacation :days 3"));
+        assert!(script.contains(":mime text:\nContent-Type: multipart/alternative;"));
+        assert!(script.contains("Content-Type: text/html; charset=utf-8\n\n<p>I am away</p>\n"));
+    }
+    #[tokio::test]
+    pub async fn test_mock_client() {
+        let client = MockSieveClient::default();
+        let script = generate_script(None, Some(&vacation())).unwrap();
+        client.set_script("alice", Some(&script)).await.unwrap();
+        assert_eq!(client.scripts.lock().get("alice"), Some(&script));
+        client.set_script("alice", None).await.unwrap();
+        assert!(client.scripts.lock().is_empty());
+    }
+}
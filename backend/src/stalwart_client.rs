@@ -0,0 +1,145 @@
+use std::fmt::Debug;
+
+use ahash::HashMap;
+use futures_util::future::BoxFuture;
+use parking_lot::Mutex;
+use reqwest::{StatusCode, Url};
+use serde::Deserialize;
+use thiserror::Error;
+use utils::config::{StalwartApi, StalwartHttpApi};
+
+#[derive(Debug, Error)]
+pub enum StalwartClientError {
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+    #[error("Stalwart responded with {0}")]
+    UnexpectedStatus(StatusCode),
+    #[error("Invalid Stalwart url {0}")]
+    InvalidUrl(String),
+}
+
+/// Asks Stalwart about the accounts it is storing mail for
+pub trait StalwartClient: Debug + Send + Sync {
+    /// The number of bytes the account is using.
+    ///
+    /// None if Stalwart does not know about the account
+    fn get_used_quota<'a>(
+        &'a self,
+        username: &'a str,
+    ) -> BoxFuture<'a, Result<Option<i64>, StalwartClientError>>;
+}
+
+pub fn new_client(api: StalwartApi) -> Box<dyn StalwartClient> {
+    match api {
+        StalwartApi::Http(settings) => Box::new(HttpStalwartClient::new(settings)),
+        StalwartApi::Mock(usage) => Box::new(MockStalwartClient::new(usage.into_iter())),
+    }
+}
+/// Talks to the management API of Stalwart
+#[derive(Debug)]
+pub struct HttpStalwartClient {
+    client: reqwest::Client,
+    settings: StalwartHttpApi,
+}
+#[derive(Debug, Deserialize)]
+struct PrincipalResponse {
+    data: Principal,
+}
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Principal {
+    #[serde(default)]
+    used_quota: i64,
+}
+impl HttpStalwartClient {
+    pub fn new(settings: StalwartHttpApi) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            settings,
+        }
+    }
+    /// The username is percent encoded into its own path segment
+    fn principal_url(&self, username: &str) -> Result<Url, StalwartClientError> {
+        let invalid = || StalwartClientError::InvalidUrl(self.settings.url.clone());
+        let mut url = Url::parse(&self.settings.url).map_err(|_| invalid())?;
+        url.path_segments_mut()
+            .map_err(|_| invalid())?
+            .pop_if_empty()
+            .extend(["api", "principal", username]);
+        Ok(url)
+    }
+}
+impl StalwartClient for HttpStalwartClient {
+    fn get_used_quota<'a>(
+        &'a self,
+        username: &'a str,
+    ) -> BoxFuture<'a, Result<Option<i64>, StalwartClientError>> {
+        Box::pin(async move {
+            let url = self.principal_url(username)?;
+            let response = self
+                .client
+                .get(url)
+                .basic_auth(&self.settings.username, Some(&self.settings.password))
+                .send()
+                .await?;
+            match response.status() {
+                StatusCode::NOT_FOUND => Ok(None),
+                status if status.is_success() => {
+                    let principal: PrincipalResponse = response.json().await?;
+                    Ok(Some(principal.data.used_quota))
+                }
+                status => Err(StalwartClientError::UnexpectedStatus(status)),
+            }
+        })
+    }
+}
+/// Responds with the usage it was given
+#[derive(Debug, Default)]
+pub struct MockStalwartClient {
+    pub usage: Mutex<HashMap<String, i64>>,
+}
+impl MockStalwartClient {
+    pub fn new(usage: impl Iterator<Item = (String, i64)>) -> Self {
+        Self {
+            usage: Mutex::new(usage.collect()),
+        }
+    }
+}
+impl StalwartClient for MockStalwartClient {
+    fn get_used_quota<'a>(
+        &'a self,
+        username: &'a str,
+    ) -> BoxFuture<'a, Result<Option<i64>, StalwartClientError>> {
+        let used = self.usage.lock().get(username).copied();
+        Box::pin(async move { Ok(used) })
+    }
+}
+#[cfg(test)]
+mod tests {
+    use utils::config::StalwartHttpApi;
+
+    use super::HttpStalwartClient;
+
+    #[test]
+    pub fn test_principal_url() {
+        let client = |url: &str| {
+            HttpStalwartClient::new(StalwartHttpApi {
+                url: url.to_owned(),
+                username: "admin".to_owned(),
+                password: "password".to_owned(),
+            })
+        };
+        let url = client("https://mail.example.com/")
+            .principal_url("user")
+            .unwrap();
+        assert_eq!(url.as_str(), "https://mail.example.com/api/principal/user");
+        let url = client("https://example.com/stalwart")
+            .principal_url("../admin?x=1#")
+            .unwrap();
+        assert_eq!(
+            url.as_str(),
+            "https://example.com/stalwart/api/principal/..%2Fadmin%3Fx=1%23"
+        );
+        assert!(client("mail.example.com").principal_url("user").is_err());
+    }
+}
@@ -0,0 +1,173 @@
+//! Helpers shared by the tests. An in-memory SQLite database and an email service that captures what it sends
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use entities::{
+    account::AccountType, groups::GroupPermissions, AccountEntity, AccountModel,
+    ActiveAccountModel, ActiveGroupModel, GroupEntity, GroupModel,
+};
+use migration::{Migrator, MigratorTrait};
+use sea_orm::{ActiveValue, Database, DatabaseConnection, EntityTrait};
+use utils::{
+    config::{Database as DatabaseSettings, EmailSetting, Settings},
+    database::{EmailAddress, Password},
+};
+
+use crate::{
+    email_service::{EmailAccess, EmailService},
+    email_transport::CapturedEmail,
+    headers::Origin,
+    SharedConfig,
+};
+
+/// An email service using the capture transport. The queue is removed on drop
+pub struct TestEmail {
+    pub access: Arc<EmailAccess>,
+    queue: PathBuf,
+}
+impl TestEmail {
+    pub async fn start() -> Self {
+        Self::start_with(|_| {}).await
+    }
+    /// Changes the email settings before the service starts
+    pub async fn start_with(change: impl FnOnce(&mut EmailSetting)) -> Self {
+        static QUEUES: AtomicUsize = AtomicUsize::new(0);
+        let queue = std::env::temp_dir().join(format!(
+            "stalwart-panel-test-{}-{}.redb",
+            std::process::id(),
+            QUEUES.fetch_add(1, Ordering::Relaxed)
+        ));
+        let mut settings = email_settings(&queue);
+        change(&mut settings);
+        let access = Arc::new(EmailService::start(settings).await.unwrap());
+        Self { access, queue }
+    }
+    /// Waits for `count` emails and removes them from the capture.
+    ///
+    /// Returns fewer if they are not sent within a few seconds
+    pub async fn take(&self, count: usize) -> Vec<CapturedEmail> {
+        let capture = self.access.capture().unwrap();
+        capture.wait_for(count, Duration::from_secs(5)).await;
+        capture.take()
+    }
+    /// Checks that nothing else is sent
+    pub async fn assert_none_sent(&self) {
+        let capture = self.access.capture().unwrap();
+        let sent = capture.wait_for(1, Duration::from_millis(500)).await;
+        assert!(sent.is_empty(), "Unexpected emails {:?}", sent);
+    }
+}
+impl Drop for TestEmail {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.queue);
+    }
+}
+/// Email settings using the capture transport
+pub fn email_settings(queue: &Path) -> EmailSetting {
+    toml::from_str(&format!(
+        r#"
+        host = "localhost"
+        encryption = "NONE"
+        from = "Stalwart Panel<panel@example.com>"
+        [transport]
+        type = "Capture"
+        [queue]
+        database_location = {:?}
+        "#,
+        queue
+    ))
+    .unwrap()
+}
+/// The defaults of [Settings::new]. Nothing connects to the database or the email queue in them
+pub fn settings() -> Settings {
+    Settings::new(
+        DatabaseSettings::test(),
+        "postmaster@example.com".to_owned(),
+        email_settings(&std::env::temp_dir().join("stalwart-panel-unused.redb")),
+    )
+}
+pub fn shared_config() -> SharedConfig {
+    SharedConfig::new(&settings())
+}
+/// Undoes the soft line breaks and the escaped `=` of quoted printable bodies
+pub fn unfold_quoted_printable(message: &str) -> String {
+    message.replace("=\r\n", "").replace("=3D", "=")
+}
+pub fn origin() -> Origin {
+    Origin {
+        url: "https://panel.example.com".to_owned(),
+        is_https: true,
+    }
+}
+pub fn address(address: &str) -> EmailAddress {
+    EmailAddress::new(address).unwrap()
+}
+/// A migrated in-memory database
+pub async fn database() -> DatabaseConnection {
+    let database = Database::connect("sqlite::memory:").await.unwrap();
+    Migrator::up(&database, None).await.unwrap();
+    database
+}
+pub async fn add_group(
+    database: &DatabaseConnection,
+    name: &str,
+    permissions: GroupPermissions,
+) -> GroupModel {
+    let group = ActiveGroupModel {
+        id: ActiveValue::NotSet,
+        group_name: ActiveValue::Set(name.to_owned()),
+        permissions: ActiveValue::Set(permissions),
+        default_quota: ActiveValue::Set(None),
+        max_quota: ActiveValue::Set(None),
+        alias_limit: ActiveValue::Set(0),
+        created: entities::now(),
+    };
+    let id = GroupEntity::insert(group)
+        .exec(database)
+        .await
+        .unwrap()
+        .last_insert_id;
+    GroupEntity::find_by_id(id)
+        .one(database)
+        .await
+        .unwrap()
+        .unwrap()
+}
+/// An active account without a backup email
+pub async fn add_account(
+    database: &DatabaseConnection,
+    username: &str,
+    group_id: i64,
+) -> AccountModel {
+    let account = ActiveAccountModel {
+        id: ActiveValue::NotSet,
+        name: ActiveValue::Set(username.to_owned()),
+        username: ActiveValue::Set(username.to_owned()),
+        description: ActiveValue::Set(String::new()),
+        group_id: ActiveValue::Set(group_id),
+        password: ActiveValue::Set(Password::new_hashed("")),
+        require_password_change: ActiveValue::Set(false),
+        quota: ActiveValue::Set(0),
+        account_type: ActiveValue::Set(AccountType::Individual),
+        active: ActiveValue::Set(true),
+        backup_email: ActiveValue::Set(None),
+        backup_email_verified: ActiveValue::Set(None),
+        created: entities::now(),
+    };
+    let id = AccountEntity::insert(account)
+        .exec(database)
+        .await
+        .unwrap()
+        .last_insert_id;
+    AccountEntity::find_by_id(id)
+        .one(database)
+        .await
+        .unwrap()
+        .unwrap()
+}
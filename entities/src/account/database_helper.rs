@@ -1,5 +1,8 @@
 use sea_orm::{
-    prelude::*, sea_query::IntoCondition, FromQueryResult, JoinType, QueryOrder, QuerySelect,
+    prelude::*,
+    sea_query::{Func, IntoCondition, LikeExpr, SelectStatement, SimpleExpr},
+    Condition, FromQueryResult, ItemsAndPagesNumber, JoinType, Order, QueryOrder, QuerySelect,
+    QueryTrait, Select,
 };
 use serde::{Deserialize, Serialize};
 use typeshare::typeshare;
@@ -8,7 +11,7 @@ use utils::database::EmailAddress;
 use crate::{
     account::AccountType,
     emails::{EmailType, Emails},
-    AccountEntity, AccountModel,
+    AccountEntity, AccountModel, EmailEntity,
 };
 
 pub type AccountWithEmails = (AccountModel, Emails);
@@ -39,16 +42,186 @@ pub struct AccountSimple {
     pub name: String,
     pub username: String,
     pub description: String,
+    pub group_id: i64,
+    pub quota: i64,
     pub account_type: AccountType,
     pub active: bool,
     pub primary_email: Option<EmailAddress>,
+    pub created: DateTimeWithTimeZone,
+    /// The bytes Stalwart reports the account is using. Filled in by the panel
+    #[sea_orm(skip)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quota_used: Option<i64>,
 }
-impl AccountSimple {
-    /// Get all accounts active or not
+/// The columns that [AccountList] can be sorted by
+#[typeshare]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountSort {
+    #[default]
+    Id,
+    Username,
+    Name,
+    Created,
+    Quota,
+}
+impl AccountSort {
+    fn column(&self) -> AccountColumn {
+        match self {
+            AccountSort::Id => AccountColumn::Id,
+            AccountSort::Username => AccountColumn::Username,
+            AccountSort::Name => AccountColumn::Name,
+            AccountSort::Created => AccountColumn::Created,
+            AccountSort::Quota => AccountColumn::Quota,
+        }
+    }
+}
+#[typeshare]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+impl From<SortOrder> for Order {
+    fn from(value: SortOrder) -> Self {
+        match value {
+            SortOrder::Asc => Order::Asc,
+            SortOrder::Desc => Order::Desc,
+        }
+    }
+}
+fn default_per_page() -> u64 {
+    50
+}
+/// Filters, sorting and paging for [AccountSimple::get_accounts]
+///
+/// All filters are optional. Leaving them out will return every account.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AccountListQuery {
+    /// Zero based page number
+    #[serde(default)]
+    pub page: u64,
+    #[serde(default = "default_per_page")]
+    pub per_page: u64,
+    #[serde(default)]
+    pub sort: AccountSort,
+    #[serde(default)]
+    pub order: SortOrder,
+    pub group: Option<i64>,
+    pub account_type: Option<AccountType>,
+    pub active: Option<bool>,
+    /// Only accounts with an email address on this domain
+    pub domain: Option<String>,
+    /// Matched against the username, name, description and all email addresses of the account
+    pub search: Option<String>,
+}
+impl Default for AccountListQuery {
+    fn default() -> Self {
+        Self {
+            page: 0,
+            per_page: default_per_page(),
+            sort: Default::default(),
+            order: Default::default(),
+            group: None,
+            account_type: None,
+            active: None,
+            domain: None,
+            search: None,
+        }
+    }
+}
+impl AccountListQuery {
+    /// The largest page that can be requested at once
+    pub const MAX_PER_PAGE: u64 = 1000;
 
-    pub async fn get_all_accounts(
-        connection: &impl ConnectionTrait,
-    ) -> Result<Vec<AccountSimple>, DbErr> {
+    /// The filters as a condition on the accounts table. Paging and sorting are ignored
+    pub fn condition(&self) -> Condition {
+        let mut condition = Condition::all();
+        if let Some(group) = self.group {
+            condition = condition.add(AccountColumn::GroupId.eq(group));
+        }
+        if let Some(account_type) = &self.account_type {
+            condition = condition.add(AccountColumn::AccountType.eq(account_type.clone()));
+        }
+        if let Some(active) = self.active {
+            condition = condition.add(AccountColumn::Active.eq(active));
+        }
+        if let Some(domain) = self.domain.as_deref().filter(|v| !v.is_empty()) {
+            condition = condition.add(has_email_on_domain(domain));
+        }
+        if let Some(search) = self
+            .search
+            .as_deref()
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+        {
+            let pattern = format!("%{}%", escape_like(&search.to_lowercase()));
+            condition = condition.add(
+                Condition::any()
+                    .add(lower_like(AccountColumn::Username, &pattern))
+                    .add(lower_like(AccountColumn::Name, &pattern))
+                    .add(lower_like(AccountColumn::Description, &pattern))
+                    .add(AccountColumn::Id.in_subquery(accounts_with_email_like(pattern))),
+            );
+        }
+        condition
+    }
+    /// If any filter is set. Without one [Self::condition] matches every account
+    pub fn has_filters(&self) -> bool {
+        let is_set =
+            |value: &Option<String>| value.as_deref().is_some_and(|v| !v.trim().is_empty());
+        self.group.is_some()
+            || self.account_type.is_some()
+            || self.active.is_some()
+            || is_set(&self.domain)
+            || is_set(&self.search)
+    }
+}
+/// A page of accounts
+#[typeshare]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct AccountList {
+    pub accounts: Vec<AccountSimple>,
+    /// The number of accounts matching the filters
+    pub total: u64,
+    pub total_pages: u64,
+    pub page: u64,
+    pub per_page: u64,
+}
+/// Escapes the wildcards of a LIKE pattern. Used with `\` as the escape character
+pub(crate) fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+fn lower_like(column: AccountColumn, pattern: &str) -> SimpleExpr {
+    Expr::expr(Func::lower(Expr::col((AccountEntity, column))))
+        .like(LikeExpr::new(pattern).escape('\\'))
+}
+/// Matches accounts that own an email address on the domain
+pub(crate) fn has_email_on_domain(domain: &str) -> SimpleExpr {
+    let pattern = format!("%@{}", escape_like(&domain.to_lowercase()));
+    AccountColumn::Id.in_subquery(accounts_with_email_like(pattern))
+}
+/// Selects the ids of the accounts that own an email address matching the pattern
+fn accounts_with_email_like(pattern: String) -> SelectStatement {
+    EmailEntity::find()
+        .select_only()
+        .column(EmailColumn::Account)
+        .filter(
+            Expr::expr(Func::lower(Expr::col((
+                EmailEntity,
+                EmailColumn::EmailAddress,
+            ))))
+            .like(LikeExpr::new(pattern).escape('\\')),
+        )
+        .into_query()
+}
+impl AccountSimple {
+    fn select() -> Select<AccountEntity> {
         AccountEntity::find()
             .select_only()
             .columns(vec![
@@ -56,8 +229,11 @@ impl AccountSimple {
                 AccountColumn::Name,
                 AccountColumn::Username,
                 AccountColumn::Description,
+                AccountColumn::GroupId,
+                AccountColumn::Quota,
                 AccountColumn::Active,
                 AccountColumn::AccountType,
+                AccountColumn::Created,
             ])
             .column_as(EmailColumn::EmailAddress, "primary_email")
             .join(
@@ -70,6 +246,12 @@ impl AccountSimple {
                             .into_condition()
                     }),
             )
+    }
+    /// Get all accounts active or not
+    pub async fn get_all_accounts(
+        connection: &impl ConnectionTrait,
+    ) -> Result<Vec<AccountSimple>, DbErr> {
+        Self::select()
             .order_by_asc(AccountColumn::Id)
             .into_model::<AccountSimple>()
             .all(connection)
@@ -79,27 +261,48 @@ impl AccountSimple {
     pub async fn get_all_active_accounts(
         connection: &impl ConnectionTrait,
     ) -> Result<Vec<AccountSimple>, DbErr> {
-        AccountEntity::find()
-            .select_only()
-            .columns(vec![
-                AccountColumn::Id,
-                AccountColumn::Name,
-                AccountColumn::Username,
-                AccountColumn::Description,
-                AccountColumn::Active,
-                AccountColumn::AccountType,
-            ])
-            .column_as(EmailColumn::EmailAddress, "primary_email")
-            .filter(
-                EmailColumn::EmailType
-                    .eq(EmailType::Primary)
-                    .and(AccountColumn::Active.eq(true)),
-            )
-            .join(JoinType::LeftJoin, crate::account::Relation::Email.def())
+        Self::select()
+            .filter(AccountColumn::Active.eq(true))
+            .order_by_asc(AccountColumn::Id)
             .into_model::<AccountSimple>()
             .all(connection)
             .await
     }
+    /// Get a page of accounts matching the query
+    pub async fn get_accounts(
+        connection: &impl ConnectionTrait,
+        query: &AccountListQuery,
+    ) -> Result<AccountList, DbErr> {
+        let per_page = query.per_page.clamp(1, AccountListQuery::MAX_PER_PAGE);
+        let paginator = Self::select()
+            .filter(query.condition())
+            .order_by(query.sort.column(), query.order.into())
+            .order_by_asc(AccountColumn::Id)
+            .into_model::<AccountSimple>()
+            .paginate(connection, per_page);
+        let ItemsAndPagesNumber {
+            number_of_items,
+            number_of_pages,
+        } = paginator.num_items_and_pages().await?;
+        let accounts = paginator.fetch_page(query.page).await?;
+        Ok(AccountList {
+            accounts,
+            total: number_of_items,
+            total_pages: number_of_pages,
+            page: query.page,
+            per_page,
+        })
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::escape_like;
+
+    #[test]
+    pub fn test_escape_like() {
+        assert_eq!(escape_like("example.com"), "example.com");
+        assert_eq!(escape_like("100%_off"), "100\\%\\_off");
+    }
 }
 #[test]
 pub fn test() {}
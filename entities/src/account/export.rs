@@ -0,0 +1,382 @@
+//! Dumps accounts with their emails and groups as CSV, JSON or LDIF
+use std::{collections::BTreeMap, fmt::Write, str::FromStr};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use sea_orm::{prelude::*, Condition, QuerySelect};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use utils::database::{password::PasswordType, EmailAddress, Password};
+
+use crate::{
+    account::{
+        database_helper::has_email_on_domain, full_user::FullUser, AccountType,
+        Column as AccountColumn,
+    },
+    emails::EmailType,
+    groups::GroupPermissions,
+    AccountEntity,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    Csv,
+    Ldif,
+}
+impl ExportFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Json => "application/json",
+            ExportFormat::Csv => "text/csv",
+            ExportFormat::Ldif => "text/x-ldif",
+        }
+    }
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Json => "json",
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ldif => "ldif",
+        }
+    }
+}
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(ExportFormat::Json),
+            "csv" => Ok(ExportFormat::Csv),
+            "ldif" => Ok(ExportFormat::Ldif),
+            other => Err(format!("Unknown export format `{other}`")),
+        }
+    }
+}
+#[derive(Debug, Error)]
+pub enum ExportError {
+    #[error(transparent)]
+    Database(#[from] DbErr),
+    #[error("Unable to write CSV: {0}")]
+    Csv(#[from] csv::Error),
+    #[error("Unable to write JSON: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+fn default_base_dn() -> String {
+    "dc=example,dc=com".to_owned()
+}
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ExportOptions {
+    #[serde(default)]
+    pub format: ExportFormat,
+    /// Only accounts in this group
+    pub group: Option<i64>,
+    /// Only accounts with an email address on this domain
+    pub domain: Option<String>,
+    /// Includes the password hashes so the accounts can be moved to another server
+    #[serde(default)]
+    pub include_password_hashes: bool,
+    /// The base of every DN in an LDIF export
+    #[serde(default = "default_base_dn")]
+    pub base_dn: String,
+}
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            format: Default::default(),
+            group: None,
+            domain: None,
+            include_password_hashes: false,
+            base_dn: default_base_dn(),
+        }
+    }
+}
+
+/// An account with everything that belongs to it
+///
+/// The CSV columns line up with [ImportAccount](crate::account::import::ImportAccount)
+/// so an export can be imported again.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ExportAccount {
+    pub id: i64,
+    pub name: String,
+    pub username: String,
+    pub description: String,
+    pub quota: i64,
+    pub account_type: AccountType,
+    pub active: bool,
+    pub backup_email: Option<EmailAddress>,
+    pub created: DateTimeWithTimeZone,
+    pub group: i64,
+    pub group_name: String,
+    pub group_permissions: GroupPermissions,
+    pub primary_email: Option<EmailAddress>,
+    pub aliases: Vec<EmailAddress>,
+    pub lists: Vec<EmailAddress>,
+    pub catch_all: Vec<EmailAddress>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password_hash: Option<String>,
+}
+impl From<FullUser> for ExportAccount {
+    fn from(user: FullUser) -> Self {
+        let mut primary_email = None;
+        let mut aliases = Vec::new();
+        let mut lists = Vec::new();
+        let mut catch_all = Vec::new();
+        for email in user.emails.into_inner() {
+            match email.email_type {
+                EmailType::Primary => primary_email = Some(email.email_address),
+                EmailType::Alias => aliases.push(email.email_address),
+                EmailType::List => lists.push(email.email_address),
+                EmailType::CatchAll => catch_all.push(email.email_address),
+            }
+        }
+        Self {
+            id: user.id,
+            name: user.name,
+            username: user.username,
+            description: user.description,
+            quota: user.quota,
+            account_type: user.account_type,
+            active: user.active,
+            backup_email: user.backup_email,
+            created: user.created,
+            group: user.group_id,
+            group_name: user.group_name,
+            group_permissions: user.group_permissions,
+            primary_email,
+            aliases,
+            lists,
+            catch_all,
+            password_hash: None,
+        }
+    }
+}
+/// One line of a CSV export
+#[derive(Serialize)]
+struct CsvRow<'a> {
+    id: i64,
+    name: &'a str,
+    username: &'a str,
+    description: &'a str,
+    quota: i64,
+    account_type: &'a AccountType,
+    active: bool,
+    group: i64,
+    group_name: &'a str,
+    modify_accounts: bool,
+    manage_system: bool,
+    primary_email: Option<&'a str>,
+    aliases: String,
+    lists: String,
+    catch_all: String,
+    backup_email: Option<&'a str>,
+    password_hash: Option<&'a str>,
+    created: String,
+}
+fn join(addresses: &[EmailAddress]) -> String {
+    addresses
+        .iter()
+        .map(|address| address.as_str())
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+impl ExportAccount {
+    /// Gets every account matching the options
+    pub async fn get_all(
+        connection: &impl ConnectionTrait,
+        options: &ExportOptions,
+    ) -> Result<Vec<Self>, DbErr> {
+        let mut filter = Condition::all();
+        if let Some(group) = options.group {
+            filter = filter.add(AccountColumn::GroupId.eq(group));
+        }
+        if let Some(domain) = options.domain.as_deref().filter(|v| !v.is_empty()) {
+            filter = filter.add(has_email_on_domain(domain));
+        }
+        let mut passwords: BTreeMap<i64, Password> = if options.include_password_hashes {
+            AccountEntity::find()
+                .select_only()
+                .columns([AccountColumn::Id, AccountColumn::Password])
+                .filter(filter.clone())
+                .into_tuple::<(i64, Password)>()
+                .all(connection)
+                .await?
+                .into_iter()
+                .collect()
+        } else {
+            BTreeMap::new()
+        };
+        let accounts = FullUser::get_all_with_emails(connection, filter)
+            .await?
+            .into_iter()
+            .map(|user| {
+                let password = passwords.remove(&user.id);
+                let mut account = ExportAccount::from(user);
+                account.password_hash = password.map(Into::into);
+                account
+            })
+            .collect();
+        Ok(accounts)
+    }
+
+    /// Writes the accounts in the format
+    pub fn write(
+        accounts: &[Self],
+        format: ExportFormat,
+        base_dn: &str,
+    ) -> Result<Vec<u8>, ExportError> {
+        match format {
+            ExportFormat::Json => serde_json::to_vec_pretty(accounts).map_err(ExportError::from),
+            ExportFormat::Csv => Self::write_csv(accounts),
+            ExportFormat::Ldif => Ok(Self::write_ldif(accounts, base_dn).into_bytes()),
+        }
+    }
+    fn write_csv(accounts: &[Self]) -> Result<Vec<u8>, ExportError> {
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        for account in accounts {
+            writer.serialize(CsvRow {
+                id: account.id,
+                name: &account.name,
+                username: &account.username,
+                description: &account.description,
+                quota: account.quota,
+                account_type: &account.account_type,
+                active: account.active,
+                group: account.group,
+                group_name: &account.group_name,
+                modify_accounts: account.group_permissions.modify_accounts,
+                manage_system: account.group_permissions.manage_system,
+                primary_email: account.primary_email.as_ref().map(|v| v.as_str()),
+                aliases: join(&account.aliases),
+                lists: join(&account.lists),
+                catch_all: join(&account.catch_all),
+                backup_email: account.backup_email.as_ref().map(|v| v.as_str()),
+                password_hash: account.password_hash.as_deref(),
+                created: account.created.to_rfc3339(),
+            })?;
+        }
+        writer
+            .into_inner()
+            .map_err(|error| ExportError::Csv(error.into_error().into()))
+    }
+    /// Writes the accounts as `inetOrgPerson` entries under `ou=people`
+    /// and their groups as `groupOfNames` entries under `ou=groups`
+    fn write_ldif(accounts: &[Self], base_dn: &str) -> String {
+        let mut ldif = String::from("version: 1\n");
+        let mut groups: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+        for account in accounts {
+            let dn = format!(
+                "uid={},ou=people,{base_dn}",
+                escape_dn_value(&account.username)
+            );
+            ldif.push('\n');
+            ldif_attribute(&mut ldif, "dn", &dn);
+            ldif_attribute(&mut ldif, "objectClass", "top");
+            ldif_attribute(&mut ldif, "objectClass", "inetOrgPerson");
+            ldif_attribute(&mut ldif, "uid", &account.username);
+            ldif_attribute(&mut ldif, "cn", &account.name);
+            let surname = account
+                .name
+                .rsplit(' ')
+                .next()
+                .filter(|v| !v.is_empty())
+                .unwrap_or(&account.username);
+            ldif_attribute(&mut ldif, "sn", surname);
+            ldif_attribute(&mut ldif, "displayName", &account.name);
+            if !account.description.is_empty() {
+                ldif_attribute(&mut ldif, "description", &account.description);
+            }
+            for address in account
+                .primary_email
+                .iter()
+                .chain(account.aliases.iter())
+                .chain(account.lists.iter())
+            {
+                ldif_attribute(&mut ldif, "mail", address);
+            }
+            if let Some(password) = account.password_hash.as_deref().and_then(ldap_password) {
+                ldif_attribute(&mut ldif, "userPassword", &password);
+            }
+            groups.entry(&account.group_name).or_default().push(dn);
+        }
+        for (group, members) in groups {
+            ldif.push('\n');
+            ldif_attribute(
+                &mut ldif,
+                "dn",
+                &format!("cn={},ou=groups,{base_dn}", escape_dn_value(group)),
+            );
+            ldif_attribute(&mut ldif, "objectClass", "top");
+            ldif_attribute(&mut ldif, "objectClass", "groupOfNames");
+            ldif_attribute(&mut ldif, "cn", group);
+            for member in members {
+                ldif_attribute(&mut ldif, "member", &member);
+            }
+        }
+        ldif
+    }
+}
+/// Prefixes the hash with the scheme LDAP servers expect
+fn ldap_password(hash: &str) -> Option<String> {
+    match PasswordType::identify(hash) {
+        PasswordType::Argon2 => Some(format!("{{ARGON2}}{hash}")),
+        PasswordType::SHA256 | PasswordType::SHA512 => Some(format!("{{CRYPT}}{hash}")),
+        _ => None,
+    }
+}
+/// Escapes a value for use inside a DN. [RFC 4514](https://www.rfc-editor.org/rfc/rfc4514#section-2.4)
+fn escape_dn_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    let last = value.chars().count().saturating_sub(1);
+    for (index, c) in value.chars().enumerate() {
+        match c {
+            ',' | '+' | '"' | '\\' | '<' | '>' | ';' | '=' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '#' | ' ' if index == 0 => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            ' ' if index == last => escaped.push_str("\\ "),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+/// Writes `name: value`. Values that are not a SAFE-STRING are base64 encoded.
+/// [RFC 2849](https://www.rfc-editor.org/rfc/rfc2849)
+fn ldif_attribute(ldif: &mut String, name: &str, value: &str) {
+    let safe = value.chars().enumerate().all(|(index, c)| {
+        c.is_ascii()
+            && !matches!(c, '\0' | '\n' | '\r')
+            && !(index == 0 && matches!(c, ' ' | ':' | '<'))
+    }) && !value.ends_with(' ');
+    if safe {
+        let _ = writeln!(ldif, "{name}: {value}");
+    } else {
+        let _ = writeln!(ldif, "{name}:: {}", STANDARD.encode(value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape_dn_value, ldif_attribute};
+
+    #[test]
+    pub fn test_escape_dn_value() {
+        assert_eq!(escape_dn_value("jane"), "jane");
+        assert_eq!(escape_dn_value("Doe, Jane"), "Doe\\, Jane");
+        assert_eq!(escape_dn_value("#admins"), "\\#admins");
+    }
+    #[test]
+    pub fn test_ldif_attribute() {
+        let mut ldif = String::new();
+        ldif_attribute(&mut ldif, "cn", "Jane Doe");
+        ldif_attribute(&mut ldif, "cn", "Jöhn");
+        assert_eq!(ldif, "cn: Jane Doe\ncn:: SsO2aG4=\n");
+    }
+}
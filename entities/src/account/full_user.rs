@@ -1,4 +1,9 @@
-use sea_orm::{prelude::*, sea_query::SimpleExpr, FromQueryResult, JoinType, QuerySelect};
+use std::collections::HashMap;
+
+use sea_orm::{
+    prelude::*, sea_query::SimpleExpr, Condition, FromQueryResult, JoinType, QueryOrder,
+    QuerySelect, QueryTrait,
+};
 use serde::Serialize;
 use typeshare::typeshare;
 use utils::database::EmailAddress;
@@ -6,8 +11,9 @@ use utils::database::EmailAddress;
 use super::{Column as AccountColumn, Entity as AccountEntity, Relation as AccountRelation};
 use crate::{
     account::AccountType,
-    emails::Emails,
+    emails::{Column as EmailColumn, Emails},
     groups::{Column as GroupColumn, GroupPermissions},
+    EmailEntity, EmailModel,
 };
 #[typeshare]
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, FromQueryResult)]
@@ -21,6 +27,7 @@ pub struct FullUser {
     pub account_type: AccountType,
     pub active: bool,
     pub backup_email: Option<EmailAddress>,
+    pub backup_email_verified: Option<DateTimeWithTimeZone>,
     pub created: DateTimeWithTimeZone,
     // Group Details
     pub group_id: i64,
@@ -29,6 +36,10 @@ pub struct FullUser {
     #[sea_orm(skip)]
     #[serde(skip_serializing_if = "Emails::is_empty")]
     pub emails: Emails,
+    /// The bytes Stalwart reports the account is using. Filled in by the panel
+    #[sea_orm(skip)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quota_used: Option<i64>,
 }
 impl FullUser {
     async fn get_user(
@@ -68,4 +79,42 @@ impl FullUser {
             Self::get_user(connection, AccountColumn::Id.eq(id)).await
         }
     }
+    /// Gets every user matching the filter with all of their emails
+    pub async fn get_all_with_emails(
+        connection: &impl ConnectionTrait,
+        filter: Condition,
+    ) -> Result<Vec<Self>, DbErr> {
+        // The same filter as a subquery. Binding every id can go over the parameter limit of the database
+        let ids = AccountEntity::find()
+            .select_only()
+            .column(AccountColumn::Id)
+            .join(JoinType::InnerJoin, AccountRelation::Group.def())
+            .filter(filter.clone())
+            .into_query();
+        let mut users = AccountEntity::find()
+            .column_as(GroupColumn::Id, "group_id")
+            .column_as(GroupColumn::GroupName, "group_name")
+            .column_as(GroupColumn::Permissions, "group_permissions")
+            .join(JoinType::InnerJoin, AccountRelation::Group.def())
+            .filter(filter)
+            .order_by_asc(AccountColumn::Id)
+            .into_model::<Self>()
+            .all(connection)
+            .await?;
+        let mut emails: HashMap<i64, Vec<EmailModel>> = HashMap::new();
+        for email in EmailEntity::find()
+            .filter(EmailColumn::Account.in_subquery(ids))
+            .order_by_asc(EmailColumn::Id)
+            .all(connection)
+            .await?
+        {
+            emails.entry(email.account).or_default().push(email);
+        }
+        for user in users.iter_mut() {
+            if let Some(user_emails) = emails.remove(&user.id) {
+                user.emails = user_emails.into();
+            }
+        }
+        Ok(users)
+    }
 }
@@ -0,0 +1,572 @@
+//! Bulk creation of accounts from CSV or JSON files
+//!
+//! Every row is validated before anything is written to the database.
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    str::FromStr,
+};
+
+use sea_orm::{prelude::*, ActiveValue, TransactionTrait};
+use serde::{de::Visitor, Deserialize, Deserializer, Serialize};
+use thiserror::Error;
+use utils::{
+    database::{password::PasswordType, EmailAddress, Password},
+    quota_size::QuotaSize,
+};
+
+use crate::{
+    account::{
+        quota::{check_domain_allocation, QuotaError},
+        AccountType, Column as AccountColumn,
+    },
+    emails::{Column as EmailColumn, EmailType},
+    now, AccountEntity, ActiveAccountModel, EmailActiveModel, EmailEntity, GroupEntity,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportFormat {
+    #[default]
+    Json,
+    Csv,
+}
+impl FromStr for ImportFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(ImportFormat::Json),
+            "csv" => Ok(ImportFormat::Csv),
+            _ => Err(()),
+        }
+    }
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportMode {
+    /// All rows are inserted in one transaction.
+    /// If any row is invalid or fails to insert nothing is imported
+    #[default]
+    Transaction,
+    /// Rows are inserted one at a time. Rows that are invalid or fail are skipped and reported
+    BestEffort,
+}
+#[derive(Debug, Error)]
+pub enum ImportError {
+    #[error("Invalid CSV: {0}")]
+    Csv(#[from] csv::Error),
+    #[error("Invalid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Database(#[from] DbErr),
+}
+
+/// A single row of an import file
+///
+/// In CSV files `aliases` is a single column with the addresses separated by `;`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ImportAccount {
+    pub name: String,
+    pub username: String,
+    #[serde(default)]
+    pub description: String,
+    /// Bytes or a size such as `5GiB`. Defaults to the default quota of the group
+    #[serde(default)]
+    pub quota: Option<QuotaSize>,
+    /// The group id. Defaults to the panels default group
+    #[serde(default)]
+    pub group: Option<i64>,
+    #[serde(default)]
+    pub primary_email: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_aliases")]
+    pub aliases: Vec<String>,
+    #[serde(default)]
+    pub backup_email: Option<String>,
+    /// An already hashed password. If not provided a random password is generated
+    /// and the user will be required to change it
+    #[serde(default)]
+    pub password_hash: Option<String>,
+}
+impl ImportAccount {
+    pub fn parse(format: ImportFormat, data: &[u8]) -> Result<Vec<Self>, ImportError> {
+        match format {
+            ImportFormat::Json => serde_json::from_slice(data).map_err(ImportError::from),
+            ImportFormat::Csv => csv::ReaderBuilder::new()
+                .trim(csv::Trim::All)
+                .from_reader(data)
+                .deserialize()
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(ImportError::from),
+        }
+    }
+}
+
+fn deserialize_aliases<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<String>, D::Error> {
+    struct AliasesVisitor;
+    impl<'de> Visitor<'de> for AliasesVisitor {
+        type Value = Vec<String>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a list of email addresses or a string separated by `;`")
+        }
+        fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+            Ok(v.split(';')
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(str::to_owned)
+                .collect())
+        }
+        fn visit_unit<E: serde::de::Error>(self) -> Result<Self::Value, E> {
+            Ok(Vec::new())
+        }
+        fn visit_none<E: serde::de::Error>(self) -> Result<Self::Value, E> {
+            Ok(Vec::new())
+        }
+        fn visit_seq<A: serde::de::SeqAccess<'de>>(
+            self,
+            mut seq: A,
+        ) -> Result<Self::Value, A::Error> {
+            let mut aliases = Vec::with_capacity(seq.size_hint().unwrap_or_default());
+            while let Some(alias) = seq.next_element::<String>()? {
+                aliases.push(alias);
+            }
+            Ok(aliases)
+        }
+    }
+    deserializer.deserialize_any(AliasesVisitor)
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportOptions {
+    pub mode: ImportMode,
+    /// Used for rows without a group
+    pub default_group: i64,
+    /// Used to hash the generated passwords
+    pub password_type: PasswordType,
+    /// Lowercase domain to the bytes it can hand out. The quotas of the imported accounts count against it
+    pub domain_allocations: HashMap<String, i64>,
+}
+/// A row that could not be imported
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RowError {
+    /// One based index of the row. Not counting the CSV header
+    pub row: usize,
+    pub username: String,
+    pub errors: Vec<String>,
+}
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ImportedAccount {
+    pub row: usize,
+    pub id: i64,
+    pub username: String,
+    pub backup_email: Option<EmailAddress>,
+    pub require_password_change: bool,
+}
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize)]
+pub struct ImportReport {
+    pub imported: Vec<ImportedAccount>,
+    pub errors: Vec<RowError>,
+}
+
+/// A row that passed validation
+#[derive(Debug)]
+struct ValidAccount {
+    row: usize,
+    name: String,
+    username: String,
+    description: String,
+    quota: i64,
+    group_id: i64,
+    password: Password,
+    require_password_change: bool,
+    primary_email: Option<EmailAddress>,
+    aliases: Vec<EmailAddress>,
+    backup_email: Option<EmailAddress>,
+}
+
+/// How many values are bound in one `IN` list.
+///
+/// An import can have more rows than the parameter limit of the database allows in one query
+const CHUNK_SIZE: usize = 1000;
+/// The rows where the column is one of the values
+async fn find_in_chunks<E: EntityTrait>(
+    connection: &impl ConnectionTrait,
+    column: E::Column,
+    values: &[&str],
+) -> Result<Vec<E::Model>, DbErr> {
+    let mut found = Vec::new();
+    for chunk in values.chunks(CHUNK_SIZE) {
+        found.extend(
+            E::find()
+                .filter(column.is_in(chunk.iter().copied()))
+                .all(connection)
+                .await?,
+        );
+    }
+    Ok(found)
+}
+/// Checks every row against each other and the database
+async fn validate(
+    connection: &impl ConnectionTrait,
+    rows: Vec<ImportAccount>,
+    options: &ImportOptions,
+) -> Result<(Vec<ValidAccount>, Vec<RowError>), DbErr> {
+    let groups: HashMap<i64, _> = GroupEntity::find()
+        .all(connection)
+        .await?
+        .into_iter()
+        .map(|group| (group.id, group))
+        .collect();
+    let usernames: Vec<&str> = rows.iter().map(|row| row.username.as_str()).collect();
+    let taken_usernames: HashSet<String> =
+        find_in_chunks::<AccountEntity>(connection, AccountColumn::Username, &usernames)
+            .await?
+            .into_iter()
+            .map(|account| account.username)
+            .collect();
+    let addresses: Vec<&str> = rows
+        .iter()
+        .flat_map(|row| row.primary_email.iter().chain(row.aliases.iter()))
+        .map(String::as_str)
+        .collect();
+    let taken_addresses: HashSet<String> =
+        find_in_chunks::<EmailEntity>(connection, EmailColumn::EmailAddress, &addresses)
+            .await?
+            .into_iter()
+            .map(|email| email.email_address.to_string())
+            .collect();
+    let backup_emails: Vec<&str> = rows
+        .iter()
+        .flat_map(|row| row.backup_email.as_deref())
+        .collect();
+    let taken_backup_emails: HashSet<String> =
+        find_in_chunks::<AccountEntity>(connection, AccountColumn::BackupEmail, &backup_emails)
+            .await?
+            .into_iter()
+            .flat_map(|account| account.backup_email)
+            .map(|email| email.to_string())
+            .collect();
+
+    let mut seen_usernames = HashSet::new();
+    let mut seen_addresses = HashSet::new();
+    let mut seen_backup_emails = HashSet::new();
+    // The quotas of the valid rows so far on each allocated domain
+    let mut allocated: HashMap<String, i64> = HashMap::new();
+    let mut valid = Vec::with_capacity(rows.len());
+    let mut invalid = Vec::new();
+    for (index, row) in rows.into_iter().enumerate() {
+        let mut errors = Vec::new();
+        let ImportAccount {
+            name,
+            username,
+            description,
+            quota,
+            group,
+            primary_email,
+            aliases,
+            backup_email,
+            password_hash,
+        } = row;
+        if username.is_empty() {
+            errors.push("Username is required".to_owned());
+        } else if taken_usernames.contains(&username) {
+            errors.push(format!("Username `{username}` already exists"));
+        } else if !seen_usernames.insert(username.clone()) {
+            errors.push(format!("Username `{username}` appears more than once"));
+        }
+        if name.is_empty() {
+            errors.push("Name is required".to_owned());
+        }
+        let group_id = group.unwrap_or(options.default_group);
+        let group = groups.get(&group_id);
+        if group.is_none() {
+            errors.push(format!("Group {group_id} does not exist"));
+        }
+        let quota = match quota {
+            Some(quota) => quota.bytes(),
+            None => group.map_or(0, |group| group.default_quota_or_unlimited()),
+        };
+        if quota < 0 {
+            errors.push("Quota can not be negative".to_owned());
+        } else if let Some(Err(error)) = group.map(|group| group.check_quota(quota)) {
+            errors.push(error.to_string());
+        }
+        let mut check_address = |address: String, errors: &mut Vec<String>| {
+            let address = match EmailAddress::new(address.clone()) {
+                Ok(ok) if ok.is_catch_all() => {
+                    errors.push(format!("`{ok}` is a catch-all and can not be imported"));
+                    return None;
+                }
+                Ok(ok) => ok,
+                Err(_) => {
+                    errors.push(format!("`{address}` is not a valid email address"));
+                    return None;
+                }
+            };
+            if taken_addresses.contains(address.as_str()) {
+                errors.push(format!("`{address}` is already in use"));
+            } else if !seen_addresses.insert(address.to_string()) {
+                errors.push(format!("`{address}` appears more than once"));
+            }
+            Some(address)
+        };
+        let primary_email = primary_email
+            .filter(|v| !v.is_empty())
+            .and_then(|address| check_address(address, &mut errors));
+        let aliases: Vec<EmailAddress> = aliases
+            .into_iter()
+            .flat_map(|address| check_address(address, &mut errors))
+            .collect();
+        let allocation = primary_email
+            .as_ref()
+            .and_then(|address| address.split_once('@'))
+            .map(|(_, domain)| domain.to_lowercase())
+            .and_then(|domain| {
+                let allocation = options.domain_allocations.get(&domain).copied()?;
+                Some((domain, allocation))
+            });
+        if let Some((domain, allocation)) = &allocation {
+            let earlier_rows = allocated.get(domain).copied().unwrap_or_default();
+            // 0 is unlimited and is refused on its own
+            let requested = if quota == 0 {
+                0
+            } else {
+                quota.saturating_add(earlier_rows)
+            };
+            match check_domain_allocation(connection, domain, *allocation, None, requested).await {
+                Ok(()) => {}
+                Err(QuotaError::Database(error)) => return Err(error),
+                Err(error) => errors.push(error.to_string()),
+            }
+        }
+        let backup_email = match backup_email.filter(|v| !v.is_empty()) {
+            Some(address) => match EmailAddress::new(address.clone()) {
+                Ok(ok) if ok.is_catch_all() => {
+                    errors.push(format!("`{ok}` is not a valid backup email"));
+                    None
+                }
+                Ok(ok) => {
+                    if taken_backup_emails.contains(ok.as_str()) {
+                        errors.push(format!("Backup email `{ok}` is used by another account"));
+                    } else if !seen_backup_emails.insert(ok.to_string()) {
+                        errors.push(format!("Backup email `{ok}` appears more than once"));
+                    }
+                    Some(ok)
+                }
+                Err(_) => {
+                    errors.push(format!("`{address}` is not a valid backup email"));
+                    None
+                }
+            },
+            None => None,
+        };
+        let (password, require_password_change) = match password_hash.filter(|v| !v.is_empty()) {
+            Some(hash) => {
+                let password = Password::new_hashed(hash);
+                if password.hash_type() == PasswordType::None {
+                    errors.push("Unsupported password hash".to_owned());
+                }
+                (Some(password), false)
+            }
+            None => match Password::create_password(options.password_type) {
+                Ok((password, _)) => (Some(password), true),
+                Err(error) => {
+                    errors.push(format!("Unable to generate a password: {error}"));
+                    (None, true)
+                }
+            },
+        };
+
+        match password {
+            Some(password) if errors.is_empty() => {
+                if let Some((domain, _)) = allocation {
+                    *allocated.entry(domain).or_default() += quota;
+                }
+                valid.push(ValidAccount {
+                    row: index + 1,
+                    name,
+                    username,
+                    description,
+                    quota,
+                    group_id,
+                    password,
+                    require_password_change,
+                    primary_email,
+                    aliases,
+                    backup_email,
+                })
+            }
+            _ => invalid.push(RowError {
+                row: index + 1,
+                username,
+                errors,
+            }),
+        }
+    }
+    Ok((valid, invalid))
+}
+
+async fn insert_account(
+    connection: &impl ConnectionTrait,
+    account: ValidAccount,
+) -> Result<ImportedAccount, DbErr> {
+    let ValidAccount {
+        row,
+        name,
+        username,
+        description,
+        quota,
+        group_id,
+        password,
+        require_password_change,
+        primary_email,
+        aliases,
+        backup_email,
+    } = account;
+    let model = ActiveAccountModel {
+        id: ActiveValue::NotSet,
+        name: ActiveValue::Set(name),
+        username: ActiveValue::Set(username.clone()),
+        description: ActiveValue::Set(description),
+        group_id: ActiveValue::Set(group_id),
+        password: ActiveValue::Set(password),
+        require_password_change: ActiveValue::Set(require_password_change),
+        quota: ActiveValue::Set(quota),
+        account_type: ActiveValue::Set(AccountType::Individual),
+        active: ActiveValue::Set(true),
+        backup_email: ActiveValue::Set(backup_email.clone()),
+        backup_email_verified: ActiveValue::Set(None),
+        created: now(),
+    };
+    let id = AccountEntity::insert(model)
+        .exec(connection)
+        .await?
+        .last_insert_id;
+
+    let emails: Vec<EmailActiveModel> = primary_email
+        .map(|address| (address, EmailType::Primary))
+        .into_iter()
+        .chain(
+            aliases
+                .into_iter()
+                .map(|address| (address, EmailType::Alias)),
+        )
+        .map(|(email_address, email_type)| EmailActiveModel {
+            id: ActiveValue::NotSet,
+            account: ActiveValue::Set(id),
+            email_address: ActiveValue::Set(email_address),
+            email_type: ActiveValue::Set(email_type),
+            created: now(),
+        })
+        .collect();
+    if !emails.is_empty() {
+        EmailEntity::insert_many(emails).exec(connection).await?;
+    }
+    Ok(ImportedAccount {
+        row,
+        id,
+        username,
+        backup_email,
+        require_password_change,
+    })
+}
+
+/// Validates and then imports the accounts
+///
+/// ## Returns
+/// - Ok(ImportReport) - The accounts that were created and the rows that were not.
+/// In [ImportMode::Transaction] `imported` is empty if there are any errors
+/// - Err(DbErr) - If the database could not be queried or a transaction could not be started
+pub async fn import_accounts<C>(
+    connection: &C,
+    rows: Vec<ImportAccount>,
+    options: ImportOptions,
+) -> Result<ImportReport, DbErr>
+where
+    C: ConnectionTrait + TransactionTrait,
+{
+    let (valid, errors) = validate(connection, rows, &options).await?;
+    let mut report = ImportReport {
+        imported: Vec::with_capacity(valid.len()),
+        errors,
+    };
+    match options.mode {
+        ImportMode::Transaction => {
+            if !report.errors.is_empty() {
+                return Ok(report);
+            }
+            let transaction = connection.begin().await?;
+            for account in valid {
+                let (row, username) = (account.row, account.username.clone());
+                match insert_account(&transaction, account).await {
+                    Ok(imported) => report.imported.push(imported),
+                    Err(error) => {
+                        transaction.rollback().await?;
+                        report.imported.clear();
+                        report.errors.push(RowError {
+                            row,
+                            username,
+                            errors: vec![error.to_string()],
+                        });
+                        return Ok(report);
+                    }
+                }
+            }
+            transaction.commit().await?;
+        }
+        ImportMode::BestEffort => {
+            for account in valid {
+                let (row, username) = (account.row, account.username.clone());
+                let transaction = connection.begin().await?;
+                match insert_account(&transaction, account).await {
+                    Ok(imported) => {
+                        transaction.commit().await?;
+                        report.imported.push(imported);
+                    }
+                    Err(error) => {
+                        transaction.rollback().await?;
+                        report.errors.push(RowError {
+                            row,
+                            username,
+                            errors: vec![error.to_string()],
+                        });
+                    }
+                }
+            }
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ImportAccount, ImportFormat};
+
+    #[test]
+    pub fn test_parse_csv() {
+        let csv = "name,username,quota,group,primary_email,aliases,backup_email,password_hash\n\
+                   Jane Doe,jane,1024,,jane@example.com,j@example.com;doe@example.com,,\n";
+        let rows = ImportAccount::parse(ImportFormat::Csv, csv.as_bytes()).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].group, None);
+        assert_eq!(rows[0].backup_email, None);
+        assert_eq!(rows[0].aliases, vec!["j@example.com", "doe@example.com"]);
+    }
+    #[test]
+    pub fn test_parse_json() {
+        let json = r#"[{"name": "Jane Doe", "username": "jane", "aliases": ["j@example.com"]}]"#;
+        let rows = ImportAccount::parse(ImportFormat::Json, json.as_bytes()).unwrap();
+        assert_eq!(rows[0].aliases, vec!["j@example.com"]);
+        assert_eq!(rows[0].quota, None);
+    }
+    #[test]
+    pub fn test_parse_quota_sizes() {
+        let csv = "name,username,quota\nJane Doe,jane,5GiB\nJohn Doe,john,1024\nBob,bob,\n";
+        let rows = ImportAccount::parse(ImportFormat::Csv, csv.as_bytes()).unwrap();
+        let quotas: Vec<_> = rows.iter().map(|row| row.quota.map(i64::from)).collect();
+        assert_eq!(quotas, vec![Some(5 << 30), Some(1024), None]);
+    }
+}
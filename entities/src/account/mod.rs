@@ -1,6 +1,9 @@
 pub mod database_helper;
+pub mod export;
 pub mod full_user;
+pub mod import;
 pub mod panel_user;
+pub mod quota;
 
 use sea_orm::entity::prelude::*;
 use serde::{Deserialize, Serialize};
@@ -58,6 +61,11 @@ pub struct Model {
     pub active: bool,
     #[sea_orm(unique, nullable, column_type = "Text")]
     pub backup_email: Option<EmailAddress>,
+    /// When the backup email was confirmed. None while it is unverified.
+    ///
+    /// Password reset links are only sent to verified backup emails
+    #[sea_orm(nullable)]
+    pub backup_email_verified: Option<DateTimeWithTimeZone>,
     #[sea_orm(default_expr = "Expr::current_timestamp()")]
     pub created: DateTimeWithTimeZone,
 }
@@ -24,6 +24,7 @@ pub struct PanelUser {
     pub require_password_change: bool,
     pub active: bool,
     pub backup_email: Option<EmailAddress>,
+    pub backup_email_verified: Option<DateTimeWithTimeZone>,
     // Group Details
     pub group_id: i64,
     pub group_name: String,
@@ -63,11 +64,18 @@ impl PanelUser {
     ) -> Result<Option<Self>, DbErr> {
         Self::get_inner(connection, AccountColumn::Username.eq(username)).await
     }
+    /// Only finds accounts that have verified their backup email
     pub async fn get_by_backup_email(
         connection: &impl ConnectionTrait,
         backup_email: String,
     ) -> Result<Option<Self>, DbErr> {
-        Self::get_inner(connection, AccountColumn::BackupEmail.eq(backup_email)).await
+        Self::get_inner(
+            connection,
+            AccountColumn::BackupEmail
+                .eq(backup_email)
+                .and(AccountColumn::BackupEmailVerified.is_not_null()),
+        )
+        .await
     }
     pub async fn get_by_id(
         connection: &impl ConnectionTrait,
@@ -86,6 +94,7 @@ impl IntoActiveModel<ActiveAccountModel> for PanelUser {
             password: Unchanged(self.password),
             active: Unchanged(self.active),
             backup_email: Unchanged(self.backup_email),
+            backup_email_verified: Unchanged(self.backup_email_verified),
             group_id: Unchanged(self.group_id),
             created: Unchanged(self.created),
             ..Default::default()
@@ -0,0 +1,131 @@
+//! Limits on the quota an account can be given
+use sea_orm::{
+    prelude::*,
+    sea_query::{Func, LikeExpr},
+    QuerySelect, QueryTrait,
+};
+use thiserror::Error;
+
+use crate::{
+    account::{database_helper::escape_like, Column as AccountColumn},
+    emails::{Column as EmailColumn, EmailType},
+    AccountEntity, EmailEntity, GroupModel,
+};
+
+#[derive(Debug, Error)]
+pub enum QuotaError {
+    #[error(transparent)]
+    Database(#[from] DbErr),
+    #[error("The quota is larger than the group maximum of {0} bytes")]
+    AboveGroupMaximum(i64),
+    #[error("The quota would put {domain} over its allocation of {allocation} bytes")]
+    AboveDomainAllocation { domain: String, allocation: i64 },
+}
+
+impl GroupModel {
+    /// The quota to use when one was not provided. 0 (unlimited) if the group has no default
+    pub fn default_quota_or_unlimited(&self) -> i64 {
+        self.default_quota.unwrap_or(0)
+    }
+    /// A quota of 0 is unlimited so it is above any maximum
+    pub fn check_quota(&self, quota: i64) -> Result<(), QuotaError> {
+        match self.max_quota {
+            Some(max) if quota == 0 || quota > max => Err(QuotaError::AboveGroupMaximum(max)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Checks that the quotas of every account with a primary email on the domain will fit in the allocation
+///
+/// # Arguments
+/// account - The account being changed. Its current quota is replaced with `quota`
+pub async fn check_domain_allocation(
+    connection: &impl ConnectionTrait,
+    domain: &str,
+    allocation: i64,
+    account: Option<i64>,
+    quota: i64,
+) -> Result<(), QuotaError> {
+    let above = || QuotaError::AboveDomainAllocation {
+        domain: domain.to_owned(),
+        allocation,
+    };
+    if quota == 0 {
+        return Err(above());
+    }
+    let pattern = format!("%@{}", escape_like(&domain.to_lowercase()));
+    let on_domain = EmailEntity::find()
+        .select_only()
+        .column(EmailColumn::Account)
+        .filter(EmailColumn::EmailType.eq(EmailType::Primary))
+        .filter(
+            Expr::expr(Func::lower(Expr::col((
+                EmailEntity,
+                EmailColumn::EmailAddress,
+            ))))
+            .like(LikeExpr::new(pattern).escape('\\')),
+        )
+        .into_query();
+    let mut others = AccountEntity::find()
+        .select_only()
+        .column(AccountColumn::Quota)
+        .filter(AccountColumn::Id.in_subquery(on_domain));
+    if let Some(account) = account {
+        others = others.filter(AccountColumn::Id.ne(account));
+    }
+    let assigned = others
+        .into_tuple::<i64>()
+        .all(connection)
+        .await?
+        .into_iter()
+        .fold(quota, i64::saturating_add);
+    if assigned > allocation {
+        Err(above())
+    } else {
+        Ok(())
+    }
+}
+#[cfg(test)]
+mod tests {
+    use chrono::Local;
+
+    use super::QuotaError;
+    use crate::{groups::GroupPermissions, GroupModel};
+
+    fn group(default_quota: Option<i64>, max_quota: Option<i64>) -> GroupModel {
+        GroupModel {
+            id: 1,
+            group_name: "user".to_owned(),
+            permissions: GroupPermissions::default(),
+            default_quota,
+            max_quota,
+            alias_limit: 0,
+            created: Local::now().into(),
+        }
+    }
+
+    #[test]
+    pub fn test_default_quota_or_unlimited() {
+        assert_eq!(group(None, None).default_quota_or_unlimited(), 0);
+        assert_eq!(group(Some(1000), None).default_quota_or_unlimited(), 1000);
+    }
+    #[test]
+    pub fn test_check_quota() {
+        let unlimited = group(None, None);
+        assert!(unlimited.check_quota(0).is_ok());
+        assert!(unlimited.check_quota(i64::MAX).is_ok());
+
+        let limited = group(None, Some(1000));
+        assert!(limited.check_quota(1).is_ok());
+        assert!(limited.check_quota(1000).is_ok());
+        assert!(matches!(
+            limited.check_quota(1001),
+            Err(QuotaError::AboveGroupMaximum(1000))
+        ));
+        assert!(matches!(
+            limited.check_quota(0),
+            Err(QuotaError::AboveGroupMaximum(1000))
+        ));
+    }
+}
@@ -0,0 +1,75 @@
+use sea_orm::{prelude::*, ActiveValue, IntoActiveModel, QueryOrder};
+use serde::Deserialize;
+use typeshare::typeshare;
+use utils::database::EmailAddress;
+
+use super::{ActiveModel, Column as DomainSettingsColumn, Model};
+use crate::DomainSettingsEntity;
+
+#[typeshare]
+#[derive(Debug, Clone, Deserialize)]
+pub struct NewDomainSettings {
+    #[serde(default)]
+    pub sub_addressing: bool,
+}
+
+/// Returns the domain in lowercase or None if it can not be the domain of an address
+pub fn normalize_domain(domain: &str) -> Option<String> {
+    if domain.contains('@') || EmailAddress::catch_all(domain).is_err() {
+        return None;
+    }
+    Some(domain.to_lowercase())
+}
+pub async fn get_all_domain_settings(
+    connection: &impl ConnectionTrait,
+) -> Result<Vec<Model>, DbErr> {
+    DomainSettingsEntity::find()
+        .order_by_asc(DomainSettingsColumn::Domain)
+        .all(connection)
+        .await
+}
+/// `domain` must be normalized. See [normalize_domain]
+pub async fn get_domain_settings(
+    connection: &impl ConnectionTrait,
+    domain: &str,
+) -> Result<Option<Model>, DbErr> {
+    DomainSettingsEntity::find()
+        .filter(DomainSettingsColumn::Domain.eq(domain))
+        .one(connection)
+        .await
+}
+/// Replaces the settings of the domain
+pub async fn set_domain_settings(
+    connection: &impl ConnectionTrait,
+    domain: String,
+    settings: NewDomainSettings,
+) -> Result<Model, DbErr> {
+    match get_domain_settings(connection, &domain).await? {
+        Some(existing) => {
+            let mut existing = existing.into_active_model();
+            existing.sub_addressing = ActiveValue::Set(settings.sub_addressing);
+            existing.update(connection).await
+        }
+        None => {
+            ActiveModel {
+                id: ActiveValue::NotSet,
+                domain: ActiveValue::Set(domain),
+                sub_addressing: ActiveValue::Set(settings.sub_addressing),
+                created: crate::now(),
+            }
+            .insert(connection)
+            .await
+        }
+    }
+}
+/// Returns false if the domain had no settings
+pub async fn remove_domain_settings(
+    connection: &impl ConnectionTrait,
+    domain: &str,
+) -> Result<bool, DbErr> {
+    let result = DomainSettingsEntity::delete_many()
+        .filter(DomainSettingsColumn::Domain.eq(domain))
+        .exec(connection)
+        .await?;
+    Ok(result.rows_affected > 0)
+}
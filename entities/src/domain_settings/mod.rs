@@ -0,0 +1,29 @@
+pub mod database_helper;
+
+use sea_orm::{entity::prelude::*, prelude::DateTimeWithTimeZone};
+use serde::Serialize;
+use typeshare::typeshare;
+
+/// Settings for every address on a domain. A domain without settings uses the defaults
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize)]
+#[sea_orm(table_name = "domain_settings")]
+#[typeshare]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = true)]
+    pub id: i64,
+    /// Always lowercase
+    #[sea_orm(unique, column_type = "Text")]
+    pub domain: String,
+    /// Mail to `user+tag@{domain}` is delivered to `user@{domain}`.
+    ///
+    /// Applied by the Stalwart `recipients` query. An address that exists with the tag is still delivered to its own account
+    #[sea_orm(default_value = "false")]
+    pub sub_addressing: bool,
+    #[sea_orm(default_expr = "Expr::current_timestamp()")]
+    pub created: DateTimeWithTimeZone,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
@@ -35,6 +35,13 @@ pub enum EmailType {
     #[sea_orm(string_value = "list")]
     #[strum(serialize = "list")]
     List,
+    /// `*@{domain}`. Receives mail for addresses on the domain that do not exist
+    ///
+    /// On domains with sub-addressing `user+tag@{domain}` only reaches it when `user@{domain}` does not exist.
+    /// See [crate::domain_settings]
+    #[sea_orm(string_value = "catch_all")]
+    #[strum(serialize = "catch_all")]
+    CatchAll,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize)]
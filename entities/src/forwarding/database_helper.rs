@@ -0,0 +1,79 @@
+use sea_orm::{prelude::*, ActiveValue, IntoActiveModel};
+use serde::Deserialize;
+use typeshare::typeshare;
+
+use super::{ActiveModel, Column as ForwardingColumn, Destinations, Model};
+use crate::ForwardingEntity;
+
+#[typeshare]
+#[derive(Debug, Clone, Deserialize)]
+pub struct NewForwarding {
+    pub destinations: Destinations,
+    #[serde(default)]
+    pub keep_copy: bool,
+}
+
+pub async fn get_forwarding(
+    connection: &impl ConnectionTrait,
+    account: i64,
+) -> Result<Option<Model>, DbErr> {
+    ForwardingEntity::find()
+        .filter(ForwardingColumn::Account.eq(account))
+        .one(connection)
+        .await
+}
+/// Replaces the forwarding of the account
+pub async fn set_forwarding(
+    connection: &impl ConnectionTrait,
+    account: i64,
+    forwarding: NewForwarding,
+) -> Result<Model, DbErr> {
+    match get_forwarding(connection, account).await? {
+        Some(existing) => {
+            let mut existing = existing.into_active_model();
+            existing.destinations = ActiveValue::Set(forwarding.destinations);
+            existing.keep_copy = ActiveValue::Set(forwarding.keep_copy);
+            existing.update(connection).await
+        }
+        None => {
+            ActiveModel {
+                id: ActiveValue::NotSet,
+                account: ActiveValue::Set(account),
+                destinations: ActiveValue::Set(forwarding.destinations),
+                keep_copy: ActiveValue::Set(forwarding.keep_copy),
+                created: crate::now(),
+            }
+            .insert(connection)
+            .await
+        }
+    }
+}
+/// Returns false if the account was not forwarding
+pub async fn remove_forwarding(
+    connection: &impl ConnectionTrait,
+    account: i64,
+) -> Result<bool, DbErr> {
+    let result = ForwardingEntity::delete_many()
+        .filter(ForwardingColumn::Account.eq(account))
+        .exec(connection)
+        .await?;
+    Ok(result.rows_affected > 0)
+}
+/// The most addresses an account can forward to
+pub const MAX_DESTINATIONS: usize = 10;
+impl NewForwarding {
+    /// Returns why the forwarding can not be saved
+    pub fn validate(&self) -> Result<(), &'static str> {
+        let destinations = &self.destinations.0;
+        if destinations.is_empty() {
+            return Err("At least one destination is required");
+        }
+        if destinations.len() > MAX_DESTINATIONS {
+            return Err("Too many destinations");
+        }
+        if destinations.iter().any(|address| address.is_catch_all()) {
+            return Err("A catch-all can not be a destination");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,50 @@
+pub mod database_helper;
+
+use sea_orm::{entity::prelude::*, prelude::DateTimeWithTimeZone, FromJsonQueryResult};
+use serde::{Deserialize, Serialize};
+use typeshare::typeshare;
+use utils::database::EmailAddress;
+
+/// The addresses mail is forwarded to. Stored as a JSON array
+#[derive(Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize, FromJsonQueryResult)]
+#[serde(transparent)]
+pub struct Destinations(pub Vec<EmailAddress>);
+
+/// Forwards the mail of an account to other addresses.
+///
+/// Pushed to Stalwart as a Sieve script
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize)]
+#[sea_orm(table_name = "forwarding_rules")]
+#[typeshare]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = true)]
+    pub id: i64,
+    #[sea_orm(unique)]
+    pub account: i64,
+    #[sea_orm(column_type = "Json")]
+    pub destinations: Destinations,
+    /// Keep a copy in the mailbox of the account
+    pub keep_copy: bool,
+    #[sea_orm(default_expr = "Expr::current_timestamp()")]
+    pub created: DateTimeWithTimeZone,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "crate::account::Entity",
+        from = "Column::Account",
+        to = "crate::account::Column::Id",
+        on_update = "Cascade",
+        on_delete = "Cascade"
+    )]
+    Account,
+}
+
+impl Related<crate::account::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Account.def()
+    }
+}
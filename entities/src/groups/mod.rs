@@ -15,6 +15,13 @@ pub struct Model {
     pub group_name: String,
     #[sea_orm(column_type = "Json")]
     pub permissions: GroupPermissions,
+    /// The quota given to new accounts in the group when one is not provided
+    pub default_quota: Option<i64>,
+    /// The largest quota an account in the group can have
+    pub max_quota: Option<i64>,
+    /// How many aliases accounts in the group can add to themselves. 0 turns off self-managed aliases
+    #[sea_orm(default_value = 0)]
+    pub alias_limit: i32,
     #[sea_orm(default_expr = "Expr::current_timestamp()")]
     pub created: DateTimeWithTimeZone,
 }
@@ -1,14 +1,40 @@
 pub mod account;
+pub mod domain_settings;
 pub mod emails;
+pub mod forwarding;
 pub mod groups;
+pub mod list_members;
+pub mod quota_alerts;
+pub mod shared_mailboxes;
+pub mod vacation;
 
 pub use account::{
     ActiveModel as ActiveAccountModel, Entity as AccountEntity, Model as AccountModel,
 };
 use chrono::Local;
+pub use domain_settings::{
+    ActiveModel as DomainSettingsActiveModel, Entity as DomainSettingsEntity,
+    Model as DomainSettingsModel,
+};
 pub use emails::{ActiveModel as EmailActiveModel, Entity as EmailEntity, Model as EmailModel};
+pub use forwarding::{
+    ActiveModel as ForwardingActiveModel, Entity as ForwardingEntity, Model as ForwardingModel,
+};
 pub use groups::{ActiveModel as ActiveGroupModel, Entity as GroupEntity, Model as GroupModel};
+pub use list_members::{
+    ActiveModel as ListMemberActiveModel, Entity as ListMemberEntity, Model as ListMemberModel,
+};
+pub use quota_alerts::{
+    ActiveModel as QuotaAlertActiveModel, Entity as QuotaAlertEntity, Model as QuotaAlertModel,
+};
 use sea_orm::{prelude::DateTimeWithTimeZone, ActiveValue};
+pub use shared_mailboxes::{
+    ActiveModel as SharedMailboxMemberActiveModel, Entity as SharedMailboxMemberEntity,
+    Model as SharedMailboxMemberModel,
+};
+pub use vacation::{
+    ActiveModel as VacationActiveModel, Entity as VacationEntity, Model as VacationModel,
+};
 
 /// Returns an ActiveValue with the current time.
 pub fn now() -> ActiveValue<DateTimeWithTimeZone> {
@@ -0,0 +1,111 @@
+use ahash::{HashMap, HashMapExt};
+use sea_orm::{prelude::*, QueryOrder, QuerySelect};
+use serde::Serialize;
+use typeshare::typeshare;
+use utils::database::EmailAddress;
+
+use super::Column as ListMemberColumn;
+use crate::{
+    account::Column as AccountColumn,
+    emails::{Column as EmailColumn, EmailType},
+    AccountEntity, EmailEntity, EmailModel, ListMemberEntity,
+};
+
+/// A member of a list with the address the list is delivered to
+#[typeshare]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ListMember {
+    pub id: i64,
+    pub account: Option<i64>,
+    pub username: Option<String>,
+    /// The primary email of the account or the external address.
+    ///
+    /// None if the account does not have a primary email. Stalwart will skip the member
+    pub address: Option<EmailAddress>,
+    pub created: DateTimeWithTimeZone,
+}
+
+/// Gets the email if it is a list
+pub async fn get_list(
+    connection: &impl ConnectionTrait,
+    list_id: i64,
+) -> Result<Option<EmailModel>, DbErr> {
+    EmailEntity::find_by_id(list_id)
+        .filter(EmailColumn::EmailType.eq(EmailType::List))
+        .one(connection)
+        .await
+}
+
+pub async fn get_members(
+    connection: &impl ConnectionTrait,
+    list_id: i64,
+) -> Result<Vec<ListMember>, DbErr> {
+    let members = ListMemberEntity::find()
+        .filter(ListMemberColumn::List.eq(list_id))
+        .order_by_asc(ListMemberColumn::Id)
+        .all(connection)
+        .await?;
+    let account_ids: Vec<i64> = members.iter().filter_map(|member| member.account).collect();
+
+    let mut usernames: HashMap<i64, String> = HashMap::new();
+    let mut primary_emails: HashMap<i64, EmailAddress> = HashMap::new();
+    if !account_ids.is_empty() {
+        usernames = AccountEntity::find()
+            .select_only()
+            .columns([AccountColumn::Id, AccountColumn::Username])
+            .filter(AccountColumn::Id.is_in(account_ids.clone()))
+            .into_tuple::<(i64, String)>()
+            .all(connection)
+            .await?
+            .into_iter()
+            .collect();
+        primary_emails = EmailEntity::find()
+            .select_only()
+            .columns([EmailColumn::Account, EmailColumn::EmailAddress])
+            .filter(EmailColumn::Account.is_in(account_ids))
+            .filter(EmailColumn::EmailType.eq(EmailType::Primary))
+            .into_tuple::<(i64, EmailAddress)>()
+            .all(connection)
+            .await?
+            .into_iter()
+            .collect();
+    }
+
+    let members = members
+        .into_iter()
+        .map(|member| {
+            let (username, address) = match member.account {
+                Some(account) => (
+                    usernames.get(&account).cloned(),
+                    primary_emails.get(&account).cloned(),
+                ),
+                None => (None, member.external_address),
+            };
+            ListMember {
+                id: member.id,
+                account: member.account,
+                username,
+                address,
+                created: member.created,
+            }
+        })
+        .collect();
+    Ok(members)
+}
+
+/// Checks if the account or address is already on the list
+pub async fn is_member(
+    connection: &impl ConnectionTrait,
+    list_id: i64,
+    account: Option<i64>,
+    external_address: Option<&EmailAddress>,
+) -> Result<bool, DbErr> {
+    let mut query = ListMemberEntity::find().filter(ListMemberColumn::List.eq(list_id));
+    if let Some(account) = account {
+        query = query.filter(ListMemberColumn::Account.eq(account));
+    }
+    if let Some(external_address) = external_address {
+        query = query.filter(ListMemberColumn::ExternalAddress.eq(external_address.clone()));
+    }
+    query.count(connection).await.map(|count| count > 0)
+}
@@ -0,0 +1,59 @@
+pub mod database_helper;
+
+use sea_orm::{entity::prelude::*, prelude::DateTimeWithTimeZone};
+use serde::Serialize;
+use typeshare::typeshare;
+use utils::database::EmailAddress;
+
+/// A recipient of a mailing list.
+///
+/// Either an account, which receives the list at its primary address, or an external address
+///
+/// Lists have no moderation settings. Stalwart's SQL directory has no lookup to read them from
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize)]
+#[sea_orm(table_name = "list_members")]
+#[typeshare]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = true)]
+    pub id: i64,
+    /// The id of the email with the type list
+    pub list: i64,
+    pub account: Option<i64>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub external_address: Option<EmailAddress>,
+    #[sea_orm(default_expr = "Expr::current_timestamp()")]
+    pub created: DateTimeWithTimeZone,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "crate::emails::Entity",
+        from = "Column::List",
+        to = "crate::emails::Column::Id",
+        on_update = "Cascade",
+        on_delete = "Cascade"
+    )]
+    List,
+    #[sea_orm(
+        belongs_to = "crate::account::Entity",
+        from = "Column::Account",
+        to = "crate::account::Column::Id",
+        on_update = "Cascade",
+        on_delete = "Cascade"
+    )]
+    Account,
+}
+
+impl Related<crate::emails::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::List.def()
+    }
+}
+impl Related<crate::account::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Account.def()
+    }
+}
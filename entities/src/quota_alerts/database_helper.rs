@@ -0,0 +1,52 @@
+use sea_orm::{prelude::*, ActiveValue, IntoActiveModel};
+
+use super::{ActiveModel, Column as QuotaAlertColumn};
+use crate::QuotaAlertEntity;
+
+/// The highest threshold the account has been alerted for
+pub async fn get_alerted(
+    connection: &impl ConnectionTrait,
+    account: i64,
+) -> Result<Option<u8>, DbErr> {
+    let alert = QuotaAlertEntity::find()
+        .filter(QuotaAlertColumn::Account.eq(account))
+        .one(connection)
+        .await?;
+    Ok(alert.and_then(|alert| u8::try_from(alert.threshold).ok()))
+}
+/// Records the highest threshold the account has been alerted for. None removes it
+pub async fn set_alerted(
+    connection: &impl ConnectionTrait,
+    account: i64,
+    threshold: Option<u8>,
+) -> Result<(), DbErr> {
+    let existing = QuotaAlertEntity::find()
+        .filter(QuotaAlertColumn::Account.eq(account))
+        .one(connection)
+        .await?;
+    match (existing, threshold) {
+        (Some(existing), Some(threshold)) => {
+            let mut existing = existing.into_active_model();
+            existing.threshold = ActiveValue::Set(threshold as i16);
+            existing.created = crate::now();
+            existing.update(connection).await?;
+        }
+        (None, Some(threshold)) => {
+            ActiveModel {
+                id: ActiveValue::NotSet,
+                account: ActiveValue::Set(account),
+                threshold: ActiveValue::Set(threshold as i16),
+                created: crate::now(),
+            }
+            .insert(connection)
+            .await?;
+        }
+        (Some(existing), None) => {
+            QuotaAlertEntity::delete_by_id(existing.id)
+                .exec(connection)
+                .await?;
+        }
+        (None, None) => {}
+    }
+    Ok(())
+}
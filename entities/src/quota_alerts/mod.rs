@@ -0,0 +1,41 @@
+pub mod database_helper;
+
+use sea_orm::{entity::prelude::*, prelude::DateTimeWithTimeZone};
+use serde::Serialize;
+
+/// The highest quota alert threshold the account has been emailed about.
+///
+/// Removed once the account drops below every threshold
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize)]
+#[sea_orm(table_name = "quota_alerts")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = true)]
+    pub id: i64,
+    #[sea_orm(unique)]
+    pub account: i64,
+    /// A percentage of the quota
+    pub threshold: i16,
+    /// When the alert was sent
+    #[sea_orm(default_expr = "Expr::current_timestamp()")]
+    pub created: DateTimeWithTimeZone,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "crate::account::Entity",
+        from = "Column::Account",
+        to = "crate::account::Column::Id",
+        on_update = "Cascade",
+        on_delete = "Cascade"
+    )]
+    Account,
+}
+
+impl Related<crate::account::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Account.def()
+    }
+}
@@ -0,0 +1,40 @@
+use sea_orm::{prelude::*, FromQueryResult, JoinType, QueryOrder, QuerySelect};
+use serde::Serialize;
+use typeshare::typeshare;
+
+use super::{AccessLevel, Column as SharedMailboxColumn, Relation as SharedMailboxRelation};
+use crate::{account::Column as AccountColumn, SharedMailboxMemberEntity};
+
+/// A member of a shared mailbox with their account details
+#[typeshare]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, FromQueryResult)]
+pub struct SharedMailboxMember {
+    pub id: i64,
+    pub member: i64,
+    pub username: String,
+    pub name: String,
+    pub access: AccessLevel,
+    pub created: DateTimeWithTimeZone,
+}
+
+pub async fn get_members(
+    connection: &impl ConnectionTrait,
+    mailbox: i64,
+) -> Result<Vec<SharedMailboxMember>, DbErr> {
+    SharedMailboxMemberEntity::find()
+        .select_only()
+        .columns([
+            SharedMailboxColumn::Id,
+            SharedMailboxColumn::Member,
+            SharedMailboxColumn::Access,
+            SharedMailboxColumn::Created,
+        ])
+        .column(AccountColumn::Username)
+        .column(AccountColumn::Name)
+        .join(JoinType::InnerJoin, SharedMailboxRelation::Member.def())
+        .filter(SharedMailboxColumn::Mailbox.eq(mailbox))
+        .order_by_asc(AccountColumn::Username)
+        .into_model::<SharedMailboxMember>()
+        .all(connection)
+        .await
+}
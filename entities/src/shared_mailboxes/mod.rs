@@ -0,0 +1,78 @@
+pub mod database_helper;
+
+use sea_orm::{entity::prelude::*, prelude::DateTimeWithTimeZone};
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumString};
+use typeshare::typeshare;
+
+/// What a member can do with a shared mailbox
+///
+/// Stalwart gives `read` members access to the mailbox and lets `send_as` members send from its addresses
+#[derive(
+    DeriveActiveEnum,
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    Default,
+    Deserialize,
+    Serialize,
+    EnumString,
+    Display,
+    EnumIter,
+)]
+#[sea_orm(rs_type = "String", db_type = "Text")]
+#[serde(rename_all = "snake_case")]
+#[typeshare]
+pub enum AccessLevel {
+    #[default]
+    #[sea_orm(string_value = "read")]
+    #[strum(serialize = "read")]
+    Read,
+    #[sea_orm(string_value = "send_as")]
+    #[strum(serialize = "send_as")]
+    SendAs,
+    /// Read and Send As
+    #[sea_orm(string_value = "full")]
+    #[strum(serialize = "full")]
+    Full,
+}
+
+/// Gives an account access to a group account
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize)]
+#[sea_orm(table_name = "shared_mailbox_members")]
+#[typeshare]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = true)]
+    pub id: i64,
+    /// The account with the type group
+    pub mailbox: i64,
+    pub member: i64,
+    #[sea_orm(default_value = "read", column_type = "Text")]
+    pub access: AccessLevel,
+    #[sea_orm(default_expr = "Expr::current_timestamp()")]
+    pub created: DateTimeWithTimeZone,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "crate::account::Entity",
+        from = "Column::Mailbox",
+        to = "crate::account::Column::Id",
+        on_update = "Cascade",
+        on_delete = "Cascade"
+    )]
+    Mailbox,
+    #[sea_orm(
+        belongs_to = "crate::account::Entity",
+        from = "Column::Member",
+        to = "crate::account::Column::Id",
+        on_update = "Cascade",
+        on_delete = "Cascade"
+    )]
+    Member,
+}
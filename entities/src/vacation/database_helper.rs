@@ -0,0 +1,89 @@
+use sea_orm::{prelude::*, ActiveValue, IntoActiveModel, TryIntoModel};
+use serde::Deserialize;
+use typeshare::typeshare;
+
+use super::{ActiveModel, Column as VacationColumn, Model};
+use crate::VacationEntity;
+
+#[typeshare]
+#[derive(Debug, Clone, Deserialize)]
+pub struct NewVacation {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    pub start_date: Option<Date>,
+    pub end_date: Option<Date>,
+    pub subject: String,
+    pub text_body: String,
+    pub html_body: Option<String>,
+    #[serde(default = "default_reply_interval")]
+    pub reply_interval: i32,
+}
+fn default_enabled() -> bool {
+    true
+}
+fn default_reply_interval() -> i32 {
+    7
+}
+impl NewVacation {
+    /// Returns why the vacation responder can not be saved
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if let (Some(start), Some(end)) = (self.start_date, self.end_date) {
+            if end < start {
+                return Err("The end date is before the start date");
+            }
+        }
+        if self.text_body.trim().is_empty() {
+            return Err("The reply can not be empty");
+        }
+        // The range Stalwart and most Sieve implementations accept for `:days`
+        if !(1..=365).contains(&self.reply_interval) {
+            return Err("The reply interval must be between 1 and 365 days");
+        }
+        Ok(())
+    }
+}
+
+pub async fn get_vacation(
+    connection: &impl ConnectionTrait,
+    account: i64,
+) -> Result<Option<Model>, DbErr> {
+    VacationEntity::find()
+        .filter(VacationColumn::Account.eq(account))
+        .one(connection)
+        .await
+}
+/// Replaces the vacation responder of the account
+pub async fn set_vacation(
+    connection: &impl ConnectionTrait,
+    account: i64,
+    vacation: NewVacation,
+) -> Result<Model, DbErr> {
+    let mut model = match get_vacation(connection, account).await? {
+        Some(existing) => existing.into_active_model(),
+        None => ActiveModel {
+            id: ActiveValue::NotSet,
+            account: ActiveValue::Set(account),
+            created: crate::now(),
+            ..Default::default()
+        },
+    };
+    model.enabled = ActiveValue::Set(vacation.enabled);
+    model.start_date = ActiveValue::Set(vacation.start_date);
+    model.end_date = ActiveValue::Set(vacation.end_date);
+    model.subject = ActiveValue::Set(vacation.subject);
+    model.text_body = ActiveValue::Set(vacation.text_body);
+    model.html_body = ActiveValue::Set(vacation.html_body);
+    model.reply_interval = ActiveValue::Set(vacation.reply_interval);
+    model.save(connection).await?.try_into_model()
+}
+/// Returns false if the account did not have a vacation responder
+pub async fn remove_vacation(
+    connection: &impl ConnectionTrait,
+    account: i64,
+) -> Result<bool, DbErr> {
+    let result = VacationEntity::delete_many()
+        .filter(VacationColumn::Account.eq(account))
+        .exec(connection)
+        .await?;
+    Ok(result.rows_affected > 0)
+}
@@ -0,0 +1,53 @@
+pub mod database_helper;
+
+use sea_orm::{entity::prelude::*, prelude::DateTimeWithTimeZone};
+use serde::Serialize;
+use typeshare::typeshare;
+
+/// Replies to incoming mail while the account is away.
+///
+/// Pushed to Stalwart as a Sieve `vacation` action
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize)]
+#[sea_orm(table_name = "vacation_responders")]
+#[typeshare]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = true)]
+    pub id: i64,
+    #[sea_orm(unique)]
+    pub account: i64,
+    pub enabled: bool,
+    /// The first day replies are sent. None to start right away
+    pub start_date: Option<Date>,
+    /// The last day replies are sent. None to reply until disabled
+    pub end_date: Option<Date>,
+    #[sea_orm(column_type = "Text")]
+    pub subject: String,
+    #[sea_orm(column_type = "Text")]
+    pub text_body: String,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub html_body: Option<String>,
+    /// Days before the same sender gets another reply
+    pub reply_interval: i32,
+    #[sea_orm(default_expr = "Expr::current_timestamp()")]
+    pub created: DateTimeWithTimeZone,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "crate::account::Entity",
+        from = "Column::Account",
+        to = "crate::account::Column::Id",
+        on_update = "Cascade",
+        on_delete = "Cascade"
+    )]
+    Account,
+}
+
+impl Related<crate::account::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Account.def()
+    }
+}
@@ -1,24 +1,52 @@
 pub use sea_orm_migration::prelude::*;
 
 mod m20220101_000001_create_table;
+mod m20231128_000002_quota_alerts;
+mod m20231201_000003_group_quotas;
+mod m20231210_000004_list_members;
+mod m20231215_000005_shared_mailbox_members;
+mod m20231220_000006_forwarding_rules;
+mod m20231222_000007_vacation_responders;
+mod m20231226_000008_group_alias_limit;
+mod m20231228_000009_backup_email_verified;
+mod m20240104_000010_email_indexes;
+mod m20240110_000011_domain_settings;
 
+/// The migrations define every table themselves instead of creating them from the entities.
+/// So a change to an entity needs a new migration
 pub struct Migrator;
 
-macro_rules! entities {
-    ($schema:ident,$manager:ident, $($entity_type:path),*) => {
-        $(
-        {
-            let statement = $schema.create_table_from_entity($entity_type);
-            $manager.create_table(statement).await?;
-        }
-        )*
+/// A text column that is in a unique key or an index.
+///
+/// MySQL can only index `TEXT` with a prefix length. So it is a `VARCHAR(255)` there
+pub(crate) fn indexed_text<T: IntoIden>(manager: &SchemaManager, column: T) -> ColumnDef {
+    let mut definition = ColumnDef::new(column);
+    match manager.get_database_backend() {
+        sea_orm::DatabaseBackend::MySql => definition.string_len(255),
+        _ => definition.text(),
     };
+    definition
+}
+/// The default of a text column. MySQL only accepts them written as an expression
+pub(crate) fn text_default(value: &'static str) -> SimpleExpr {
+    Expr::cust(format!("('{value}')"))
 }
-pub(crate) use entities;
 
 #[async_trait::async_trait]
 impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
-        vec![Box::new(m20220101_000001_create_table::Migration)]
+        vec![
+            Box::new(m20220101_000001_create_table::Migration),
+            Box::new(m20231128_000002_quota_alerts::Migration),
+            Box::new(m20231201_000003_group_quotas::Migration),
+            Box::new(m20231210_000004_list_members::Migration),
+            Box::new(m20231215_000005_shared_mailbox_members::Migration),
+            Box::new(m20231220_000006_forwarding_rules::Migration),
+            Box::new(m20231222_000007_vacation_responders::Migration),
+            Box::new(m20231226_000008_group_alias_limit::Migration),
+            Box::new(m20231228_000009_backup_email_verified::Migration),
+            Box::new(m20240104_000010_email_indexes::Migration),
+            Box::new(m20240110_000011_domain_settings::Migration),
+        ]
     }
 }
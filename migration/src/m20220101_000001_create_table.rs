@@ -1,17 +1,150 @@
 use sea_orm_migration::prelude::*;
 
-use crate::sea_orm::Schema;
+use crate::{indexed_text, text_default};
 
+/// Creates the groups, accounts and emails.
+///
+/// The columns added by later migrations are not here. Databases created when this migration built the tables from the entities
+/// already have them and the later migrations skip those columns
 #[derive(DeriveMigrationName)]
 pub struct Migration;
 
 #[async_trait::async_trait]
 impl MigrationTrait for Migration {
     async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
-        let schema = Schema::new(manager.get_database_backend());
-        crate::entities!(schema, manager, entities::GroupEntity);
-        crate::entities!(schema, manager, entities::AccountEntity);
-        crate::entities!(schema, manager, entities::EmailEntity);
+        manager
+            .create_table(
+                Table::create()
+                    .table(Groups::Table)
+                    .col(
+                        ColumnDef::new(Groups::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        indexed_text(manager, Groups::GroupName)
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(ColumnDef::new(Groups::Permissions).json().not_null())
+                    .col(
+                        ColumnDef::new(Groups::Created)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_table(
+                Table::create()
+                    .table(Accounts::Table)
+                    .col(
+                        ColumnDef::new(Accounts::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Accounts::Name).text().not_null())
+                    .col(
+                        indexed_text(manager, Accounts::Username)
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(
+                        ColumnDef::new(Accounts::Description)
+                            .text()
+                            .not_null()
+                            .default(text_default("")),
+                    )
+                    .col(ColumnDef::new(Accounts::GroupId).big_integer().not_null())
+                    .col(ColumnDef::new(Accounts::Password).text().not_null())
+                    .col(
+                        ColumnDef::new(Accounts::RequirePasswordChange)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .col(
+                        ColumnDef::new(Accounts::Quota)
+                            .big_integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(
+                        ColumnDef::new(Accounts::AccountType)
+                            .text()
+                            .not_null()
+                            .default(text_default("individual")),
+                    )
+                    .col(
+                        ColumnDef::new(Accounts::Active)
+                            .boolean()
+                            .not_null()
+                            .default(true),
+                    )
+                    .col(
+                        indexed_text(manager, Accounts::BackupEmail)
+                            .null()
+                            .unique_key(),
+                    )
+                    .col(
+                        ColumnDef::new(Accounts::Created)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-accounts-group_id")
+                            .from(Accounts::Table, Accounts::GroupId)
+                            .to(Groups::Table, Groups::Id)
+                            .on_update(ForeignKeyAction::Cascade)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_table(
+                Table::create()
+                    .table(Emails::Table)
+                    .col(
+                        ColumnDef::new(Emails::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Emails::Account).big_integer().not_null())
+                    .col(indexed_text(manager, Emails::EmailAddress).not_null())
+                    .col(
+                        ColumnDef::new(Emails::EmailType)
+                            .text()
+                            .not_null()
+                            .default(text_default("alias")),
+                    )
+                    .col(
+                        ColumnDef::new(Emails::Created)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-emails-account")
+                            .from(Emails::Table, Emails::Account)
+                            .to(Accounts::Table, Accounts::Id)
+                            .on_update(ForeignKeyAction::Cascade)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
 
         Ok(())
     }
@@ -32,14 +165,35 @@ impl MigrationTrait for Migration {
 #[derive(Iden)]
 pub enum Groups {
     Table,
+    Id,
+    GroupName,
+    Permissions,
+    Created,
 }
 
 #[derive(Iden)]
 pub enum Accounts {
     Table,
+    Id,
+    Name,
+    Username,
+    Description,
+    GroupId,
+    Password,
+    RequirePasswordChange,
+    Quota,
+    AccountType,
+    Active,
+    BackupEmail,
+    Created,
 }
 
 #[derive(Iden)]
 pub enum Emails {
     Table,
+    Id,
+    Account,
+    EmailAddress,
+    EmailType,
+    Created,
 }
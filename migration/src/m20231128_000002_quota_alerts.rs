@@ -0,0 +1,74 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20220101_000001_create_table::Accounts;
+
+/// Adds the highest quota alert threshold each account has been emailed about.
+/// So a restart does not send the alerts again
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(QuotaAlerts::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(QuotaAlerts::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(QuotaAlerts::Account)
+                            .big_integer()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(
+                        ColumnDef::new(QuotaAlerts::Threshold)
+                            .small_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(QuotaAlerts::Created)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-quota_alerts-account")
+                            .from(QuotaAlerts::Table, QuotaAlerts::Account)
+                            .to(Accounts::Table, Accounts::Id)
+                            .on_update(ForeignKeyAction::Cascade)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                TableDropStatement::new()
+                    .table(QuotaAlerts::Table)
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+}
+#[derive(Iden)]
+pub enum QuotaAlerts {
+    Table,
+    Id,
+    Account,
+    Threshold,
+    Created,
+}
@@ -0,0 +1,48 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20220101_000001_create_table::Groups;
+
+/// Adds the default and maximum quotas to groups.
+///
+/// Databases created when the first migration built the tables from the entities may already have the columns
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in [GroupQuotas::DefaultQuota, GroupQuotas::MaxQuota] {
+            if manager.has_column("groups", column.to_string()).await? {
+                continue;
+            }
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Groups::Table)
+                        .add_column(ColumnDef::new(column).big_integer().null())
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in [GroupQuotas::DefaultQuota, GroupQuotas::MaxQuota] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Groups::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
+#[derive(Iden, Clone, Copy)]
+pub enum GroupQuotas {
+    DefaultQuota,
+    MaxQuota,
+}
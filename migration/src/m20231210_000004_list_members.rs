@@ -0,0 +1,74 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20220101_000001_create_table::{Accounts, Emails};
+
+/// Adds the members of mailing lists. A member is either an account or an external address
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ListMembers::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ListMembers::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(ListMembers::List).big_integer().not_null())
+                    .col(ColumnDef::new(ListMembers::Account).big_integer().null())
+                    .col(ColumnDef::new(ListMembers::ExternalAddress).text().null())
+                    .col(
+                        ColumnDef::new(ListMembers::Created)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-list_members-list")
+                            .from(ListMembers::Table, ListMembers::List)
+                            .to(Emails::Table, Emails::Id)
+                            .on_update(ForeignKeyAction::Cascade)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-list_members-account")
+                            .from(ListMembers::Table, ListMembers::Account)
+                            .to(Accounts::Table, Accounts::Id)
+                            .on_update(ForeignKeyAction::Cascade)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                TableDropStatement::new()
+                    .table(ListMembers::Table)
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+}
+#[derive(Iden)]
+pub enum ListMembers {
+    Table,
+    Id,
+    List,
+    Account,
+    ExternalAddress,
+    Created,
+}
@@ -0,0 +1,87 @@
+use sea_orm_migration::prelude::*;
+
+use crate::{m20220101_000001_create_table::Accounts, text_default};
+
+/// Adds the accounts that can use a shared mailbox and what they can do with it
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(SharedMailboxMembers::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(SharedMailboxMembers::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(SharedMailboxMembers::Mailbox)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(SharedMailboxMembers::Member)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(SharedMailboxMembers::Access)
+                            .text()
+                            .not_null()
+                            .default(text_default("read")),
+                    )
+                    .col(
+                        ColumnDef::new(SharedMailboxMembers::Created)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-shared_mailbox_members-mailbox")
+                            .from(SharedMailboxMembers::Table, SharedMailboxMembers::Mailbox)
+                            .to(Accounts::Table, Accounts::Id)
+                            .on_update(ForeignKeyAction::Cascade)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-shared_mailbox_members-member")
+                            .from(SharedMailboxMembers::Table, SharedMailboxMembers::Member)
+                            .to(Accounts::Table, Accounts::Id)
+                            .on_update(ForeignKeyAction::Cascade)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                TableDropStatement::new()
+                    .table(SharedMailboxMembers::Table)
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+}
+#[derive(Iden)]
+pub enum SharedMailboxMembers {
+    Table,
+    Id,
+    Mailbox,
+    Member,
+    Access,
+    Created,
+}
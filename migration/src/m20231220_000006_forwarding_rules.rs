@@ -0,0 +1,79 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20220101_000001_create_table::Accounts;
+
+/// Adds where the mail of an account is forwarded to. An account has at most one rule
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ForwardingRules::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ForwardingRules::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(ForwardingRules::Account)
+                            .big_integer()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(
+                        ColumnDef::new(ForwardingRules::Destinations)
+                            .json()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ForwardingRules::KeepCopy)
+                            .boolean()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ForwardingRules::Created)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-forwarding_rules-account")
+                            .from(ForwardingRules::Table, ForwardingRules::Account)
+                            .to(Accounts::Table, Accounts::Id)
+                            .on_update(ForeignKeyAction::Cascade)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                TableDropStatement::new()
+                    .table(ForwardingRules::Table)
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+}
+#[derive(Iden)]
+pub enum ForwardingRules {
+    Table,
+    Id,
+    Account,
+    Destinations,
+    KeepCopy,
+    Created,
+}
@@ -0,0 +1,97 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20220101_000001_create_table::Accounts;
+
+/// Adds the automatic replies of an account. An account has at most one responder
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(VacationResponders::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(VacationResponders::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(VacationResponders::Account)
+                            .big_integer()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(
+                        ColumnDef::new(VacationResponders::Enabled)
+                            .boolean()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(VacationResponders::StartDate).date().null())
+                    .col(ColumnDef::new(VacationResponders::EndDate).date().null())
+                    .col(
+                        ColumnDef::new(VacationResponders::Subject)
+                            .text()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(VacationResponders::TextBody)
+                            .text()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(VacationResponders::HtmlBody).text().null())
+                    .col(
+                        ColumnDef::new(VacationResponders::ReplyInterval)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(VacationResponders::Created)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-vacation_responders-account")
+                            .from(VacationResponders::Table, VacationResponders::Account)
+                            .to(Accounts::Table, Accounts::Id)
+                            .on_update(ForeignKeyAction::Cascade)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                TableDropStatement::new()
+                    .table(VacationResponders::Table)
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+}
+#[derive(Iden)]
+pub enum VacationResponders {
+    Table,
+    Id,
+    Account,
+    Enabled,
+    StartDate,
+    EndDate,
+    Subject,
+    TextBody,
+    HtmlBody,
+    ReplyInterval,
+    Created,
+}
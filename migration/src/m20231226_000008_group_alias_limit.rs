@@ -0,0 +1,47 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20220101_000001_create_table::Groups;
+
+/// Adds the number of aliases accounts in a group can add to themselves
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        if manager
+            .has_column("groups", GroupAliasLimit::AliasLimit.to_string())
+            .await?
+        {
+            return Ok(());
+        }
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Groups::Table)
+                    .add_column(
+                        ColumnDef::new(GroupAliasLimit::AliasLimit)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Groups::Table)
+                    .drop_column(GroupAliasLimit::AliasLimit)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+#[derive(Iden, Clone, Copy)]
+pub enum GroupAliasLimit {
+    AliasLimit,
+}
@@ -0,0 +1,64 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20220101_000001_create_table::Accounts;
+
+/// Adds when the backup email of an account was confirmed.
+///
+/// Backup emails that existed before are treated as verified since the account was created.
+/// Otherwise every existing account would stop getting password resets and notifications at them
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        if manager
+            .has_column(
+                "accounts",
+                BackupEmailVerified::BackupEmailVerified.to_string(),
+            )
+            .await?
+        {
+            return Ok(());
+        }
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Accounts::Table)
+                    .add_column(
+                        ColumnDef::new(BackupEmailVerified::BackupEmailVerified)
+                            .timestamp_with_time_zone()
+                            .null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .exec_stmt(
+                Query::update()
+                    .table(Accounts::Table)
+                    .value(
+                        BackupEmailVerified::BackupEmailVerified,
+                        Expr::col(Accounts::Created),
+                    )
+                    .and_where(Expr::col(Accounts::BackupEmail).is_not_null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Accounts::Table)
+                    .drop_column(BackupEmailVerified::BackupEmailVerified)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+#[derive(Iden, Clone, Copy)]
+pub enum BackupEmailVerified {
+    BackupEmailVerified,
+}
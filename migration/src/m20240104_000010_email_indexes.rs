@@ -0,0 +1,96 @@
+use sea_orm_migration::prelude::*;
+
+use crate::{
+    m20220101_000001_create_table::{Accounts, Emails},
+    sea_orm::DatabaseBackend,
+};
+
+/// Indexes the columns Stalwart and the panel look accounts up by.
+/// Makes sure an address only belongs to one account and an account only has one primary address.
+///
+/// MySQL does not have partial indexes. So the unique primary address is a generated column there.
+/// Fails if an address is already used twice or an account already has two primary addresses
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for (name, table, column, unique) in indexes() {
+            let mut index = Index::create();
+            index.name(name).table(table).col(column);
+            if unique {
+                index.unique();
+            }
+            manager.create_index(index).await?;
+        }
+        let statement = match manager.get_database_backend() {
+            DatabaseBackend::MySql => {
+                "ALTER TABLE emails ADD COLUMN primary_account BIGINT AS (IF(email_type = 'primary', account, NULL)) VIRTUAL, ADD UNIQUE INDEX `idx-emails-primary_account` (primary_account)"
+            }
+            DatabaseBackend::Postgres | DatabaseBackend::Sqlite => {
+                r#"CREATE UNIQUE INDEX "idx-emails-primary_account" ON emails (account) WHERE email_type = 'primary'"#
+            }
+        };
+        manager
+            .get_connection()
+            .execute_unprepared(statement)
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        if manager.get_database_backend() == DatabaseBackend::MySql {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Emails::Table)
+                        .drop_column(PrimaryAccount::PrimaryAccount)
+                        .to_owned(),
+                )
+                .await?;
+        } else {
+            manager
+                .drop_index(
+                    Index::drop()
+                        .name("idx-emails-primary_account")
+                        .table(Emails::Table)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        for (name, table, _, _) in indexes() {
+            manager
+                .drop_index(Index::drop().name(name).table(table).to_owned())
+                .await?;
+        }
+        Ok(())
+    }
+}
+/// The name, table, column and if the index is unique
+fn indexes() -> [(&'static str, DynIden, DynIden, bool); 3] {
+    [
+        (
+            "idx-emails-email_address",
+            Emails::Table.into_iden(),
+            Emails::EmailAddress.into_iden(),
+            true,
+        ),
+        (
+            "idx-emails-account",
+            Emails::Table.into_iden(),
+            Emails::Account.into_iden(),
+            false,
+        ),
+        (
+            "idx-accounts-group_id",
+            Accounts::Table.into_iden(),
+            Accounts::GroupId.into_iden(),
+            false,
+        ),
+    ]
+}
+#[derive(Iden, Clone, Copy)]
+pub enum PrimaryAccount {
+    PrimaryAccount,
+}
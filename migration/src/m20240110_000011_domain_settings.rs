@@ -0,0 +1,67 @@
+use sea_orm_migration::prelude::*;
+
+use crate::indexed_text;
+
+/// Adds settings that apply to every address on a domain.
+///
+/// A domain without a row keeps the defaults
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(DomainSettings::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(DomainSettings::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        indexed_text(manager, DomainSettings::Domain)
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(
+                        ColumnDef::new(DomainSettings::SubAddressing)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .col(
+                        ColumnDef::new(DomainSettings::Created)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                TableDropStatement::new()
+                    .table(DomainSettings::Table)
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+}
+#[derive(Iden)]
+pub enum DomainSettings {
+    Table,
+    Id,
+    Domain,
+    SubAddressing,
+    Created,
+}
@@ -0,0 +1,195 @@
+//! Runs the migrations against an in-memory SQLite database.
+use entities::{
+    account::AccountType, emails::EmailType, groups::GroupPermissions, AccountEntity,
+    ActiveAccountModel, ActiveGroupModel, DomainSettingsEntity, EmailActiveModel, EmailEntity,
+    ForwardingEntity, GroupEntity, ListMemberEntity, QuotaAlertEntity, SharedMailboxMemberEntity,
+    VacationEntity,
+};
+use migration::{Migrator, MigratorTrait};
+use sea_orm_migration::sea_orm::{
+    ActiveValue, ConnectionTrait, Database, DatabaseConnection, DbErr, EntityTrait, InsertResult,
+};
+use utils::database::{EmailAddress, Password};
+
+async fn migrate() -> DatabaseConnection {
+    let connection = Database::connect("sqlite::memory:")
+        .await
+        .expect("Failed to open the test database");
+    Migrator::up(&connection, None)
+        .await
+        .expect("Failed to migrate the test database");
+    connection
+}
+/// Selecting every column of an entity fails if a migration is missing for it
+async fn check_entities(connection: &DatabaseConnection) {
+    GroupEntity::find().all(connection).await.unwrap();
+    AccountEntity::find().all(connection).await.unwrap();
+    EmailEntity::find().all(connection).await.unwrap();
+    ListMemberEntity::find().all(connection).await.unwrap();
+    SharedMailboxMemberEntity::find()
+        .all(connection)
+        .await
+        .unwrap();
+    ForwardingEntity::find().all(connection).await.unwrap();
+    VacationEntity::find().all(connection).await.unwrap();
+    QuotaAlertEntity::find().all(connection).await.unwrap();
+    DomainSettingsEntity::find().all(connection).await.unwrap();
+}
+#[tokio::test]
+async fn migrations_match_entities() {
+    let connection = migrate().await;
+    assert!(Migrator::get_pending_migrations(&connection)
+        .await
+        .unwrap()
+        .is_empty());
+    check_entities(&connection).await;
+
+    Migrator::reset(&connection)
+        .await
+        .expect("Failed to roll back the migrations");
+    assert_eq!(
+        Migrator::get_pending_migrations(&connection)
+            .await
+            .unwrap()
+            .len(),
+        Migrator::migrations().len()
+    );
+    Migrator::up(&connection, None)
+        .await
+        .expect("Failed to migrate the test database again");
+    check_entities(&connection).await;
+}
+async fn add_email(
+    connection: &DatabaseConnection,
+    account: i64,
+    email_address: &str,
+    email_type: EmailType,
+) -> Result<InsertResult<EmailActiveModel>, DbErr> {
+    let email = EmailActiveModel {
+        id: ActiveValue::NotSet,
+        account: ActiveValue::Set(account),
+        email_address: ActiveValue::Set(EmailAddress::new(email_address).unwrap()),
+        email_type: ActiveValue::Set(email_type),
+        created: entities::now(),
+    };
+    EmailEntity::insert(email).exec(connection).await
+}
+#[tokio::test]
+async fn addresses_are_unique() {
+    let connection = migrate().await;
+    let group = ActiveGroupModel {
+        id: ActiveValue::Set(1),
+        group_name: ActiveValue::Set("user".to_owned()),
+        permissions: ActiveValue::Set(GroupPermissions::default()),
+        default_quota: ActiveValue::Set(None),
+        max_quota: ActiveValue::Set(None),
+        alias_limit: ActiveValue::Set(0),
+        created: entities::now(),
+    };
+    GroupEntity::insert(group).exec(&connection).await.unwrap();
+    let mut accounts = Vec::new();
+    for username in ["alice", "bob"] {
+        let account = ActiveAccountModel {
+            id: ActiveValue::NotSet,
+            name: ActiveValue::Set(username.to_owned()),
+            username: ActiveValue::Set(username.to_owned()),
+            description: ActiveValue::Set(String::new()),
+            group_id: ActiveValue::Set(1),
+            password: ActiveValue::Set(Password::new_hashed("")),
+            require_password_change: ActiveValue::Set(false),
+            quota: ActiveValue::Set(0),
+            account_type: ActiveValue::Set(AccountType::Individual),
+            active: ActiveValue::NotSet,
+            backup_email: ActiveValue::Set(None),
+            backup_email_verified: ActiveValue::Set(None),
+            created: entities::now(),
+        };
+        let id = AccountEntity::insert(account)
+            .exec(&connection)
+            .await
+            .unwrap()
+            .last_insert_id;
+        accounts.push(id);
+    }
+    let bob = AccountEntity::find_by_id(accounts[1])
+        .one(&connection)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(bob.active, "accounts are active by default");
+
+    add_email(
+        &connection,
+        accounts[0],
+        "shared@example.com",
+        EmailType::Primary,
+    )
+    .await
+    .unwrap();
+    assert!(
+        add_email(
+            &connection,
+            accounts[1],
+            "shared@example.com",
+            EmailType::Alias
+        )
+        .await
+        .is_err(),
+        "An address only belongs to one account"
+    );
+    assert!(
+        add_email(
+            &connection,
+            accounts[0],
+            "second@example.com",
+            EmailType::Primary
+        )
+        .await
+        .is_err(),
+        "An account only has one primary address"
+    );
+    add_email(
+        &connection,
+        accounts[0],
+        "second@example.com",
+        EmailType::Alias,
+    )
+    .await
+    .unwrap();
+    add_email(
+        &connection,
+        accounts[1],
+        "bob@example.com",
+        EmailType::Primary,
+    )
+    .await
+    .unwrap();
+}
+#[tokio::test]
+async fn existing_backup_emails_are_verified() {
+    let connection = Database::connect("sqlite::memory:").await.unwrap();
+    // Up to the migration before backup_email_verified
+    Migrator::up(&connection, Some(8)).await.unwrap();
+    for statement in [
+        "INSERT INTO groups (id, group_name, permissions) VALUES (1, 'user', '{}')",
+        "INSERT INTO accounts (id, name, username, group_id, password, backup_email) \
+            VALUES (1, 'alice', 'alice', 1, '', 'alice@backup.example.com'), \
+            (2, 'bob', 'bob', 1, '', NULL)",
+    ] {
+        connection.execute_unprepared(statement).await.unwrap();
+    }
+    Migrator::up(&connection, None).await.unwrap();
+
+    let alice = AccountEntity::find_by_id(1)
+        .one(&connection)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(alice.backup_email_verified, Some(alice.created));
+    let bob = AccountEntity::find_by_id(2)
+        .one(&connection)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(bob.backup_email_verified, None);
+}
@@ -0,0 +1,332 @@
+//! Runs every lookup query Stalwart is configured with against a migrated database.
+//!
+//! The SQLite queries always run against an in-memory database.
+//! Set `STALWART_PANEL_TEST_POSTGRES` and or `STALWART_PANEL_TEST_MYSQL` to a database url to run them against those.
+//! Those tests are ignored by default. Run them with `cargo test -p migration -- --ignored`.
+//! CI runs them against the Postgres and MySQL service containers of the `stalwart-queries` job.
+//! **Everything in the database is dropped.**
+use entities::{
+    account::AccountType, emails::EmailType, groups::GroupPermissions,
+    shared_mailboxes::AccessLevel, AccountEntity, ActiveAccountModel, ActiveGroupModel,
+    DomainSettingsActiveModel, DomainSettingsEntity, EmailActiveModel, EmailEntity, GroupEntity,
+    ListMemberActiveModel, ListMemberEntity, SharedMailboxMemberActiveModel,
+    SharedMailboxMemberEntity,
+};
+use migration::{Migrator, MigratorTrait};
+use sea_orm_migration::sea_orm::{
+    ActiveValue, ConnectionTrait, Database, DatabaseConnection, EntityTrait, QueryResult, Statement,
+};
+use utils::{
+    database::{EmailAddress, Password},
+    stalwart_config::sql::SQLQuery,
+};
+
+async fn connect(variable: &str) -> DatabaseConnection {
+    let url = std::env::var(variable)
+        .unwrap_or_else(|_| panic!("{variable} must point to a scratch database"));
+    migrate(&url).await
+}
+async fn migrate(url: &str) -> DatabaseConnection {
+    let connection = Database::connect(url)
+        .await
+        .expect("Failed to connect to the test database");
+    Migrator::fresh(&connection)
+        .await
+        .expect("Failed to migrate the test database");
+    connection
+}
+fn address(value: &str) -> EmailAddress {
+    EmailAddress::new(value).unwrap()
+}
+async fn add_account(
+    connection: &DatabaseConnection,
+    username: &str,
+    account_type: AccountType,
+    active: bool,
+) -> i64 {
+    let account = ActiveAccountModel {
+        id: ActiveValue::NotSet,
+        name: ActiveValue::Set(username.to_owned()),
+        username: ActiveValue::Set(username.to_owned()),
+        description: ActiveValue::Set(String::new()),
+        group_id: ActiveValue::Set(1),
+        password: ActiveValue::Set(Password::new_hashed("")),
+        require_password_change: ActiveValue::Set(false),
+        quota: ActiveValue::Set(0),
+        account_type: ActiveValue::Set(account_type),
+        active: ActiveValue::Set(active),
+        backup_email: ActiveValue::Set(None),
+        backup_email_verified: ActiveValue::Set(None),
+        created: entities::now(),
+    };
+    AccountEntity::insert(account)
+        .exec(connection)
+        .await
+        .unwrap()
+        .last_insert_id
+}
+async fn add_email(
+    connection: &DatabaseConnection,
+    account: i64,
+    email_address: &str,
+    email_type: EmailType,
+) -> i64 {
+    let email = EmailActiveModel {
+        id: ActiveValue::NotSet,
+        account: ActiveValue::Set(account),
+        email_address: ActiveValue::Set(address(email_address)),
+        email_type: ActiveValue::Set(email_type),
+        created: entities::now(),
+    };
+    EmailEntity::insert(email)
+        .exec(connection)
+        .await
+        .unwrap()
+        .last_insert_id
+}
+async fn add_member(
+    connection: &DatabaseConnection,
+    list: i64,
+    account: Option<i64>,
+    external_address: Option<&str>,
+) {
+    let member = ListMemberActiveModel {
+        id: ActiveValue::NotSet,
+        list: ActiveValue::Set(list),
+        account: ActiveValue::Set(account),
+        external_address: ActiveValue::Set(external_address.map(address)),
+        created: entities::now(),
+    };
+    ListMemberEntity::insert(member)
+        .exec(connection)
+        .await
+        .unwrap();
+}
+async fn add_shared_member(
+    connection: &DatabaseConnection,
+    mailbox: i64,
+    member: i64,
+    access: AccessLevel,
+) {
+    let member = SharedMailboxMemberActiveModel {
+        id: ActiveValue::NotSet,
+        mailbox: ActiveValue::Set(mailbox),
+        member: ActiveValue::Set(member),
+        access: ActiveValue::Set(access),
+        created: entities::now(),
+    };
+    SharedMailboxMemberEntity::insert(member)
+        .exec(connection)
+        .await
+        .unwrap();
+}
+/// Alice and Bob (inactive) have primary emails, Carol does not but is the catch-all for `example.com`.
+/// `team@example.com` is a list of all three and `guest@example.org`.
+/// `support@example.com` is a shared mailbox. Alice has full access and Carol can only read it.
+/// `example.com` has sub-addressing and Bob has the alias `alice+bob@example.com`.
+/// Dave is on `example.net`, which does not.
+async fn seed(connection: &DatabaseConnection) {
+    let group = ActiveGroupModel {
+        id: ActiveValue::Set(1),
+        group_name: ActiveValue::Set("user".to_owned()),
+        permissions: ActiveValue::Set(GroupPermissions::default()),
+        default_quota: ActiveValue::Set(None),
+        max_quota: ActiveValue::Set(None),
+        alias_limit: ActiveValue::Set(0),
+        created: entities::now(),
+    };
+    GroupEntity::insert(group).exec(connection).await.unwrap();
+    let alice = add_account(connection, "alice", AccountType::Individual, true).await;
+    let bob = add_account(connection, "bob", AccountType::Individual, false).await;
+    let carol = add_account(connection, "carol", AccountType::Individual, true).await;
+    add_email(connection, alice, "alice@example.com", EmailType::Primary).await;
+    add_email(connection, bob, "bob@example.com", EmailType::Primary).await;
+    add_email(connection, carol, "*@example.com", EmailType::CatchAll).await;
+    let list = add_email(connection, alice, "team@example.com", EmailType::List).await;
+    for member in [alice, bob, carol] {
+        add_member(connection, list, Some(member), None).await;
+    }
+    add_member(connection, list, None, Some("guest@example.org")).await;
+    let support = add_account(connection, "support", AccountType::Group, true).await;
+    add_email(
+        connection,
+        support,
+        "support@example.com",
+        EmailType::Primary,
+    )
+    .await;
+    add_shared_member(connection, support, alice, AccessLevel::Full).await;
+    add_shared_member(connection, support, carol, AccessLevel::Read).await;
+    add_email(connection, bob, "alice+bob@example.com", EmailType::Alias).await;
+    let dave = add_account(connection, "dave", AccountType::Individual, true).await;
+    add_email(connection, dave, "dave@example.net", EmailType::Primary).await;
+    let domain = DomainSettingsActiveModel {
+        id: ActiveValue::NotSet,
+        domain: ActiveValue::Set("example.com".to_owned()),
+        sub_addressing: ActiveValue::Set(true),
+        created: entities::now(),
+    };
+    DomainSettingsEntity::insert(domain)
+        .exec(connection)
+        .await
+        .unwrap();
+}
+async fn rows(connection: &DatabaseConnection, sql: &str, value: &str) -> Vec<QueryResult> {
+    connection
+        .query_all(Statement::from_sql_and_values(
+            connection.get_database_backend(),
+            sql,
+            [value.into()],
+        ))
+        .await
+        .unwrap_or_else(|error| panic!("`{sql}` failed: {error}"))
+}
+/// The first column of every row
+async fn query(connection: &DatabaseConnection, sql: &str, value: &str) -> Vec<String> {
+    rows(connection, sql, value)
+        .await
+        .into_iter()
+        .map(|row| row.try_get_by_index::<String>(0).unwrap())
+        .collect()
+}
+/// The columns Stalwart maps with `SQLColumns` (name, type, description and quota)
+async fn query_account(
+    connection: &DatabaseConnection,
+    sql: &str,
+    username: &str,
+) -> Vec<(String, String, String, i64)> {
+    rows(connection, sql, username)
+        .await
+        .into_iter()
+        .map(|row| {
+            (
+                row.try_get("", "username").unwrap(),
+                row.try_get("", "account_type").unwrap(),
+                row.try_get("", "description").unwrap(),
+                row.try_get("", "quota").unwrap(),
+            )
+        })
+        .collect()
+}
+async fn check_queries(connection: DatabaseConnection, queries: SQLQuery) {
+    seed(&connection).await;
+    assert_eq!(
+        query_account(&connection, &queries.name, "alice").await,
+        vec![(
+            "alice".to_owned(),
+            "individual".to_owned(),
+            String::new(),
+            0
+        )]
+    );
+    let alice = rows(&connection, &queries.name, "alice").await;
+    assert!(alice[0].try_get::<String>("", "password").is_ok());
+    assert_eq!(
+        query_account(&connection, &queries.name, "support").await[0].1,
+        "group"
+    );
+    assert!(query_account(&connection, &queries.name, "bob")
+        .await
+        .is_empty());
+    assert!(query_account(&connection, &queries.name, "nobody")
+        .await
+        .is_empty());
+    assert_eq!(
+        query(&connection, &queries.recipients, "alice@example.com").await,
+        vec!["alice"]
+    );
+    assert_eq!(
+        query(&connection, &queries.recipients, "nobody@example.com").await,
+        vec!["carol"]
+    );
+    assert!(
+        query(&connection, &queries.recipients, "nobody@example.org")
+            .await
+            .is_empty()
+    );
+    assert_eq!(
+        query(&connection, &queries.recipients, "alice+news@example.com").await,
+        vec!["alice"]
+    );
+    // The address with the tag exists
+    assert_eq!(
+        query(&connection, &queries.recipients, "alice+bob@example.com").await,
+        vec!["bob"]
+    );
+    assert_eq!(
+        query(&connection, &queries.recipients, "nobody+news@example.com").await,
+        vec!["carol"]
+    );
+    assert_eq!(
+        query(&connection, &queries.recipients, "dave@example.net").await,
+        vec!["dave"]
+    );
+    assert!(
+        query(&connection, &queries.recipients, "dave+news@example.net")
+            .await
+            .is_empty()
+    );
+    assert_eq!(
+        query(&connection, &queries.emails, "alice").await,
+        vec!["alice@example.com", "support@example.com"]
+    );
+    assert!(query(&connection, &queries.emails, "carol")
+        .await
+        .is_empty());
+    assert_eq!(
+        query(&connection, &queries.members, "alice").await,
+        vec!["support"]
+    );
+    assert_eq!(
+        query(&connection, &queries.members, "carol").await,
+        vec!["support"]
+    );
+    // The panel group is not a Stalwart group
+    assert!(query(&connection, &queries.members, "bob").await.is_empty());
+    assert_eq!(
+        query(&connection, &queries.expand, "team@example.com").await,
+        vec!["alice@example.com", "guest@example.org"]
+    );
+    assert!(query(&connection, &queries.expand, "alice@example.com")
+        .await
+        .is_empty());
+    assert_eq!(
+        query(&connection, &queries.verify, "alice").await,
+        vec!["alice@example.com"]
+    );
+    assert_eq!(
+        query(&connection, &queries.verify, "example.com").await,
+        vec![
+            "alice@example.com",
+            "bob@example.com",
+            "support@example.com"
+        ]
+    );
+    assert_eq!(
+        rows(&connection, &queries.domains, "example.com")
+            .await
+            .len(),
+        1
+    );
+    assert!(rows(&connection, &queries.domains, "example.org")
+        .await
+        .is_empty());
+}
+
+#[tokio::test]
+#[ignore = "needs a scratch Postgres database in STALWART_PANEL_TEST_POSTGRES"]
+async fn postgres_queries() {
+    let connection = connect("STALWART_PANEL_TEST_POSTGRES").await;
+    check_queries(connection, SQLQuery::new_postgres()).await;
+}
+#[tokio::test]
+#[ignore = "needs a scratch MySQL database in STALWART_PANEL_TEST_MYSQL"]
+async fn mysql_queries() {
+    let connection = connect("STALWART_PANEL_TEST_MYSQL").await;
+    check_queries(connection, SQLQuery::new_mysql()).await;
+}
+#[tokio::test]
+async fn sqlite_queries() {
+    let connection = migrate("sqlite::memory:").await;
+    check_queries(connection, SQLQuery::new_sqlite()).await;
+}
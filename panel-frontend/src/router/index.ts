@@ -4,6 +4,7 @@ import LoginView from '@/views/LoginView.vue'
 import Accounts from '@/views/accounts/Accounts.vue'
 import ViewAccount from '@/views/accounts/ViewAccount.vue'
 import NewAccount from '@/views/accounts/NewAccount.vue'
+import ConfirmBackupEmail from '@/views/ConfirmBackupEmail.vue'
 export {}
 
 declare module 'vue-router' {
@@ -32,6 +33,14 @@ const router = createRouter({
         requiresAuth: false
       }
     },
+    {
+      path: '/confirm-backup-email',
+      name: 'confirm-backup-email',
+      component: ConfirmBackupEmail,
+      meta: {
+        requiresAuth: false
+      }
+    },
     {
       path: '/accounts',
       name: 'accounts',
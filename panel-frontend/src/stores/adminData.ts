@@ -3,9 +3,12 @@ import type { Group } from '@/types/groups'
 import { sessionStore } from '@/stores/session'
 import http from '@/http'
 import type { Domain } from '@/types/other'
-import type { AccountSimple } from '@/types/user'
+import type { AccountList, AccountSimple } from '@/types/user'
 import { ref, type Ref } from 'vue'
 
+// The largest page the server returns
+const ACCOUNTS_PER_PAGE = 1000
+
 export const adminStore = defineStore('adminData', () => {
   const groups: Ref<Group[]> = ref([])
   const domains: Ref<Domain[]> = ref([])
@@ -55,15 +58,22 @@ export const adminStore = defineStore('adminData', () => {
     if (accounts.value.length > 0 && !forceRefresh) {
       return
     }
-    await http
-      .get<AccountSimple[]>('api/accounts/list?active=false')
-      .then((response) => {
-        accounts.value = response.data
-        console.debug(`Loaded ${accounts.value.length} accounts`)
-      })
-      .catch((error) => {
-        console.error(`Error while loading accounts: ${error}`)
-      })
+    // The grid filters and sorts every account. So every page is loaded
+    const loaded: AccountSimple[] = []
+    try {
+      for (let page = 0, totalPages = 1; page < totalPages; page++) {
+        const response = await http.get<AccountList>(
+          `api/accounts/list?page=${page}&per_page=${ACCOUNTS_PER_PAGE}`
+        )
+        loaded.push(...response.data.accounts)
+        totalPages = response.data.total_pages
+      }
+    } catch (error) {
+      console.error(`Error while loading accounts: ${error}`)
+      return
+    }
+    accounts.value = loaded
+    console.debug(`Loaded ${accounts.value.length} accounts`)
   }
   return { groups, domains, accounts, getGroups, getAccounts, getDomains }
 })
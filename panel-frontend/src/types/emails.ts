@@ -1,7 +1,8 @@
 export enum EmailType {
   Primary = 'Primary',
   Alias = 'Alias',
-  Group = 'Group'
+  Group = 'Group',
+  CatchAll = 'CatchAll'
 }
 export interface Email {
   id: number
@@ -10,3 +11,10 @@ export interface Email {
   email_type: EmailType
   created: Date
 }
+export interface ListMember {
+  id: number
+  account?: number
+  username?: string
+  address?: string
+  created: Date
+}
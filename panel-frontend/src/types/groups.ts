@@ -6,5 +6,8 @@ export interface Group {
   id: number
   group_name: string
   group_permissions: GroupPermissions
+  default_quota?: number
+  max_quota?: number
+  alias_limit: number
   created: Date
 }
@@ -16,9 +16,11 @@ export interface FullUser {
   description: string
   requires_password_change: boolean
   quota: number
+  quota_used?: number
   account_type: AccountType
   active: boolean
   backup_email?: string
+  backup_email_verified?: Date
   created: Date
   group_id: number
   group_name: string
@@ -57,6 +59,49 @@ export interface AccountSimple {
   username: string
   description: string
   account_type: AccountType
+  group_id: number
+  quota: number
+  quota_used?: number
   primary_email?: string
   active: boolean
+  created: Date
+}
+export interface AccountList {
+  accounts: AccountSimple[]
+  total: number
+  total_pages: number
+  page: number
+  per_page: number
+}
+export type AccessLevel = 'read' | 'send_as' | 'full'
+export interface SharedMailboxMember {
+  id: number
+  member: number
+  username: string
+  name: string
+  access: AccessLevel
+  created: Date
+}
+export interface Forwarding {
+  id: number
+  account: number
+  destinations: string[]
+  keep_copy: boolean
+  created: Date
+}
+export interface Vacation {
+  id: number
+  account: number
+  enabled: boolean
+  start_date?: string
+  end_date?: string
+  subject: string
+  text_body: string
+  html_body?: string
+  reply_interval: number
+  created: Date
+}
+export interface QuotaUsage {
+  quota: number
+  quota_used?: number
 }
@@ -0,0 +1,38 @@
+use std::{io::Write, path::PathBuf};
+
+use entities::account::export::{ExportAccount, ExportOptions};
+use log::info;
+use sea_orm::ConnectOptions;
+use utils::config::Settings;
+
+use crate::Error;
+
+/// Exports the accounts in the database configured in the panel config
+///
+/// Writes to stdout if no output file is given.
+pub(crate) async fn export_accounts(
+    panel_config: PathBuf,
+    options: ExportOptions,
+    output: Option<PathBuf>,
+) -> Result<(), Error> {
+    let settings = Settings::load(&panel_config)?;
+
+    let database_connection =
+        sea_orm::Database::connect(ConnectOptions::new(settings.database.to_string())).await?;
+    let accounts = ExportAccount::get_all(&database_connection, &options).await?;
+    database_connection.close().await?;
+
+    let content = ExportAccount::write(&accounts, options.format, &options.base_dn)?;
+    match output {
+        Some(output) => {
+            std::fs::write(&output, content)?;
+            info!(
+                "Exported {} accounts to {}",
+                accounts.len(),
+                output.display()
+            );
+        }
+        None => std::io::stdout().write_all(&content)?,
+    }
+    Ok(())
+}
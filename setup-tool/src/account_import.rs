@@ -0,0 +1,65 @@
+use std::{path::PathBuf, process::ExitCode};
+
+use entities::account::import::{
+    import_accounts as import, ImportAccount, ImportFormat, ImportMode, ImportOptions,
+};
+use log::{error, info};
+use sea_orm::ConnectOptions;
+use utils::config::Settings;
+
+use crate::Error;
+
+/// Imports accounts from a file into the database configured in the panel config
+///
+/// Password reset emails can only be sent through the panels API.
+/// Accounts imported without a password hash will need an admin to send them one.
+pub(crate) async fn import_accounts(
+    panel_config: PathBuf,
+    file: PathBuf,
+    best_effort: bool,
+) -> Result<ExitCode, Error> {
+    let settings = Settings::load(&panel_config)?;
+
+    let format = file
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .and_then(|extension| extension.parse().ok())
+        .unwrap_or(ImportFormat::Json);
+    let rows = ImportAccount::parse(format, &std::fs::read(&file)?)?;
+    info!("Importing {} accounts from {}", rows.len(), file.display());
+
+    let database_connection =
+        sea_orm::Database::connect(ConnectOptions::new(settings.database.to_string())).await?;
+    let options = ImportOptions {
+        mode: if best_effort {
+            ImportMode::BestEffort
+        } else {
+            ImportMode::Transaction
+        },
+        default_group: settings.default_group,
+        password_type: settings.password_hash_for_new_passwords,
+        domain_allocations: settings
+            .quota
+            .domain_allocations
+            .iter()
+            .map(|(domain, allocation)| (domain.to_lowercase(), allocation.bytes()))
+            .collect(),
+    };
+    let report = import(&database_connection, rows, options).await?;
+    database_connection.close().await?;
+
+    for row in &report.errors {
+        error!(
+            "Row {} ({}): {}",
+            row.row,
+            row.username,
+            row.errors.join(", ")
+        );
+    }
+    info!("Imported {} accounts", report.imported.len());
+    if report.errors.is_empty() {
+        Ok(ExitCode::SUCCESS)
+    } else {
+        Ok(ExitCode::FAILURE)
+    }
+}
@@ -1,17 +1,33 @@
 use inquire::{Select, Text};
-use utils::config::Database;
+use log::error;
+use utils::config::{Database, SqliteSettings};
 
 use crate::DatabaseType;
 
 pub fn get_database_config() -> Option<Database> {
-    let database_type = Select::new("Database Type", vec!["Mysql", "Postgres"])
+    let database_type = Select::new("Database Type", vec!["Mysql", "Postgres", "Sqlite"])
         .prompt()
         .map(|x| match x {
             "Mysql" => DatabaseType::Mysql,
             "Postgres" => DatabaseType::Postgres,
+            "Sqlite" => DatabaseType::Sqlite,
             _ => unreachable!(),
         })
         .ok()?;
+    if let DatabaseType::Sqlite = database_type {
+        let path = Text::new("Database File")
+            .with_help_message("The SQLite database file. Created if it does not exist")
+            .with_default("stalwart-panel.db")
+            .prompt()
+            .ok()?;
+        return match SqliteSettings::absolute(path) {
+            Ok(sqlite) => Some(Database::Sqlite(sqlite)),
+            Err(error) => {
+                error!("Unable to resolve the database file. Error {}", error);
+                None
+            }
+        };
+    }
 
     let user = Text::new("Database User")
         .with_help_message("The user to connect to the database with")
@@ -47,5 +63,6 @@ pub fn get_database_config() -> Option<Database> {
             host,
             database,
         })),
+        DatabaseType::Sqlite => unreachable!("SQLite is handled above"),
     }
 }
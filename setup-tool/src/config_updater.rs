@@ -1,9 +1,10 @@
 use std::path::PathBuf;
 
+use log::warn;
 use toml_edit::{Document, Item};
 use utils::{
     config::Database,
-    stalwart_config::sql::{SQLColumns, SQLQuery},
+    stalwart_config::sql::{SQLColumns, SQLQuery, SQLSettings},
 };
 
 #[test]
@@ -32,23 +33,47 @@ pub fn test_config_update() {
 
     let database = Database::test();
 
-    update_config(&database, document, &copy);
+    update_config(&database, None, document, &copy);
+
+    let updated: Document = std::fs::read_to_string(&copy)
+        .expect("Failed to read updated config file")
+        .parse()
+        .expect("Failed to parse updated config file");
+    assert_eq!(
+        updated["directory"]["sql"]["options"]["subaddressing"].as_bool(),
+        Some(false)
+    );
 }
 
+/// Points Stalwart at the panel database.
+///
+/// `database_password` is written in place of the password. Such as a reference to a secret Stalwart can read.
+/// Without it the password is written in plain text
 pub(crate) fn update_config(
     database_config: &Database,
+    database_password: Option<&str>,
     mut stalwart_config: Document,
     file: &PathBuf,
 ) {
-    stalwart_config["directory"]["sql"]["address"] =
-        Item::Value(database_config.to_string().into());
+    if database_password.is_none() && database_config.has_password() {
+        warn!(
+            "The database password is written in plain text to {}. Use `--stalwart-database-password` to write a reference to a secret instead",
+            file.display()
+        );
+    }
+    let sql_settings = SQLSettings::new(database_config, database_password);
+    stalwart_config["directory"]["sql"]["address"] = Item::Value(sql_settings.address.into());
 
     let queries = match &database_config {
         Database::Mysql(_) => SQLQuery::new_mysql(),
         Database::Postgres(_) => SQLQuery::new_postgres(),
+        Database::Sqlite(_) => SQLQuery::new_sqlite(),
     };
     stalwart_config["directory"]["sql"]["query"] = queries.into();
     stalwart_config["directory"]["sql"]["columns"] = SQLColumns::default().into();
+    // The recipients query removes the `+tag` on the domains that enable sub-addressing.
+    // Stalwart would remove it on every domain
+    stalwart_config["directory"]["sql"]["options"]["subaddressing"] = Item::Value(false.into());
 
     // Backup the old config file
     let backup_file = file.with_extension("bak.toml");
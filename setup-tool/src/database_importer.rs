@@ -208,6 +208,7 @@ pub(crate) async fn import_database(
                     account_type: ActiveValue::Set(account_type),
                     active: ActiveValue::Set(active),
                     backup_email: Default::default(),
+                    backup_email_verified: Default::default(),
                     created: Default::default(),
                 },
             )
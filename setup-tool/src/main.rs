@@ -1,8 +1,12 @@
-use std::{borrow::Cow, fs::read_to_string, mem, path::PathBuf, process::ExitCode};
+use std::{borrow::Cow, fs::read_to_string, path::PathBuf, process::ExitCode};
 
-use clap::{Parser, Subcommand, ValueEnum};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use entities::{
-    account::AccountType,
+    account::{
+        export::{ExportError, ExportFormat, ExportOptions},
+        import::ImportError,
+        AccountType,
+    },
     groups::{ActiveModel, GroupPermissions},
     now, AccountEntity, ActiveAccountModel, GroupEntity,
 };
@@ -16,8 +20,9 @@ use toml_edit::{Document, TomlError};
 use utils::{
     config::{
         Database, EmailEncryption, EmailSetting, MysqlSettings, PostgresSettings, Settings,
-        StalwartManagerConfig,
+        SqliteSettings, StalwartManagerConfig,
     },
+    config_loader::ConfigErrors,
     database::{
         password::{PasswordErrors, PasswordType},
         Password,
@@ -25,7 +30,7 @@ use utils::{
     stalwart_manager::ManagerConfig,
 };
 
-use crate::config_updater::update_config;
+use crate::{config_updater::update_config, migrate::MigrateAction};
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("Failed to hash password {0}")]
@@ -40,21 +45,31 @@ pub enum Error {
     TomlSerializeError(#[from] toml::ser::Error),
     #[error("Failed to Deserialize Config {0}. Error {1}")]
     TomlDeserializeError(PathBuf, TomlError),
+    #[error("Failed to parse the panel config {0}")]
+    PanelConfigError(#[from] ConfigErrors),
+    #[error(transparent)]
+    ImportError(#[from] ImportError),
+    #[error(transparent)]
+    ExportError(#[from] ExportError),
 }
+mod account_export;
+mod account_import;
 mod ask_questions;
 mod config_updater;
 mod database_importer;
+mod migrate;
 
 #[derive(ValueEnum, Clone)]
 pub enum DatabaseType {
     Mysql,
     Postgres,
+    Sqlite,
 }
 #[derive(Parser)]
 struct Command {
-    // The Stalwart Config File
+    // The Stalwart Config File. Required unless running `import-accounts` or `export-accounts`
     #[clap(long)]
-    stalwart_config: PathBuf,
+    stalwart_config: Option<PathBuf>,
     #[clap(subcommand)]
     subcommand: Option<Commands>,
 
@@ -66,58 +81,109 @@ struct Command {
     // All uses imported will require a new password
     #[clap(long, default_value = "false")]
     require_password_changes_on_all_users: bool,
-    // Should the database Migration be a fresh install
-    #[clap(long, default_value = "true")]
+    // Drop everything in the database before migrating it. All existing data is deleted
+    #[clap(long, default_value = "false")]
     fresh_database: bool,
+    // Written into the Stalwart config instead of the database password. Such as a reference to a secret.
+    // The password is written in plain text without it
+    #[clap(long)]
+    stalwart_database_password: Option<String>,
+}
+/// The database the panel creates when running `auto`
+#[derive(Args)]
+struct DatabaseArgs {
+    // The Database Type for the new database
+    #[clap(long)]
+    database_type: DatabaseType,
+    // The Database Host for the new database. Not used by SQLite
+    #[clap(long, default_value = "")]
+    database_host: String,
+    // The Database User for the new database. Not used by SQLite
+    #[clap(long, default_value = "")]
+    database_user: String,
+    // The Database Password for the new database. Not used by SQLite
+    #[clap(long, default_value = "")]
+    database_password: String,
+    // The Database Name for the new database. The file path for SQLite, made absolute so Stalwart finds the same file
+    #[clap(long)]
+    database_name: String,
+}
+impl TryFrom<DatabaseArgs> for Database {
+    type Error = std::io::Error;
+    fn try_from(args: DatabaseArgs) -> Result<Self, Self::Error> {
+        let DatabaseArgs {
+            database_type,
+            database_host,
+            database_user,
+            database_password,
+            database_name,
+        } = args;
+        let database = match database_type {
+            DatabaseType::Mysql => Database::Mysql(MysqlSettings {
+                user: database_user,
+                password: database_password,
+                host: database_host,
+                database: database_name,
+            }),
+            DatabaseType::Postgres => Database::Postgres(PostgresSettings {
+                user: database_user,
+                password: database_password,
+                host: database_host,
+                database: database_name,
+            }),
+            DatabaseType::Sqlite => Database::Sqlite(SqliteSettings::absolute(database_name)?),
+        };
+        Ok(database)
+    }
 }
 #[derive(Subcommand)]
 enum Commands {
-    Auto {
-        // The Database Type for the new database
-        #[clap(long)]
-        database_type: DatabaseType,
-        // The Database Host for the new database
-        #[clap(long)]
-        database_host: String,
-        // The Database User for the new database
+    Auto(DatabaseArgs),
+    /// Creates accounts in an existing panel database from a CSV or JSON file
+    ImportAccounts {
+        // The stalwart-panel config file
+        #[clap(long, default_value = "stalwart-panel.toml")]
+        panel_config: PathBuf,
+        // The file to import. `.csv` files are read as CSV everything else as JSON
+        file: PathBuf,
+        // Import the valid rows even if other rows fail
+        #[clap(long, default_value = "false")]
+        best_effort: bool,
+    },
+    /// Exports the accounts, their emails and groups from an existing panel database
+    ExportAccounts {
+        // The stalwart-panel config file
+        #[clap(long, default_value = "stalwart-panel.toml")]
+        panel_config: PathBuf,
+        // json, csv or ldif
+        #[clap(long, default_value = "json")]
+        format: ExportFormat,
+        // Only export accounts in this group
         #[clap(long)]
-        database_user: String,
-        // The Database Password for the new database
+        group: Option<i64>,
+        // Only export accounts with an email address on this domain
         #[clap(long)]
-        database_password: String,
-        // The Database Name for the new database
+        domain: Option<String>,
+        // Include the password hashes
+        #[clap(long, default_value = "false")]
+        include_password_hashes: bool,
+        // The base DN used for LDIF exports
+        #[clap(long, default_value = "dc=example,dc=com")]
+        base_dn: String,
+        // The file to write to. Defaults to stdout
         #[clap(long)]
-        database_name: String,
+        output: Option<PathBuf>,
+    },
+    /// Shows, applies or rolls back the migrations of an existing panel database
+    Migrate {
+        // The stalwart-panel config file
+        #[clap(long, default_value = "stalwart-panel.toml")]
+        panel_config: PathBuf,
+        #[clap(subcommand)]
+        action: MigrateAction,
     },
 }
 
-impl Commands {
-    pub fn get_database_config(&mut self) -> Database {
-        match self {
-            Commands::Auto {
-                database_type,
-                database_host,
-                database_user,
-                database_password,
-                database_name,
-                ..
-            } => match database_type {
-                DatabaseType::Mysql => Database::Mysql(MysqlSettings {
-                    user: mem::take(database_user),
-                    password: mem::take(database_password),
-                    host: mem::take(database_host),
-                    database: mem::take(database_name),
-                }),
-                DatabaseType::Postgres => Database::Postgres(PostgresSettings {
-                    user: mem::take(database_user),
-                    password: mem::take(database_password),
-                    host: mem::take(database_host),
-                    database: mem::take(database_name),
-                }),
-            },
-        }
-    }
-}
 #[tokio::main]
 async fn main() -> Result<ExitCode, Error> {
     human_panic::setup_panic!();
@@ -129,10 +195,49 @@ async fn main() -> Result<ExitCode, Error> {
         no_questions_asked,
         require_password_changes_on_all_users,
         fresh_database,
+        stalwart_database_password,
     } = Command::parse();
+    match subcommand {
+        Some(Commands::ImportAccounts {
+            panel_config,
+            file,
+            best_effort,
+        }) => return account_import::import_accounts(panel_config, file, best_effort).await,
+        Some(Commands::ExportAccounts {
+            panel_config,
+            format,
+            group,
+            domain,
+            include_password_hashes,
+            base_dn,
+            output,
+        }) => {
+            let options = ExportOptions {
+                format,
+                group,
+                domain,
+                include_password_hashes,
+                base_dn,
+            };
+            account_export::export_accounts(panel_config, options, output).await?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(Commands::Migrate {
+            panel_config,
+            action,
+        }) => {
+            migrate::migrate(panel_config, action).await?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        _ => {}
+    }
+    let Some(stalwart_config_path) = stalwart_config_path else {
+        error!("`--stalwart-config` is required");
+        return Ok(ExitCode::FAILURE);
+    };
     let database_config = match subcommand {
-        None => ask_questions::get_database_config(),
-        Some(mut sub_command) => Some(sub_command.get_database_config()),
+        Some(Commands::Auto(database)) => Some(Database::try_from(database)?),
+        _ => ask_questions::get_database_config(),
     };
     let Some(database_config) = database_config else {
         error!("No Database Config Provided");
@@ -207,11 +312,24 @@ async fn main() -> Result<ExitCode, Error> {
             .as_str()
             .map(|s| s.to_string())
             .unwrap_or("localhost".to_string()),
+        port: None,
         encryption: EmailEncryption::StartTLS,
+        auth_mechanism: None,
+        ca_bundle: None,
+        transport: Default::default(),
         from: format!("Stalwart Panel<panel@{}>", main_domain),
         reply_to: None,
+        locale: None,
+        notifications: Default::default(),
+        templates_directory: None,
+        queue: Default::default(),
     };
-    update_config(&database_config, stalwart_config, &stalwart_config_path);
+    update_config(
+        &database_config,
+        stalwart_database_password.as_deref(),
+        stalwart_config,
+        &stalwart_config_path,
+    );
 
     info!("Stalwart has been configured. Creating the panel config");
 
@@ -265,6 +383,9 @@ async fn setup_database(
             id: ActiveValue::Set(1),
             group_name: ActiveValue::Set("user".to_string()),
             permissions: ActiveValue::Set(GroupPermissions::default()),
+            default_quota: ActiveValue::NotSet,
+            max_quota: ActiveValue::NotSet,
+            alias_limit: ActiveValue::NotSet,
             created: ActiveValue::Set(Default::default()),
         };
         debug!("Inserting User Group {:?}", group);
@@ -275,6 +396,9 @@ async fn setup_database(
             id: ActiveValue::Set(2),
             group_name: ActiveValue::Set(super_user_name.to_string()),
             permissions: ActiveValue::Set(GroupPermissions::new_admin()),
+            default_quota: ActiveValue::NotSet,
+            max_quota: ActiveValue::NotSet,
+            alias_limit: ActiveValue::NotSet,
             created: ActiveValue::Set(Default::default()),
         };
         debug!("Inserting User Group {:?}", group);
@@ -313,6 +437,7 @@ async fn create_default_account(
         account_type: ActiveValue::Set(AccountType::Individual),
         active: ActiveValue::Set(true),
         backup_email: Default::default(),
+        backup_email_verified: Default::default(),
         created: now(),
     };
     AccountEntity::insert(postmaster)
@@ -0,0 +1,55 @@
+use std::path::PathBuf;
+
+use clap::Subcommand;
+use log::info;
+use migration::{Migrator, MigratorTrait};
+use sea_orm::ConnectOptions;
+use utils::config::Settings;
+
+use crate::Error;
+
+#[derive(Subcommand, Clone)]
+pub enum MigrateAction {
+    /// Lists every migration and if it has been applied
+    Status,
+    /// Applies the pending migrations
+    Up {
+        // Only apply this many migrations. Defaults to all of them
+        #[clap(long)]
+        steps: Option<u32>,
+    },
+    /// Rolls back the last applied migrations
+    Down {
+        // How many migrations to roll back
+        #[clap(long, default_value = "1")]
+        steps: u32,
+    },
+}
+/// Runs the migrations of the database configured in the panel config
+pub(crate) async fn migrate(panel_config: PathBuf, action: MigrateAction) -> Result<(), Error> {
+    let settings = Settings::load(&panel_config)?;
+
+    let database_connection =
+        sea_orm::Database::connect(ConnectOptions::new(settings.database.to_string())).await?;
+    match action {
+        MigrateAction::Status => {
+            for migration in Migrator::get_migration_with_status(&database_connection).await? {
+                println!("{}\t{}", migration.status(), migration.name());
+            }
+        }
+        MigrateAction::Up { steps } => {
+            let pending = Migrator::get_pending_migrations(&database_connection)
+                .await?
+                .len();
+            Migrator::up(&database_connection, steps).await?;
+            let applied = steps.map_or(pending, |steps| pending.min(steps as usize));
+            info!("Applied {} migrations", applied);
+        }
+        MigrateAction::Down { steps } => {
+            Migrator::down(&database_connection, Some(steps)).await?;
+            info!("Rolled back up to {} migrations", steps);
+        }
+    }
+    database_connection.close().await?;
+    Ok(())
+}
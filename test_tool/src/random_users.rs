@@ -77,6 +77,7 @@ impl RandomUsersCommand {
                 description,
                 active: ActiveValue::Set(true),
                 backup_email: ActiveValue::Set(Some(user.email)),
+                backup_email_verified: ActiveValue::Set(None),
                 group_id: ActiveValue::Set(default_group),
                 account_type: ActiveValue::Set(AccountType::Individual),
                 created: now(),
@@ -1,26 +1,50 @@
 use std::{
+    collections::HashMap,
     fmt::{Display, Formatter},
+    net::IpAddr,
     path::PathBuf,
 };
 
 use chrono::Duration;
 use serde::{Deserialize, Serialize};
 
-use crate::database::password::PasswordType;
+use crate::{database::password::PasswordType, quota_size::QuotaSize};
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 #[serde(tag = "type", content = "settings")]
 pub enum Database {
     Mysql(MysqlSettings),
     Postgres(PostgresSettings),
+    Sqlite(SqliteSettings),
 }
 impl Database {
     pub fn debug_message(&self) -> String {
         match self {
             Database::Mysql(mysql) => mysql.debug_message(),
             Database::Postgres(postgres) => postgres.debug_message(),
+            Database::Sqlite(sqlite) => sqlite.to_string(),
         }
     }
+    /// The connection address with `password` in place of the real password.
+    ///
+    /// Used for configs that should not hold the password itself. Such as a reference to a secret
+    pub fn address_with_password(&self, password: &str) -> String {
+        match self {
+            Database::Mysql(mysql) => format!(
+                "mysql://{}:{}@{}/{}",
+                mysql.user, password, mysql.host, mysql.database
+            ),
+            Database::Postgres(postgres) => format!(
+                "postgres://{}:{}@{}/{}",
+                postgres.user, password, postgres.host, postgres.database
+            ),
+            Database::Sqlite(sqlite) => sqlite.to_string(),
+        }
+    }
+    /// If the address contains a password. SQLite has none
+    pub fn has_password(&self) -> bool {
+        !matches!(self, Database::Sqlite(_))
+    }
     pub fn test() -> Self {
         Database::Postgres(PostgresSettings {
             user: "postgres".to_string(),
@@ -35,6 +59,7 @@ impl Display for Database {
         match self {
             Database::Mysql(mysql) => write!(f, "{}", mysql),
             Database::Postgres(postgres) => write!(f, "{}", postgres),
+            Database::Sqlite(sqlite) => write!(f, "{}", sqlite),
         }
     }
 }
@@ -85,24 +110,151 @@ impl Display for PostgresSettings {
         )
     }
 }
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct SqliteSettings {
+    /// The database file. Created if it does not exist
+    pub path: PathBuf,
+}
+impl SqliteSettings {
+    /// Resolves a relative path against the current directory.
+    /// Stalwart opens the same file from its own working directory so the path has to be absolute
+    pub fn absolute(path: impl Into<PathBuf>) -> std::io::Result<Self> {
+        let path = path.into();
+        let path = if path.is_absolute() {
+            path
+        } else {
+            std::env::current_dir()?.join(path)
+        };
+        Ok(Self { path })
+    }
+}
+impl Display for SqliteSettings {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "sqlite://{}?mode=rwc", self.path.display())
+    }
+}
 #[derive(Debug, Deserialize, Serialize, Clone, Default)]
 pub enum EmailEncryption {
+    /// Plaintext. Only for trusted local relays
     #[default]
     NONE,
+    /// Upgrades the connection with STARTTLS. Port 587 by default
     StartTLS,
+    /// Implicit TLS (SMTPS). Port 465 by default
     TLS,
 }
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum EmailAuthMechanism {
+    Plain,
+    Login,
+    /// The password is used as the OAuth2 access token
+    Xoauth2,
+}
+/// How emails leave the panel
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[serde(tag = "type", content = "settings")]
+pub enum EmailTransport {
+    /// Uses the SMTP server in the email settings
+    #[default]
+    Smtp,
+    /// Pipes emails to a sendmail compatible command. Defaults to `sendmail`
+    Sendmail { command: Option<String> },
+    /// Writes every email to the directory as a `.eml` file. For testing
+    File { directory: PathBuf },
+    /// Delivers every email into a maildir. So a local mail client can read them
+    Maildir { directory: PathBuf },
+    /// Keeps every email in memory. Only for the panel's own tests that check the emails it sends.
+    ///
+    /// Rejected when the config is loaded. Nothing would ever read the emails
+    Capture,
+}
 /// Yes the email software management software needs email settings
 ///
 /// This is for sending reset password emails and any other emails.
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct EmailSetting {
+    /// Leave empty to send without logging in. Such as to a local relay
+    #[serde(default)]
     pub username: String,
+    #[serde(default)]
     pub password: String,
     pub host: String,
+    /// Defaults to the port of the encryption
+    #[serde(default)]
+    pub port: Option<u16>,
     pub encryption: EmailEncryption,
+    /// Picked by the server if not set
+    #[serde(default)]
+    pub auth_mechanism: Option<EmailAuthMechanism>,
+    /// A PEM file of certificates to trust along with the default ones
+    #[serde(default)]
+    pub ca_bundle: Option<PathBuf>,
+    #[serde(default)]
+    pub transport: EmailTransport,
     pub from: String,
     pub reply_to: Option<String>,
+    /// Templates named `{template}.{locale}.html` are used over the default English templates when they exist.
+    ///
+    /// Such as `de`
+    #[serde(default)]
+    pub locale: Option<String>,
+    #[serde(default)]
+    pub notifications: EmailNotifications,
+    /// `.html` and `.txt` files in this directory replace the built in templates with the same name.
+    /// Such as `password_reset.html`
+    ///
+    /// Changes to the files are picked up without a restart. New files are not
+    #[serde(default)]
+    pub templates_directory: Option<PathBuf>,
+    #[serde(default)]
+    pub queue: EmailQueueSettings,
+}
+/// Outgoing emails are saved here until they are sent
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct EmailQueueSettings {
+    pub database_location: PathBuf,
+    /// Sends before an email is marked as failed
+    pub max_attempts: u32,
+    /// The wait before the first retry. It doubles after every attempt
+    #[serde(with = "crate::duration_serde::as_seconds")]
+    pub retry_delay: Duration,
+    #[serde(with = "crate::duration_serde::as_seconds")]
+    pub max_retry_delay: Duration,
+}
+impl Default for EmailQueueSettings {
+    fn default() -> Self {
+        Self {
+            database_location: PathBuf::from("email_queue.redb"),
+            max_attempts: 5,
+            retry_delay: Duration::minutes(1),
+            max_retry_delay: Duration::hours(1),
+        }
+    }
+}
+/// The emails sent to an account when something happens to it.
+/// Emails that are needed for the panel to work, such as password resets, can not be turned off
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct EmailNotifications {
+    /// Sent to a new account with how to log in
+    pub welcome: bool,
+    pub password_changed: bool,
+    pub account_deactivated: bool,
+    pub alias_added: bool,
+    /// Sent when an account logs in from an IP it has not logged in from before
+    pub new_login: bool,
+}
+impl Default for EmailNotifications {
+    fn default() -> Self {
+        Self {
+            welcome: true,
+            password_changed: true,
+            account_deactivated: true,
+            alias_added: true,
+            new_login: true,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -140,6 +292,77 @@ impl Default for PasswordReset {
         }
     }
 }
+/// How the panel reaches Stalwart's management API
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(tag = "type", content = "settings")]
+pub enum StalwartApi {
+    Http(StalwartHttpApi),
+    /// Reports the used bytes from this map of usernames.
+    /// For running the panel without a Stalwart server
+    Mock(HashMap<String, i64>),
+}
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct StalwartHttpApi {
+    /// The base url of Stalwart. Such as `https://mail.example.com`
+    pub url: String,
+    /// An account with access to the management API
+    pub username: String,
+    pub password: String,
+}
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct QuotaSettings {
+    /// Quota usage is not reported if this is not set
+    pub stalwart_api: Option<StalwartApi>,
+    /// How long the usage of an account is cached
+    #[serde(with = "crate::duration_serde::as_seconds")]
+    pub cache_lifespan: Duration,
+    /// Percentages of the quota that will send an email to the backup email of the account.
+    /// Leave empty to disable alerts
+    pub alert_thresholds: Vec<u8>,
+    #[serde(with = "crate::duration_serde::as_seconds")]
+    pub alert_check_interval: Duration,
+    /// The most quota that can be given out in total to accounts with a primary email on the domain.
+    ///
+    /// Such as `"example.com" = "50GiB"`
+    pub domain_allocations: HashMap<String, QuotaSize>,
+}
+impl Default for QuotaSettings {
+    fn default() -> Self {
+        Self {
+            stalwart_api: None,
+            cache_lifespan: Duration::minutes(5),
+            alert_thresholds: vec![80, 95],
+            alert_check_interval: Duration::hours(1),
+            domain_allocations: HashMap::new(),
+        }
+    }
+}
+/// How the panel pushes Sieve scripts to the accounts in Stalwart
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(tag = "type", content = "settings")]
+pub enum SieveApi {
+    Jmap(JmapSieveApi),
+    /// Keeps the scripts in memory.
+    /// For running the panel without a Stalwart server
+    Mock,
+}
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct JmapSieveApi {
+    /// The base url of Stalwart. Such as `https://mail.example.com`
+    pub url: String,
+    /// The master user configured in Stalwart.
+    ///
+    /// The panel logs in as `{account}%{master_user}` to change the scripts of an account
+    pub master_user: String,
+    pub master_password: String,
+}
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[serde(default)]
+pub struct SieveSettings {
+    /// Forwarding rules and vacation responders are saved but not applied if this is not set
+    pub api: Option<SieveApi>,
+}
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Settings {
     pub bind_address: String,
@@ -157,9 +380,17 @@ pub struct Settings {
     pub require_password_reset: PasswordReset,
     #[serde(default)]
     pub session_manager: SessionManager,
+    #[serde(default)]
+    pub quota: QuotaSettings,
+    #[serde(default)]
+    pub sieve: SieveSettings,
     /// This is ignored if the tls config is set
     #[serde(default)]
     pub is_https: bool,
+    /// Reverse proxies allowed to pass the client IP in the `Forwarded` or `X-Forwarded-For` header.
+    /// The IP of the connection is used for everyone else
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpAddr>,
 }
 fn default_workers() -> usize {
     2
@@ -178,7 +409,10 @@ impl Settings {
             password_hash_for_new_passwords: Default::default(),
             require_password_reset: Default::default(),
             session_manager: Default::default(),
+            quota: Default::default(),
+            sieve: Default::default(),
             is_https: false,
+            trusted_proxies: Vec::new(),
         }
     }
 }
@@ -0,0 +1,701 @@
+//! Builds [Settings] from layered sources. Later sources win
+//!
+//! 1. The TOML file
+//! 2. `STALWART_PANEL_*` environment variables. `__` separates the tables.
+//! Such as `STALWART_PANEL_DATABASE__SETTINGS__HOST`. Values are parsed as TOML
+//! unless they replace a string or set a password
+//! 3. `STALWART_PANEL_*_FILE` environment variables. The setting is read from the file.
+//! Such as `STALWART_PANEL_DATABASE__SETTINGS__PASSWORD_FILE=/run/secrets/database_password`
+//!
+//! The whole config is then validated and every problem is reported together
+//!
+//! [add_missing_defaults] fills in the settings missing from the file without touching its comments
+use std::{
+    fmt::{Display, Formatter},
+    path::{Path, PathBuf},
+};
+
+use thiserror::Error;
+use toml::{Table, Value};
+use toml_edit::{Document, Item, TableLike};
+
+use crate::{
+    config::{Database, EmailTransport, Settings},
+    database::EmailAddress,
+};
+
+pub const ENV_PREFIX: &str = "STALWART_PANEL_";
+const SECRET_SUFFIX: &str = "_FILE";
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Unable to read {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("Unable to parse {0}: {1}")]
+    Parse(PathBuf, toml::de::Error),
+    #[error("{0} does not point to a setting")]
+    InvalidEnvironmentVariable(String),
+    #[error("Unable to read the secret in {variable} from {path}: {error}")]
+    Secret {
+        variable: String,
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    #[error("{0}")]
+    Deserialize(toml::de::Error),
+    #[error("`{key}` {message}")]
+    Invalid { key: &'static str, message: String },
+}
+impl ConfigError {
+    fn invalid(key: &'static str, message: impl Into<String>) -> Self {
+        ConfigError::Invalid {
+            key,
+            message: message.into(),
+        }
+    }
+}
+/// Every problem found while loading the config
+#[derive(Debug)]
+pub struct ConfigErrors(pub Vec<ConfigError>);
+impl std::error::Error for ConfigErrors {}
+impl Display for ConfigErrors {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Found {} problem(s) in the config", self.0.len())?;
+        for error in &self.0 {
+            write!(f, "\n  - {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl Settings {
+    /// Loads the config from the file and the environment. See [crate::config_loader]
+    pub fn load(path: impl AsRef<Path>) -> Result<Settings, ConfigErrors> {
+        load(path.as_ref(), std::env::vars())
+    }
+    /// Only the TOML file. Nothing from the environment
+    pub fn load_file(path: impl AsRef<Path>) -> Result<Settings, ConfigErrors> {
+        load(path.as_ref(), std::iter::empty())
+    }
+    /// Every problem with the settings. Empty if they are valid
+    pub fn validate(&self) -> Vec<ConfigError> {
+        let mut errors = Vec::new();
+        if !self
+            .bind_address
+            .rsplit_once(':')
+            .map_or(false, |(host, port)| {
+                !host.is_empty() && port.parse::<u16>().is_ok()
+            })
+        {
+            errors.push(ConfigError::invalid(
+                "bind_address",
+                "must be a host and a port. Such as `0.0.0.0:5312`",
+            ));
+        }
+        if self.number_of_workers == 0 {
+            errors.push(ConfigError::invalid(
+                "number_of_workers",
+                "must be at least 1",
+            ));
+        }
+        let server = match &self.database {
+            Database::Mysql(mysql) => Some((&mysql.user, &mysql.host, &mysql.database)),
+            Database::Postgres(postgres) => {
+                Some((&postgres.user, &postgres.host, &postgres.database))
+            }
+            Database::Sqlite(sqlite) => {
+                if sqlite.path.as_os_str().is_empty() {
+                    errors.push(ConfigError::invalid("database.settings.path", "is empty"));
+                } else if !sqlite.path.is_absolute() {
+                    errors.push(ConfigError::invalid(
+                        "database.settings.path",
+                        "must be an absolute path. Stalwart opens the same file from its own working directory",
+                    ));
+                }
+                None
+            }
+        };
+        if let Some((user, host, database)) = server {
+            if user.is_empty() {
+                errors.push(ConfigError::invalid("database.settings.user", "is empty"));
+            }
+            if host.is_empty() {
+                errors.push(ConfigError::invalid("database.settings.host", "is empty"));
+            }
+            if database.is_empty() {
+                errors.push(ConfigError::invalid(
+                    "database.settings.database",
+                    "is empty",
+                ));
+            }
+        }
+        if let Some(tls) = &self.tls {
+            check_file(&mut errors, "tls.private_key", &tls.private_key);
+            check_file(&mut errors, "tls.certificate_chain", &tls.certificate_chain);
+        }
+        if EmailAddress::new(self.postmaster_address.as_str()).is_err() {
+            errors.push(ConfigError::invalid(
+                "postmaster_address",
+                "is not an email address",
+            ));
+        }
+
+        let email = &self.email;
+        if !is_mailbox(&email.from) {
+            errors.push(ConfigError::invalid(
+                "email.from",
+                "must be an email address. Such as `Stalwart Panel<panel@example.com>`",
+            ));
+        }
+        if email
+            .reply_to
+            .as_deref()
+            .map_or(false, |reply_to| !is_mailbox(reply_to))
+        {
+            errors.push(ConfigError::invalid(
+                "email.reply_to",
+                "is not an email address",
+            ));
+        }
+        if matches!(email.transport, EmailTransport::Smtp) && email.host.is_empty() {
+            errors.push(ConfigError::invalid(
+                "email.host",
+                "is required for the SMTP transport",
+            ));
+        }
+        if matches!(email.transport, EmailTransport::Capture) {
+            errors.push(ConfigError::invalid(
+                "email.transport",
+                "`Capture` is only for the panel's tests. Use `File` or `Maildir` to keep the emails",
+            ));
+        }
+        if let Some(ca_bundle) = &email.ca_bundle {
+            check_file(&mut errors, "email.ca_bundle", ca_bundle);
+        }
+        if let Some(templates_directory) = &email.templates_directory {
+            if !templates_directory.is_dir() {
+                errors.push(ConfigError::invalid(
+                    "email.templates_directory",
+                    format!("{} is not a directory", templates_directory.display()),
+                ));
+            }
+        }
+        if email.queue.max_attempts == 0 {
+            errors.push(ConfigError::invalid(
+                "email.queue.max_attempts",
+                "must be at least 1",
+            ));
+        }
+        if email.queue.retry_delay > email.queue.max_retry_delay {
+            errors.push(ConfigError::invalid(
+                "email.queue.retry_delay",
+                "is longer than `email.queue.max_retry_delay`",
+            ));
+        }
+
+        if self.session_manager.lifespan <= chrono::Duration::zero() {
+            errors.push(ConfigError::invalid(
+                "session_manager.lifespan",
+                "must be longer than 0 seconds",
+            ));
+        }
+        if let Some(threshold) = self
+            .quota
+            .alert_thresholds
+            .iter()
+            .find(|threshold| !(1..=100).contains(*threshold))
+        {
+            errors.push(ConfigError::invalid(
+                "quota.alert_thresholds",
+                format!("{} is not a percentage between 1 and 100", threshold),
+            ));
+        }
+        errors
+    }
+}
+fn check_file(errors: &mut Vec<ConfigError>, key: &'static str, path: &Path) {
+    if !path.is_file() {
+        errors.push(ConfigError::invalid(
+            key,
+            format!("{} does not exist", path.display()),
+        ));
+    }
+}
+/// An address with an optional name. Such as `Name<user@example.com>`
+fn is_mailbox(mailbox: &str) -> bool {
+    let address = match (mailbox.find('<'), mailbox.strip_suffix('>')) {
+        (Some(start), Some(mailbox)) => &mailbox[start + 1..],
+        _ => mailbox.trim(),
+    };
+    EmailAddress::new(address).is_ok()
+}
+
+fn load(
+    path: &Path,
+    variables: impl IntoIterator<Item = (String, String)>,
+) -> Result<Settings, ConfigErrors> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|error| ConfigErrors(vec![ConfigError::Io(path.to_path_buf(), error)]))?;
+    let mut table: Table = toml::from_str(&content)
+        .map_err(|error| ConfigErrors(vec![ConfigError::Parse(path.to_path_buf(), error)]))?;
+    let mut errors = apply_environment(&mut table, variables);
+
+    match Value::Table(table).try_into::<Settings>() {
+        Ok(settings) => {
+            errors.extend(settings.validate());
+            if errors.is_empty() {
+                Ok(settings)
+            } else {
+                Err(ConfigErrors(errors))
+            }
+        }
+        Err(error) => {
+            errors.push(ConfigError::Deserialize(error));
+            Err(ConfigErrors(errors))
+        }
+    }
+}
+/// Applies the overrides and then the secrets so a secret wins over an override of the same setting
+fn apply_environment(
+    table: &mut Table,
+    variables: impl IntoIterator<Item = (String, String)>,
+) -> Vec<ConfigError> {
+    let mut errors = Vec::new();
+    let (secrets, overrides): (Vec<_>, Vec<_>) = variables
+        .into_iter()
+        .filter(|(variable, _)| variable.starts_with(ENV_PREFIX))
+        .partition(|(variable, _)| variable.ends_with(SECRET_SUFFIX));
+    for (variable, value) in overrides {
+        let key = &variable[ENV_PREFIX.len()..];
+        if let Err(error) = set(table, &variable, key, |current| {
+            parse_value(key, current, value)
+        }) {
+            errors.push(error);
+        }
+    }
+    for (variable, path) in secrets {
+        let key = &variable[ENV_PREFIX.len()..variable.len() - SECRET_SUFFIX.len()];
+        let secret = match std::fs::read_to_string(&path) {
+            Ok(secret) => secret.trim_end_matches(['\r', '\n']).to_owned(),
+            Err(error) => {
+                errors.push(ConfigError::Secret {
+                    variable,
+                    path: PathBuf::from(path),
+                    error,
+                });
+                continue;
+            }
+        };
+        if let Err(error) = set(table, &variable, key, |_| Value::String(secret)) {
+            errors.push(error);
+        }
+    }
+    errors
+}
+/// Sets the setting at `key`. Creating the tables on the way
+fn set(
+    table: &mut Table,
+    variable: &str,
+    key: &str,
+    value: impl FnOnce(Option<&Value>) -> Value,
+) -> Result<(), ConfigError> {
+    let invalid = || ConfigError::InvalidEnvironmentVariable(variable.to_owned());
+    let key = key.to_lowercase();
+    let path: Vec<&str> = key.split("__").collect();
+    if path.iter().any(|part| part.is_empty()) {
+        return Err(invalid());
+    }
+    let (last, tables) = path.split_last().ok_or_else(invalid)?;
+    let mut table = table;
+    for part in tables {
+        table = table
+            .entry(part.to_string())
+            .or_insert_with(|| Value::Table(Table::new()))
+            .as_table_mut()
+            .ok_or_else(invalid)?;
+    }
+    let value = value(table.get(*last));
+    table.insert(last.to_string(), value);
+    Ok(())
+}
+/// Parses the value as TOML. Falls back to a string.
+///
+/// Values replacing a string and passwords stay a string. So a password of `1234` is not a number
+/// even when the file does not have the setting
+fn parse_value(key: &str, current: Option<&Value>, value: String) -> Value {
+    if matches!(current, Some(Value::String(_))) || is_password(key) {
+        return Value::String(value);
+    }
+    match toml::from_str::<Table>(&format!("value = {}", value)) {
+        Ok(mut table) => table.remove("value").unwrap_or(Value::String(value)),
+        Err(_) => Value::String(value),
+    }
+}
+/// If the last part of the key is a password. Such as `password` or `smtp_password`
+fn is_password(key: &str) -> bool {
+    key.rsplit("__").next().is_some_and(|name| {
+        let name = name.to_lowercase();
+        name == "password" || name.ends_with("_password")
+    })
+}
+
+/// A setting added by [add_missing_defaults]
+#[derive(Debug, Clone)]
+pub struct AddedSetting {
+    /// Such as `email.queue`
+    pub key: String,
+    pub value: Item,
+}
+impl Display for AddedSetting {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.value {
+            Item::Table(table) => write!(f, "[{}]\n{}", self.key, table),
+            value => write!(f, "{} = {}", self.key, value.to_string().trim()),
+        }
+    }
+}
+/// The settings the `STALWART_PANEL_*` variables set. Such as `database.settings.password`
+pub fn environment_keys(variables: impl IntoIterator<Item = (String, String)>) -> Vec<String> {
+    variables
+        .into_iter()
+        .filter_map(|(variable, _)| {
+            let key = variable.strip_prefix(ENV_PREFIX)?;
+            let key = key.strip_suffix(SECRET_SUFFIX).unwrap_or(key);
+            Some(key.to_lowercase().replace("__", "."))
+        })
+        .collect()
+}
+/// Adds the settings missing from the document with the values in `settings`.
+///
+/// Settings already in the document, comments and ordering are kept.
+/// The keys in `skip` are never written. They came from the environment and may be secrets.
+/// Returns what was added
+pub fn add_missing_defaults(
+    document: &mut Document,
+    settings: &Settings,
+    skip: &[String],
+) -> Result<Vec<AddedSetting>, toml::ser::Error> {
+    let defaults: Document = toml::to_string(settings)?
+        .parse()
+        .expect("Serialized settings are valid TOML");
+    let mut added = Vec::new();
+    merge(
+        document.as_table_mut(),
+        defaults.as_table(),
+        "",
+        skip,
+        &mut added,
+    );
+    Ok(added)
+}
+fn merge(
+    document: &mut dyn TableLike,
+    defaults: &dyn TableLike,
+    prefix: &str,
+    skip: &[String],
+    added: &mut Vec<AddedSetting>,
+) {
+    for (key, default) in defaults.iter() {
+        let path = join_key(prefix, key);
+        if skip.contains(&path) {
+            continue;
+        }
+        match document.get_mut(key) {
+            Some(existing) => {
+                if let (Some(existing), Some(default)) =
+                    (existing.as_table_like_mut(), default.as_table_like())
+                {
+                    merge(existing, default, &path, skip, added);
+                }
+            }
+            None => {
+                if let Some(value) = without_position(default, &path, skip) {
+                    document.insert(key, value.clone());
+                    added.push(AddedSetting { key: path, value });
+                }
+            }
+        }
+    }
+}
+fn join_key(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_owned()
+    } else {
+        format!("{}.{}", prefix, key)
+    }
+}
+/// Tables keep where they were in the document they were parsed from.
+/// Without a position they are written after the table they are added to.
+///
+/// The keys in `skip` are left out. None if that leaves a table empty
+fn without_position(item: &Item, path: &str, skip: &[String]) -> Option<Item> {
+    let without_skipped = |table: &dyn TableLike| {
+        let mut new_table = toml_edit::Table::new();
+        for (key, value) in table.iter() {
+            let path = join_key(path, key);
+            if skip.contains(&path) {
+                continue;
+            }
+            if let Some(value) = without_position(value, &path, skip) {
+                new_table.insert(key, value);
+            }
+        }
+        (table.is_empty() || !new_table.is_empty()).then_some(new_table)
+    };
+    match item {
+        Item::Table(table) => without_skipped(table).map(Item::Table),
+        Item::Value(toml_edit::Value::InlineTable(table)) => without_skipped(table)
+            .map(|table| Item::Value(toml_edit::Value::InlineTable(table.into_inline_table()))),
+        item => Some(item.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use toml::{Table, Value};
+    use toml_edit::Document;
+
+    use super::{add_missing_defaults, apply_environment, environment_keys, ConfigError};
+    use crate::config::Settings;
+
+    fn variables(variables: &[(&str, &str)]) -> Vec<(String, String)> {
+        variables
+            .iter()
+            .map(|(variable, value)| (variable.to_string(), value.to_string()))
+            .collect()
+    }
+    #[test]
+    pub fn test_environment_overrides() {
+        let mut table: Table = toml::from_str(
+            r#"
+            number_of_workers = 2
+            [database]
+            type = "Postgres"
+            [database.settings]
+            password = "from the file"
+            "#,
+        )
+        .unwrap();
+        let errors = apply_environment(
+            &mut table,
+            variables(&[
+                ("STALWART_PANEL_NUMBER_OF_WORKERS", "4"),
+                ("STALWART_PANEL_DATABASE__SETTINGS__PASSWORD", "1234"),
+                ("STALWART_PANEL_EMAIL__HOST", "smtp.example.com"),
+                ("STALWART_PANEL_DATABASE__TYPE__NAME", "Mysql"),
+                ("OTHER_PROGRAM_SETTING", "ignored"),
+            ]),
+        );
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert_eq!(table["number_of_workers"], Value::Integer(4));
+        assert_eq!(
+            table["database"]["settings"]["password"],
+            Value::String("1234".to_owned())
+        );
+        assert_eq!(
+            table["email"]["host"],
+            Value::String("smtp.example.com".to_owned())
+        );
+        assert_eq!(
+            table["database"]["type"],
+            Value::String("Postgres".to_owned())
+        );
+    }
+    #[test]
+    pub fn test_overrides_of_missing_settings() {
+        let mut table = Table::new();
+        let errors = apply_environment(
+            &mut table,
+            variables(&[
+                ("STALWART_PANEL_DATABASE__SETTINGS__PASSWORD", "1234"),
+                ("STALWART_PANEL_EMAIL__PASSWORD", "true"),
+                ("STALWART_PANEL_NUMBER_OF_WORKERS", "4"),
+                ("STALWART_PANEL_IS_HTTPS", "true"),
+                (
+                    "STALWART_PANEL_EMAIL__NOTIFICATIONS__PASSWORD_CHANGED",
+                    "false",
+                ),
+            ]),
+        );
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(
+            table["database"]["settings"]["password"],
+            Value::String("1234".to_owned())
+        );
+        assert_eq!(table["email"]["password"], Value::String("true".to_owned()));
+        assert_eq!(table["number_of_workers"], Value::Integer(4));
+        assert_eq!(table["is_https"], Value::Boolean(true));
+        assert_eq!(
+            table["email"]["notifications"]["password_changed"],
+            Value::Boolean(false)
+        );
+    }
+    #[test]
+    pub fn test_secret_files() {
+        let secret =
+            std::env::temp_dir().join(format!("stalwart-panel-secret-{}", std::process::id()));
+        std::fs::write(&secret, "from the secret\n").unwrap();
+        let mut table = Table::new();
+        let errors = apply_environment(
+            &mut table,
+            variables(&[
+                (
+                    "STALWART_PANEL_DATABASE__SETTINGS__PASSWORD_FILE",
+                    secret.to_str().unwrap(),
+                ),
+                (
+                    "STALWART_PANEL_DATABASE__SETTINGS__PASSWORD",
+                    "from the environment",
+                ),
+                ("STALWART_PANEL_EMAIL__PASSWORD_FILE", "/does/not/exist"),
+            ]),
+        );
+        std::fs::remove_file(&secret).unwrap();
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert_eq!(
+            table["database"]["settings"]["password"],
+            Value::String("from the secret".to_owned())
+        );
+    }
+    #[test]
+    pub fn test_capture_transport_is_rejected() {
+        let settings: Settings = toml::from_str(
+            r#"bind_address = "0.0.0.0:5312"
+postmaster_address = "postmaster@example.com"
+default_group = 1
+root_group = 2
+
+[database]
+type = "Postgres"
+settings = { user = "postgres", password = "postgres", host = "localhost:5432", database = "panel" }
+
+[email]
+host = "localhost"
+encryption = "NONE"
+from = "Stalwart Panel<panel@example.com>"
+transport = { type = "Capture" }
+"#,
+        )
+        .unwrap();
+        let errors = settings.validate();
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert!(matches!(
+            errors[0],
+            ConfigError::Invalid {
+                key: "email.transport",
+                ..
+            }
+        ));
+    }
+    #[test]
+    pub fn test_relative_sqlite_path_is_rejected() {
+        let config = |path: &str| {
+            format!(
+                r#"bind_address = "0.0.0.0:5312"
+postmaster_address = "postmaster@example.com"
+default_group = 1
+root_group = 2
+
+[database]
+type = "Sqlite"
+settings = {{ path = "{path}" }}
+
+[email]
+host = "localhost"
+encryption = "NONE"
+from = "Stalwart Panel<panel@example.com>"
+"#
+            )
+        };
+        let absolute = std::env::temp_dir().join("stalwart-panel.db");
+        let settings: Settings =
+            toml::from_str(&config(&absolute.display().to_string().replace('\\', "/"))).unwrap();
+        assert!(settings.validate().is_empty(), "{:?}", settings.validate());
+
+        let settings: Settings = toml::from_str(&config("stalwart-panel.db")).unwrap();
+        let errors = settings.validate();
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert!(matches!(
+            errors[0],
+            ConfigError::Invalid {
+                key: "database.settings.path",
+                ..
+            }
+        ));
+    }
+    #[test]
+    pub fn test_add_missing_defaults() {
+        let content = r#"# The address the panel listens on
+bind_address = "0.0.0.0:5312"
+postmaster_address = "postmaster@example.com"
+default_group = 1
+root_group = 2
+
+[database]
+type = "Postgres"
+# Not a real password
+settings = { user = "postgres", password = "postgres", host = "localhost:5432", database = "panel" }
+
+[email]
+host = "localhost"
+encryption = "NONE"
+from = "Stalwart Panel<panel@example.com>"
+"#;
+        let settings: Settings = toml::from_str(content).unwrap();
+        let mut document: Document = content.parse().unwrap();
+        let added = add_missing_defaults(&mut document, &settings, &[]).unwrap();
+        let keys: Vec<_> = added.iter().map(|added| added.key.as_str()).collect();
+        assert!(keys.contains(&"number_of_workers"), "{:?}", keys);
+        assert!(keys.contains(&"email.queue"), "{:?}", keys);
+        assert!(!keys.contains(&"bind_address"), "{:?}", keys);
+
+        let written = document.to_string();
+        assert!(written.starts_with("# The address the panel listens on\nbind_address"));
+        assert!(written.contains("# Not a real password"));
+        let written: Settings = toml::from_str(&written).unwrap();
+        assert_eq!(written.email.queue.max_attempts, 5);
+        assert!(add_missing_defaults(&mut document, &settings, &[])
+            .unwrap()
+            .is_empty());
+    }
+    #[test]
+    pub fn test_add_missing_defaults_skips_environment() {
+        let file = r#"bind_address = "0.0.0.0:5312"
+postmaster_address = "postmaster@example.com"
+default_group = 1
+root_group = 2
+"#;
+        let mut table: Table = toml::from_str(file).unwrap();
+        let environment = variables(&[
+            ("STALWART_PANEL_DATABASE__TYPE", "Postgres"),
+            ("STALWART_PANEL_DATABASE__SETTINGS__USER", "postgres"),
+            ("STALWART_PANEL_DATABASE__SETTINGS__PASSWORD", "secret"),
+            ("STALWART_PANEL_DATABASE__SETTINGS__HOST", "localhost:5432"),
+            ("STALWART_PANEL_DATABASE__SETTINGS__DATABASE", "panel"),
+            ("STALWART_PANEL_EMAIL__HOST", "localhost"),
+            ("STALWART_PANEL_EMAIL__ENCRYPTION", "NONE"),
+            (
+                "STALWART_PANEL_EMAIL__FROM",
+                "Stalwart Panel<panel@example.com>",
+            ),
+            ("STALWART_PANEL_EMAIL__PASSWORD_FILE", "/run/secrets/email"),
+        ]);
+        assert!(apply_environment(&mut table, environment[..8].to_vec()).is_empty());
+        let settings: Settings = toml::Value::Table(table).try_into().unwrap();
+        let skip = environment_keys(environment);
+        assert!(skip.contains(&"email.password".to_owned()), "{:?}", skip);
+
+        let mut document: Document = file.parse().unwrap();
+        let added = add_missing_defaults(&mut document, &settings, &skip).unwrap();
+        let keys: Vec<_> = added.iter().map(|added| added.key.as_str()).collect();
+        assert!(keys.contains(&"email.queue"), "{:?}", keys);
+        let written = document.to_string();
+        assert!(!written.contains("secret"), "{}", written);
+        assert!(!written.contains("localhost:5432"), "{}", written);
+        let written: Table = toml::from_str(&written).unwrap();
+        assert!(written["email"].get("password").is_none());
+        assert!(written["email"].get("host").is_none());
+        assert!(written["email"].get("notifications").is_some());
+        assert!(written.get("database").is_none());
+    }
+}
@@ -42,13 +42,34 @@ impl EmailAddress {
             true
         }
     }
+    /// `*` is only allowed as the entire local part. See [EmailAddress::is_catch_all]
     fn validate_user(user: &str) -> bool {
-        if user.is_empty() || user.len() > 64 {
+        if user.is_empty() || user.len() > 64 || (user != "*" && user.contains('*')) {
             false
         } else {
             true
         }
     }
+    /// Creates `*@{domain}`
+    pub fn catch_all(domain: &str) -> Result<Self, InvalidEmailAddress> {
+        Self::new(format!("*@{domain}"))
+    }
+    /// The address is `*@{domain}` and will receive mail for any address on the domain that does not exist
+    pub fn is_catch_all(&self) -> bool {
+        self.local_part() == "*"
+    }
+    pub fn local_part(&self) -> &str {
+        self.0
+            .split_once('@')
+            .map(|(user, _)| user)
+            .unwrap_or_default()
+    }
+    pub fn domain(&self) -> &str {
+        self.0
+            .split_once('@')
+            .map(|(_, domain)| domain)
+            .unwrap_or_default()
+    }
 }
 #[cfg(test)]
 mod tests {
@@ -59,6 +80,14 @@ mod tests {
         assert!(EmailAddress::new("test@gmail.com").is_ok());
         assert!(EmailAddress::new("fail.com").is_err());
     }
+    #[test]
+    pub fn test_catch_all() {
+        let catch_all = EmailAddress::new("*@customer.example").unwrap();
+        assert!(catch_all.is_catch_all());
+        assert_eq!(catch_all.domain(), "customer.example");
+        assert!(!EmailAddress::new("test@gmail.com").unwrap().is_catch_all());
+        assert!(EmailAddress::new("te*st@gmail.com").is_err());
+    }
 }
 mod _serde {
     use crate::database::EmailAddress;
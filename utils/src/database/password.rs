@@ -206,7 +206,7 @@ impl Password {
             }
             Ok(false)
         } else {
-            Ok(false)
+            Ok(true)
         }
     }
     pub fn hash_type(&self) -> PasswordType {
@@ -335,3 +335,38 @@ mod _serde {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use sha_crypt::{sha256_simple, sha512_simple, Sha256Params, Sha512Params};
+
+    use super::{Password, PasswordType};
+
+    fn check(password: &Password, hash_type: PasswordType) {
+        assert_eq!(password.hash_type(), hash_type);
+        assert!(password.check_password("correct horse").unwrap());
+        assert!(!password.check_password("wrong horse").unwrap());
+    }
+    #[test]
+    pub fn test_argon2_round_trip() {
+        let password = Password::new_hash("correct horse", PasswordType::Argon2).unwrap();
+        check(&password, PasswordType::Argon2);
+        let stored = Password::new_hashed(password.password.clone());
+        check(&stored, PasswordType::Argon2);
+    }
+    #[test]
+    pub fn test_sha256_round_trip() {
+        let hash = sha256_simple("correct horse", &Sha256Params::default()).unwrap();
+        check(&Password::new_hashed(hash), PasswordType::SHA256);
+    }
+    #[test]
+    pub fn test_sha512_round_trip() {
+        let hash = sha512_simple("correct horse", &Sha512Params::default()).unwrap();
+        check(&Password::new_hashed(hash), PasswordType::SHA512);
+    }
+    #[test]
+    pub fn test_unsupported_hashes_are_refused() {
+        let plain = Password::new_hashed("{PLAIN}correct horse");
+        assert!(plain.check_password("correct horse").is_err());
+    }
+}
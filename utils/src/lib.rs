@@ -1,6 +1,8 @@
 pub mod config;
+pub mod config_loader;
 pub mod database;
 pub mod duration_serde;
+pub mod quota_size;
 pub mod stalwart_config;
 pub mod stalwart_manager;
 
@@ -0,0 +1,184 @@
+use std::{
+    fmt::{Display, Formatter},
+    str::FromStr,
+};
+
+use serde::{de::Visitor, Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("Invalid quota `{0}`. Expected a number of bytes or a size such as `5GiB` or `500MB`")]
+pub struct InvalidQuotaSize(pub String);
+
+/// A number of bytes.
+///
+/// Deserializes from a number of bytes or a string with a unit such as `5GiB` or `500MB`.
+/// Always serializes as a number of bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct QuotaSize(pub i64);
+
+const UNITS: [(&str, i64); 9] = [
+    ("b", 1),
+    ("kb", 1000),
+    ("kib", 1 << 10),
+    ("mb", 1000 * 1000),
+    ("mib", 1 << 20),
+    ("gb", 1000 * 1000 * 1000),
+    ("gib", 1 << 30),
+    ("tb", 1000 * 1000 * 1000 * 1000),
+    ("tib", 1 << 40),
+];
+
+impl QuotaSize {
+    pub fn bytes(&self) -> i64 {
+        self.0
+    }
+    /// Rounds to one decimal in the largest binary unit the size fills. Such as `1.5 KiB` or `4.2 GiB`
+    ///
+    /// [Display] keeps every byte so it can be parsed again
+    pub fn to_human(&self) -> String {
+        let (name, multiplier) = [
+            ("TiB", 1 << 40),
+            ("GiB", 1 << 30),
+            ("MiB", 1 << 20),
+            ("KiB", 1 << 10),
+        ]
+        .into_iter()
+        .find(|(_, multiplier)| self.0.abs() >= *multiplier)
+        .unwrap_or(("B", 1));
+        let value = format!("{:.1}", self.0 as f64 / multiplier as f64);
+        format!("{} {}", value.strip_suffix(".0").unwrap_or(&value), name)
+    }
+}
+impl From<QuotaSize> for i64 {
+    fn from(value: QuotaSize) -> Self {
+        value.0
+    }
+}
+impl From<i64> for QuotaSize {
+    fn from(value: i64) -> Self {
+        Self(value)
+    }
+}
+impl FromStr for QuotaSize {
+    type Err = InvalidQuotaSize;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidQuotaSize(s.to_owned());
+        let value = s.trim();
+        let split = value
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(value.len());
+        let (number, unit) = value.split_at(split);
+        let unit = unit.trim().to_lowercase();
+        let multiplier = if unit.is_empty() {
+            1
+        } else {
+            UNITS
+                .iter()
+                .find(|(name, _)| *name == unit)
+                .map(|(_, multiplier)| *multiplier)
+                .ok_or_else(invalid)?
+        };
+        if let Ok(whole) = number.parse::<i64>() {
+            return whole
+                .checked_mul(multiplier)
+                .map(QuotaSize)
+                .ok_or_else(invalid);
+        }
+        let fraction: f64 = number.parse().map_err(|_| invalid())?;
+        let bytes = (fraction * multiplier as f64).round();
+        if !bytes.is_finite() || bytes < 0.0 || bytes > i64::MAX as f64 {
+            return Err(invalid());
+        }
+        Ok(QuotaSize(bytes as i64))
+    }
+}
+impl Display for QuotaSize {
+    /// Uses the largest binary unit that divides the size evenly
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let (name, multiplier) = UNITS
+            .iter()
+            .rev()
+            .filter(|(name, _)| *name == "b" || name.ends_with("ib"))
+            .find(|(_, multiplier)| self.0 != 0 && self.0 % multiplier == 0)
+            .copied()
+            .unwrap_or(("b", 1));
+        let name = match name {
+            "b" => "B",
+            "kib" => "KiB",
+            "mib" => "MiB",
+            "gib" => "GiB",
+            _ => "TiB",
+        };
+        write!(f, "{}{}", self.0 / multiplier, name)
+    }
+}
+impl Serialize for QuotaSize {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i64(self.0)
+    }
+}
+impl<'de> Deserialize<'de> for QuotaSize {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct QuotaSizeVisitor;
+        impl<'de> Visitor<'de> for QuotaSizeVisitor {
+            type Value = QuotaSize;
+
+            fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
+                formatter.write_str("a number of bytes or a size such as `5GiB`")
+            }
+            fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<Self::Value, E> {
+                if v < 0 {
+                    return Err(E::custom("quota can not be negative"));
+                }
+                Ok(QuotaSize(v))
+            }
+            fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<Self::Value, E> {
+                i64::try_from(v)
+                    .map(QuotaSize)
+                    .map_err(|_| E::custom("quota is too large"))
+            }
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                v.parse().map_err(E::custom)
+            }
+        }
+        deserializer.deserialize_any(QuotaSizeVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::QuotaSize;
+
+    #[test]
+    pub fn test_parse() {
+        assert_eq!("1024".parse(), Ok(QuotaSize(1024)));
+        assert_eq!("5GiB".parse(), Ok(QuotaSize(5 * 1024 * 1024 * 1024)));
+        assert_eq!("500MB".parse(), Ok(QuotaSize(500_000_000)));
+        assert_eq!("1.5 kib".parse(), Ok(QuotaSize(1536)));
+        assert!("5 apples".parse::<QuotaSize>().is_err());
+        assert!("-5GiB".parse::<QuotaSize>().is_err());
+    }
+    #[test]
+    pub fn test_display() {
+        assert_eq!(QuotaSize(5 * 1024 * 1024 * 1024).to_string(), "5GiB");
+        assert_eq!(QuotaSize(1000).to_string(), "1000B");
+        assert_eq!(QuotaSize(0).to_string(), "0B");
+    }
+    #[test]
+    pub fn test_to_human() {
+        let cases = [
+            (0, "0 B"),
+            (1000, "1000 B"),
+            (1536, "1.5 KiB"),
+            (2048, "2 KiB"),
+            (10_485_760, "10 MiB"),
+            (4_500_000_000, "4.2 GiB"),
+            (5 << 40, "5 TiB"),
+        ];
+        for (bytes, expected) in cases {
+            assert_eq!(QuotaSize(bytes).to_human(), expected);
+        }
+    }
+}
@@ -13,9 +13,13 @@ pub struct SQLSettings {
 }
 
 impl SQLSettings {
-    pub fn new(database: &Database) -> Self {
+    /// `password` is written in place of the database password. Otherwise the password is in plain text
+    pub fn new(database: &Database, password: Option<&str>) -> Self {
         Self {
-            address: database.to_string(),
+            address: match password {
+                Some(password) => database.address_with_password(password),
+                None => database.to_string(),
+            },
             directory_type: "sql".into(),
         }
     }
@@ -53,6 +57,19 @@ impl Default for TTL {
 
 /// The lookup queries that Stalwart uses to retrieve the data it needs
 ///
+/// `recipients` falls back to the catch-all address of the domain (`*@domain`) when no address matches.
+/// On domains with sub-addressing enabled in `domain_settings` it removes the `+tag` first,
+/// unless the address exists with the tag. Stalwart's own sub-addressing would remove it on every domain,
+/// so the setup tool turns it off (`directory.sql.options.subaddressing`).
+/// Stalwart binds the parameter once so the MySQL queries can only use `?` once.
+/// `||` is a logical OR in MySQL so its queries use `CONCAT` instead.
+///
+/// Members of a shared mailbox are in its group when they can read it
+/// and have its addresses when they can send as it. See `shared_mailbox_members`
+///
+/// `members` only returns shared mailboxes. Panel groups decide what an account can do in the panel.
+/// They are not principals in Stalwart and a group named like an account would give access to its mailbox
+///
 /// You can read about these queries in the [Stalwart Documentation](https://stalw.art/docs/directory/types/sql#lookup-queries)
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct SQLQuery {
@@ -70,24 +87,36 @@ impl SQLQuery {
     pub fn new_postgres() -> Self {
         Self{
             name: "SELECT username, account_type, password, description, quota FROM accounts WHERE username = $1 AND active = true".into(),
-            members: "SELECT g.group_name FROM groups AS g INNER JOIN accounts as a ON g.id = a.group_id AND a.username = $1".into(),
-            recipients: "SELECT a.username FROM accounts as a INNER JOIN emails as e ON a.id = e.account AND e.email_address = $1".into(),
-            emails: "SELECT e.email_address FROM emails as e INNER JOIN accounts as a ON e.account = a.id AND a.username = $1".into(),
+            members: "SELECT s.username FROM shared_mailbox_members AS m INNER JOIN accounts AS a ON a.id = m.member INNER JOIN accounts AS s ON s.id = m.mailbox AND s.active = true WHERE a.username = $1 AND m.access IN ('read', 'full')".into(),
+            recipients: "SELECT a.username FROM (SELECT CASE WHEN POSITION('+' IN $1) > 0 AND NOT EXISTS (SELECT 1 FROM emails WHERE email_address = $1) AND EXISTS (SELECT 1 FROM domain_settings WHERE domain = LOWER(SPLIT_PART($1, '@', 2)) AND sub_addressing = true) THEN SPLIT_PART($1, '+', 1) || '@' || SPLIT_PART($1, '@', 2) ELSE $1 END AS address) AS r INNER JOIN emails AS e ON e.email_address = r.address OR (e.email_type = 'catch_all' AND e.email_address = '*@' || SPLIT_PART(r.address, '@', 2) AND NOT EXISTS (SELECT 1 FROM emails AS x WHERE x.email_address = r.address)) INNER JOIN accounts AS a ON a.id = e.account".into(),
+            emails: "SELECT x.email_address FROM accounts AS a INNER JOIN (SELECT e.account, e.email_address, e.email_type, 0 AS shared FROM emails AS e UNION ALL SELECT m.member AS account, e.email_address, e.email_type, 1 AS shared FROM shared_mailbox_members AS m INNER JOIN emails AS e ON e.account = m.mailbox WHERE m.access IN ('send_as', 'full')) AS x ON x.account = a.id WHERE a.username = $1 AND x.email_type NOT IN ('list', 'catch_all') ORDER BY x.shared, x.email_type DESC, x.email_address".into(),
             verify: "SELECT email_address FROM emails WHERE email_address LIKE '%' || $1 || '%' AND email_type = 'primary' ORDER BY email_address LIMIT 5".into(),
-            expand: "SELECT p.email_address FROM emails AS p JOIN emails AS l ON p.username = l.username WHERE p.email_type = 'primary' AND l.email_address = $1 AND l.type = 'list' ORDER BY p.email_address LIMIT 50".into(),
+            expand: "SELECT COALESCE(p.email_address, m.external_address) AS address FROM list_members AS m INNER JOIN emails AS l ON l.id = m.list AND l.email_type = 'list' LEFT JOIN accounts AS a ON a.id = m.account LEFT JOIN emails AS p ON p.account = a.id AND a.active = true AND p.email_type = 'primary' WHERE l.email_address = $1 AND COALESCE(p.email_address, m.external_address) IS NOT NULL ORDER BY address".into(),
             domains: "SELECT 1 FROM emails WHERE email_address LIKE '%@' || $1 LIMIT 1".into(),
         }
     }
+    /// This is the queries that are used in Stalwart to use this panels database for SQLite.
+    pub fn new_sqlite() -> Self {
+        Self{
+            name: "SELECT username, account_type, password, description, quota FROM accounts WHERE username = ?1 AND active = true".into(),
+            members: "SELECT s.username FROM shared_mailbox_members AS m INNER JOIN accounts AS a ON a.id = m.member INNER JOIN accounts AS s ON s.id = m.mailbox AND s.active = true WHERE a.username = ?1 AND m.access IN ('read', 'full')".into(),
+            recipients: "SELECT a.username FROM (SELECT CASE WHEN instr(?1, '+') > 0 AND NOT EXISTS (SELECT 1 FROM emails WHERE email_address = ?1) AND EXISTS (SELECT 1 FROM domain_settings WHERE domain = lower(substr(?1, instr(?1, '@') + 1)) AND sub_addressing = true) THEN substr(?1, 1, instr(?1, '+') - 1) || substr(?1, instr(?1, '@')) ELSE ?1 END AS address) AS r INNER JOIN emails AS e ON e.email_address = r.address OR (e.email_type = 'catch_all' AND e.email_address = '*@' || substr(r.address, instr(r.address, '@') + 1) AND NOT EXISTS (SELECT 1 FROM emails AS x WHERE x.email_address = r.address)) INNER JOIN accounts AS a ON a.id = e.account".into(),
+            emails: "SELECT x.email_address FROM accounts AS a INNER JOIN (SELECT e.account, e.email_address, e.email_type, 0 AS shared FROM emails AS e UNION ALL SELECT m.member AS account, e.email_address, e.email_type, 1 AS shared FROM shared_mailbox_members AS m INNER JOIN emails AS e ON e.account = m.mailbox WHERE m.access IN ('send_as', 'full')) AS x ON x.account = a.id WHERE a.username = ?1 AND x.email_type NOT IN ('list', 'catch_all') ORDER BY x.shared, x.email_type DESC, x.email_address".into(),
+            verify: "SELECT email_address FROM emails WHERE email_address LIKE '%' || ?1 || '%' AND email_type = 'primary' ORDER BY email_address LIMIT 5".into(),
+            expand: "SELECT COALESCE(p.email_address, m.external_address) AS address FROM list_members AS m INNER JOIN emails AS l ON l.id = m.list AND l.email_type = 'list' LEFT JOIN accounts AS a ON a.id = m.account LEFT JOIN emails AS p ON p.account = a.id AND a.active = true AND p.email_type = 'primary' WHERE l.email_address = ?1 AND COALESCE(p.email_address, m.external_address) IS NOT NULL ORDER BY address".into(),
+            domains: "SELECT 1 FROM emails WHERE email_address LIKE '%@' || ?1 LIMIT 1".into(),
+        }
+    }
     /// This is the queries that are used in Stalwart to use this panels database for MySQL.
     pub fn new_mysql() -> Self {
         Self{
-            name: "SELECT username, type, password, description, quota FROM accounts WHERE name = ? AND active = true".into(),
-            members: "SELECT g.group_name FROM groups AS g INNER JOIN accounts as a ON g.id = a.group_id AND a.username = ?".into(),
-            recipients: "SELECT a.username FROM accounts as a INNER JOIN emails as e ON a.id = e.account AND e.email = ?".into(),
-            emails: "SELECT e.email FROM emails as e INNER JOIN accounts as a ON e.account = a.id AND a.username = ?".into(),
-            verify: "SELECT email FROM emails WHERE email LIKE '%' || ? || '%' AND type = 'primary' ORDER BY email LIMIT 5".into(),
-            expand: "SELECT p.email FROM emails AS p JOIN emails AS l ON p.name = l.name WHERE p.type = 'primary' AND l.email = ? AND l.type = 'list' ORDER BY p.email LIMIT 50".into(),
-            domains: "SELECT 1 FROM emails WHERE email LIKE '%@' || ? LIMIT 1".into(),
+            name: "SELECT username, account_type, password, description, quota FROM accounts WHERE username = ? AND active = true".into(),
+            members: "SELECT s.username FROM shared_mailbox_members AS m INNER JOIN accounts AS a ON a.id = m.member INNER JOIN accounts AS s ON s.id = m.mailbox AND s.active = true WHERE a.username = ? AND m.access IN ('read', 'full')".into(),
+            recipients: "SELECT a.username FROM (SELECT CASE WHEN LOCATE('+', o.address) > 0 AND NOT EXISTS (SELECT 1 FROM emails WHERE email_address = o.address) AND EXISTS (SELECT 1 FROM domain_settings WHERE domain = LOWER(SUBSTRING_INDEX(o.address, '@', -1)) AND sub_addressing = true) THEN CONCAT(SUBSTRING_INDEX(o.address, '+', 1), '@', SUBSTRING_INDEX(o.address, '@', -1)) ELSE o.address END AS address FROM (SELECT ? AS address) AS o) AS r INNER JOIN emails AS e ON e.email_address = r.address OR (e.email_type = 'catch_all' AND e.email_address = CONCAT('*@', SUBSTRING_INDEX(r.address, '@', -1)) AND NOT EXISTS (SELECT 1 FROM emails AS x WHERE x.email_address = r.address)) INNER JOIN accounts AS a ON a.id = e.account".into(),
+            emails: "SELECT x.email_address FROM accounts AS a INNER JOIN (SELECT e.account, e.email_address, e.email_type, 0 AS shared FROM emails AS e UNION ALL SELECT m.member AS account, e.email_address, e.email_type, 1 AS shared FROM shared_mailbox_members AS m INNER JOIN emails AS e ON e.account = m.mailbox WHERE m.access IN ('send_as', 'full')) AS x ON x.account = a.id WHERE a.username = ? AND x.email_type NOT IN ('list', 'catch_all') ORDER BY x.shared, x.email_type DESC, x.email_address".into(),
+            verify: "SELECT email_address FROM emails WHERE email_address LIKE CONCAT('%', ?, '%') AND email_type = 'primary' ORDER BY email_address LIMIT 5".into(),
+            expand: "SELECT COALESCE(p.email_address, m.external_address) AS address FROM list_members AS m INNER JOIN emails AS l ON l.id = m.list AND l.email_type = 'list' LEFT JOIN accounts AS a ON a.id = m.account LEFT JOIN emails AS p ON p.account = a.id AND a.active = true AND p.email_type = 'primary' WHERE l.email_address = ? AND COALESCE(p.email_address, m.external_address) IS NOT NULL ORDER BY address".into(),
+            domains: "SELECT 1 FROM emails WHERE email_address LIKE CONCAT('%@', ?) LIMIT 1".into(),
         }
     }
 }